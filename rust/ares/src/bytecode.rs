@@ -0,0 +1,2434 @@
+/** Bytecode for Nock
+ *
+ * [compile] lowers a formula to the register bytecode of `docs/codegen-bootstrap.md`, and [run]
+ * evaluates it in place of the tree-walking loop of [interpret]. Programs are cached by formula
+ * in [Context::programs] across events, until the nouns are relocated by `%pack` or `%meld` or
+ * [Context::programs_max] formulas have been compiled, so each arm is compiled once however often
+ * it is called. Hot arms the code generator has linearized are lowered from its IR by [lower], and
+ * run in place of the formula's own program on cores with the battery they were linearized for.
+ *
+ * Instructions are byte-aligned. The first byte holds the opcode in its low 5 bits and the first
+ * register operand in its high 3 bits; the other register operands are packed 3 bits apiece into
+ * the next byte. Constants are given as 32-bit little-endian indices into the program's
+ * constants, spill slots as 16-bit indices, and labels as forward offsets from the end of the
+ * instruction.
+ *
+ * Calls push a frame with the mean stack and trace stack locals of [interpret]'s frames, so that
+ * crashes unwind, and traces are written, exactly as they are for the tree walker. The caller's
+ * registers are saved on the callee's lightweight stack, as the tree walker leaves its `Ret` there.
+ */
+use crate::codegen::{self, Arm, Block, Bran, Germ, Ssa};
+use crate::interpreter::{
+    append_trace, debug_assertions, hint, inc, mean_frame_push, scry, write_trace, Context, Error,
+    Result, BAIL_EXIT, BAIL_INTR,
+};
+use crate::jets::JetErr;
+use crate::mem::{word_size_of, NockStack, Preserve};
+use crate::noun::{Atom, Cell, Noun, Slots, D};
+use crate::profile;
+use crate::serf::{TERMINATOR, TIMEOUT};
+use crate::unifying_equality::unifying_equality;
+use ares_macros::tas;
+use assert_no_alloc::permit_alloc;
+use either::Either::{self, Left, Right};
+use std::collections::{HashMap, HashSet};
+use std::ptr::copy_nonoverlapping;
+use std::result;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+crate::gdb!();
+
+const IMM: u8 = 1;
+const MOV: u8 = 2;
+const INC: u8 = 3;
+const UNC: u8 = 4;
+const CON: u8 = 5;
+const HED: u8 = 6;
+const TAL: u8 = 7;
+const HUD: u8 = 8;
+const TUL: u8 = 9;
+const CLQ: u8 = 10;
+const EQQ: u8 = 11;
+const BRN: u8 = 12;
+const HOP: u8 = 13;
+const CAL: u8 = 14;
+const LNK: u8 = 15;
+const JMP: u8 = 16;
+const LNT: u8 = 17;
+const SPY: u8 = 18;
+const HNT: u8 = 19;
+const BOM: u8 = 20;
+const DON: u8 = 21;
+const LOD: u8 = 22;
+const STO: u8 = 23;
+
+/// `hnt` before a dynamic hint's formula is evaluated
+const PRE: u8 = 0;
+/// `hnt` before the hinted formula is evaluated
+const NOCK: u8 = 1;
+/// `hnt` after the hinted formula is evaluated
+const POST: u8 = 2;
+
+const REGS: usize = 8;
+
+/// Formulas nested deeper than this are compiled as programs of their own, and evaluated with
+/// `lnk`, so compiling a formula can't overflow the Rust stack
+const MAX_DEPTH: usize = 1000;
+
+/** A compiled formula: a [ProgramMem] header, followed by its constants and then its code, in
+ * one allocation */
+#[derive(Copy, Clone)]
+pub struct Program(*mut ProgramMem);
+
+#[repr(C)]
+struct ProgramMem {
+    /// Spill slots a frame running the program needs
+    slots: usize,
+    /// Number of constants
+    consts: usize,
+    /// Length of the code in bytes
+    len: usize,
+    /// Calls of the program since it was compiled, in any event
+    runs: usize,
+    /// The formula's arm lowered from the code generator's IR, or null if it has not been
+    line: *mut ProgramMem,
+    /// The `sock` of what the lowered arm knows of its subject
+    sock: Noun,
+}
+
+impl Program {
+    /// Size of the allocation in words
+    unsafe fn words(&self) -> usize {
+        word_size_of::<ProgramMem>() + (*self.0).consts + (*self.0).len.div_ceil(8)
+    }
+
+    unsafe fn slots(&self) -> usize {
+        (*self.0).slots
+    }
+
+    unsafe fn consts(&self) -> *mut Noun {
+        self.0.add(1) as *mut Noun
+    }
+
+    unsafe fn code(&self) -> *const u8 {
+        self.consts().add((*self.0).consts) as *const u8
+    }
+
+    /** The program to run on a subject: the lowered arm, where there is one and the subject is
+     * what it knows, or else this one */
+    unsafe fn select(self, stack: &mut NockStack, subject: Noun) -> Program {
+        let line = (*self.0).line;
+        if !line.is_null() && fits(stack, (*self.0).sock, subject) {
+            Program(line)
+        } else {
+            self
+        }
+    }
+}
+
+/** Whether a noun is what a `sock` knows of it */
+unsafe fn fits(stack: &mut NockStack, sock: Noun, mut noun: Noun) -> bool {
+    let Ok(sock) = sock.as_cell() else {
+        return false;
+    };
+    match sock.head().direct().map(|tag| tag.data()) {
+        Some(tas!(b"know")) => {
+            let mut know = sock.tail();
+            unifying_equality(stack, &mut know, &mut noun)
+        }
+        Some(tas!(b"bets")) => {
+            let (Ok(bets), Ok(cell)) = (sock.tail().as_cell(), noun.as_cell()) else {
+                return false;
+            };
+            fits(stack, bets.head(), cell.head()) && fits(stack, bets.tail(), cell.tail())
+        }
+        Some(tas!(b"dice")) => noun.is_atom(),
+        Some(tas!(b"flip")) => noun.raw_equals(D(0)) || noun.raw_equals(D(1)),
+        Some(tas!(b"toss")) => true,
+        _ => false,
+    }
+}
+
+impl Preserve for Program {
+    unsafe fn assert_in_stack(&self, stack: &NockStack) {
+        stack.assert_struct_is_in(self.0 as *const u64, self.words());
+        for i in 0..(*self.0).consts {
+            (*self.consts().add(i)).assert_in_stack(stack);
+        }
+        (*self.0).sock.assert_in_stack(stack);
+        if !(*self.0).line.is_null() {
+            Program((*self.0).line).assert_in_stack(stack);
+        }
+    }
+
+    unsafe fn preserve(&mut self, stack: &mut NockStack) {
+        if stack.is_in_frame(self.0) {
+            for i in 0..(*self.0).consts {
+                (*self.consts().add(i)).preserve(stack);
+            }
+            (*self.0).sock.preserve(stack);
+            if !(*self.0).line.is_null() {
+                let mut line = Program((*self.0).line);
+                line.preserve(stack);
+                (*self.0).line = line.0;
+            }
+            let words = self.words();
+            let dest: *mut u64 = stack.struct_alloc_in_previous_frame(words);
+            copy_nonoverlapping(self.0 as *const u64, dest, words);
+            self.0 = dest as *mut ProgramMem;
+        }
+    }
+}
+
+/** The program for a formula, compiled unless it is cached. The formula is unified with the
+ * cached one, so that looking it up again is cheap. */
+fn program(context: &mut Context, formula: &mut Noun) -> Program {
+    if let Some(program) = context.programs.lookup(&mut context.stack, formula) {
+        return program;
+    }
+    //  kept across events, so dropped once full rather than grown forever; hot arms are compiled
+    //  again the next time they are called
+    if context.programs_len >= context.programs_max {
+        context.programs_reset();
+    }
+    let program = compile(&mut context.stack, *formula);
+    context.programs = context
+        .programs
+        .insert(&mut context.stack, formula, program);
+    context.programs_len += 1;
+    program
+}
+
+/** Count a call of a program, having the code generator linearize its arm once it is hot, and
+ * give the program to enter for the call */
+unsafe fn heat(
+    context: &mut Context,
+    mut program: Program,
+    subject: Noun,
+    mut formula: Noun,
+) -> result::Result<Program, Error> {
+    if context.codegen.hot != 0 {
+        (*program.0).runs += 1;
+        if (*program.0).runs == context.codegen.hot {
+            codegen::hot(context, subject, formula)?;
+            program = self::program(context, &mut formula);
+        }
+    }
+    Ok(program.select(&mut context.stack, subject))
+}
+
+/** Compile a formula, allocating the program on the NockStack. Where the formula is invalid,
+ * the program crashes when it gets there, as the tree walker does. */
+pub fn compile(stack: &mut NockStack, formula: Noun) -> Program {
+    permit_alloc(|| {
+        let mut compiler = Compiler::new();
+        compiler.formula(formula, SUBJECT, true);
+        compiler.finish(stack)
+    })
+}
+
+/** Have the VM run a lowered arm in place of a formula's program, on subjects the `sock` of what
+ * the arm knows of its subject fits. The formula's program is copied rather than changed, since
+ * it may be older than the lowered arm it would point to. */
+pub(crate) unsafe fn install(context: &mut Context, mut formula: Noun, sock: Noun, line: Program) {
+    let program = program(context, &mut formula);
+    let words = program.words();
+    let copy = Program(context.stack.struct_alloc::<u64>(words) as *mut ProgramMem);
+    copy_nonoverlapping(program.0 as *const u64, copy.0 as *mut u64, words);
+    (*copy.0).line = line.0;
+    (*copy.0).sock = sock;
+    context.programs = context
+        .programs
+        .insert(&mut context.stack, &mut formula, copy);
+}
+
+/** Lower an arm of the code generator's IR to a program, giving `None` where it can't be. Each SSA
+ * value is given a spill slot of its own, and loaded into a register for each instruction that
+ * uses it. Arms with hints are not lowered, nor are arms with loops, which the forward labels of
+ * the bytecode can't express, nor IR which uses a value on a path where it isn't defined. Calls
+ * of arms whose formula is in a part of their subject the code table knows go through `cal` and
+ * `jmp`, so that jets and traces are as they are for the formula's own program.
+ */
+pub(crate) fn lower(stack: &mut NockStack, arm: &Arm) -> Option<Program> {
+    permit_alloc(|| {
+        let order = order(arm)?;
+        defined(arm, &order)?;
+        let mut lower = Lower {
+            compiler: Compiler::new(),
+            slots: HashMap::new(),
+            next: 0,
+        };
+        lower.compiler.labels = arm.blocks.len();
+        let lump = lower.slot(arm.lump)?;
+        lower.compiler.emit(Ins::Sto(0, lump));
+        for block in order {
+            lower.block(block, &arm.blocks[block])?;
+        }
+        lower.compiler.slots = lower.next;
+        Some(lower.compiler.finish(stack))
+    })
+}
+
+/** The blocks of an arm reachable from [Arm::dole], each before the blocks it goes to, or `None`
+ * where they loop */
+fn order(arm: &Arm) -> Option<Vec<usize>> {
+    //  0 unvisited, 1 on the path from the entry, 2 done
+    let mut state = vec![0u8; arm.blocks.len()];
+    let mut done = Vec::new();
+    let mut path = vec![(arm.dole, 0)];
+    *state.get_mut(arm.dole)? = 1;
+    while let Some(&(block, next)) = path.last() {
+        match goes(&arm.blocks[block].bend).get(next) {
+            Some(&succ) => {
+                path.last_mut()?.1 += 1;
+                match *state.get(succ)? {
+                    0 => {
+                        state[succ] = 1;
+                        path.push((succ, 0));
+                    }
+                    1 => return None,
+                    _ => {}
+                }
+            }
+            None => {
+                state[block] = 2;
+                done.push(block);
+                path.pop();
+            }
+        }
+    }
+    done.reverse();
+    Some(done)
+}
+
+/** Check that each value an arm uses is defined on every path to the use */
+fn defined(arm: &Arm, order: &[usize]) -> Option<()> {
+    let mut entry: Vec<Option<HashSet<Ssa>>> = vec![None; arm.blocks.len()];
+    entry[arm.dole] = Some(HashSet::from([arm.lump]));
+    for &index in order {
+        let mut vals = entry[index].take()?;
+        let block = &arm.blocks[index];
+        for bran in &block.body {
+            let (uses, def) = bran_vals(bran);
+            if !uses.iter().all(|val| vals.contains(val)) {
+                return None;
+            }
+            vals.insert(def);
+        }
+        let (uses, def) = germ_vals(&block.bend);
+        if !uses.iter().all(|val| vals.contains(val)) {
+            return None;
+        }
+        vals.extend(def);
+        for succ in goes(&block.bend) {
+            entry[succ] = Some(match entry[succ].take() {
+                Some(other) => other.intersection(&vals).copied().collect(),
+                None => vals.clone(),
+            });
+        }
+    }
+    Some(())
+}
+
+/** The blocks an instruction ending a block goes to */
+fn goes(germ: &Germ) -> Vec<usize> {
+    match *germ {
+        Germ::Clq(_, yes, no) | Germ::Eqq(_, _, yes, no) | Germ::Brn(_, yes, no) => vec![yes, no],
+        Germ::Hop(next)
+        | Germ::Lnk(_, _, _, next)
+        | Germ::Cal(_, _, _, next)
+        | Germ::Bec(_, _, _, next)
+        | Germ::Spy(_, _, _, next)
+        | Germ::Hnt(_, next) => vec![next],
+        Germ::Lnt(..) | Germ::Jmp(..) | Germ::Eye(..) | Germ::Don(_) | Germ::Bom => vec![],
+    }
+}
+
+/** The values an instruction uses, and the value it defines */
+fn bran_vals(bran: &Bran) -> (Vec<Ssa>, Ssa) {
+    match *bran {
+        Bran::Imm(_, d) => (vec![], d),
+        Bran::Mov(s, d)
+        | Bran::Inc(s, d)
+        | Bran::Unc(s, d)
+        | Bran::Hed(s, d)
+        | Bran::Hud(s, d)
+        | Bran::Tal(s, d)
+        | Bran::Tul(s, d) => (vec![s], d),
+        Bran::Con(h, t, d) => (vec![h, t], d),
+    }
+}
+
+/** The values an instruction ending a block uses, and the value it defines for the block it goes
+ * to */
+fn germ_vals(germ: &Germ) -> (Vec<Ssa>, Option<Ssa>) {
+    match germ {
+        Germ::Clq(s, _, _) | Germ::Brn(s, _, _) | Germ::Hnt(s, _) | Germ::Don(s) => {
+            (vec![*s], None)
+        }
+        Germ::Eqq(a, b, _, _) | Germ::Lnt(a, b) => (vec![*a, *b], None),
+        Germ::Lnk(f, s, d, _) | Germ::Spy(f, s, d, _) => (vec![*f, *s], Some(*d)),
+        Germ::Cal(_, args, d, _) => (args.iter().map(|(_, val)| *val).collect(), Some(*d)),
+        Germ::Jmp(_, args) => (args.iter().map(|(_, val)| *val).collect(), None),
+        Germ::Bec(_, s, d, _) => (vec![*s], Some(*d)),
+        Germ::Eye(_, s) => (vec![*s], None),
+        Germ::Hop(_) | Germ::Bom => (vec![], None),
+    }
+}
+
+/** What is known of part of a callee's subject, as its value is built */
+#[derive(Copy, Clone)]
+enum Part {
+    /// A `sock`
+    Sock(Noun),
+    /// All of it, from a `%know` sock
+    Known(Noun),
+    /// Nothing, where the code table knows it isn't used
+    Unknown,
+}
+
+/** Lowers the blocks of an arm */
+struct Lower {
+    compiler: Compiler,
+    /// The spill slot of each SSA value
+    slots: HashMap<Ssa, u16>,
+    /// Spill slots used, by SSA values and the parts of the subjects of calls
+    next: usize,
+}
+
+impl Lower {
+    fn slot(&mut self, val: Ssa) -> Option<u16> {
+        match self.slots.get(&val) {
+            Some(slot) => Some(*slot),
+            None => {
+                let slot = self.temp()?;
+                self.slots.insert(val, slot);
+                Some(slot)
+            }
+        }
+    }
+
+    /** A spill slot of its own, for part of the subject of a call */
+    fn temp(&mut self) -> Option<u16> {
+        if self.next > u16::MAX as usize {
+            return None;
+        }
+        self.next += 1;
+        Some((self.next - 1) as u16)
+    }
+
+    fn lod(&mut self, reg: u8, val: Ssa) -> Option<()> {
+        let slot = self.slot(val)?;
+        self.compiler.emit(Ins::Lod(reg, slot));
+        Some(())
+    }
+
+    fn sto(&mut self, reg: u8, val: Ssa) -> Option<()> {
+        let slot = self.slot(val)?;
+        self.compiler.emit(Ins::Sto(reg, slot));
+        Some(())
+    }
+
+    fn block(&mut self, index: usize, block: &Block) -> Option<()> {
+        self.compiler.emit(Ins::Label(index));
+        for bran in &block.body {
+            match *bran {
+                Bran::Imm(noun, _) => {
+                    let k = self.compiler.konst(noun);
+                    self.compiler.emit(Ins::Imm(1, k));
+                }
+                Bran::Mov(s, _) => self.lod(1, s)?,
+                //  the IR is not trusted to know atoms and cells
+                Bran::Inc(s, _) | Bran::Unc(s, _) => {
+                    self.lod(1, s)?;
+                    self.compiler.emit(Ins::Inc(1, 1));
+                }
+                Bran::Con(h, t, _) => {
+                    self.lod(1, h)?;
+                    self.lod(2, t)?;
+                    self.compiler.emit(Ins::Con(1, 1, 2));
+                }
+                Bran::Hed(s, _) | Bran::Hud(s, _) => {
+                    self.lod(1, s)?;
+                    self.compiler.emit(Ins::Hed(1, 1));
+                }
+                Bran::Tal(s, _) | Bran::Tul(s, _) => {
+                    self.lod(1, s)?;
+                    self.compiler.emit(Ins::Tal(1, 1));
+                }
+            }
+            self.sto(1, bran_vals(bran).1)?;
+        }
+        match &block.bend {
+            Germ::Clq(s, yes, no) => {
+                self.lod(1, *s)?;
+                self.compiler.emit(Ins::Clq(1, *yes, *no));
+            }
+            Germ::Eqq(a, b, yes, no) => {
+                self.lod(1, *a)?;
+                self.lod(2, *b)?;
+                self.compiler.emit(Ins::Eqq(1, 2, *yes, *no));
+            }
+            Germ::Brn(s, zero, once) => {
+                self.lod(1, *s)?;
+                self.compiler.emit(Ins::Brn(1, *zero, *once));
+            }
+            Germ::Hop(next) => self.compiler.emit(Ins::Hop(*next)),
+            Germ::Lnk(f, s, d, next) => {
+                self.lod(1, *s)?;
+                self.lod(2, *f)?;
+                self.compiler.emit(Ins::Lnk(3, 1, 2));
+                self.ret(*d, *next)?;
+            }
+            Germ::Cal(barn, args, d, next) => {
+                self.subject(*barn, args)?;
+                self.call(*barn, false)?;
+                self.ret(*d, *next)?;
+            }
+            Germ::Bec(barn, s, d, next) => {
+                self.lod(1, *s)?;
+                self.call(*barn, false)?;
+                self.ret(*d, *next)?;
+            }
+            Germ::Lnt(f, s) => {
+                self.lod(1, *s)?;
+                self.lod(2, *f)?;
+                self.compiler.emit(Ins::Lnt(1, 2));
+            }
+            Germ::Jmp(barn, args) => {
+                self.subject(*barn, args)?;
+                self.call(*barn, true)?;
+            }
+            Germ::Eye(barn, s) => {
+                self.lod(1, *s)?;
+                self.call(*barn, true)?;
+            }
+            Germ::Spy(r, p, d, next) => {
+                self.lod(1, *r)?;
+                self.lod(2, *p)?;
+                self.compiler.emit(Ins::Spy(3, 1, 2));
+                self.ret(*d, *next)?;
+            }
+            Germ::Hnt(..) => return None,
+            Germ::Don(s) => {
+                self.lod(1, *s)?;
+                self.compiler.emit(Ins::Don(1));
+            }
+            Germ::Bom => self.compiler.emit(Ins::Bom),
+        }
+        Some(())
+    }
+
+    /** Store a call's product, in `r3`, and go on to the next block */
+    fn ret(&mut self, d: Ssa, next: usize) -> Option<()> {
+        self.sto(3, d)?;
+        self.compiler.emit(Ins::Hop(next));
+        Some(())
+    }
+
+    /** Call the arm a `barn` names on the subject in `r1`, product to `r3` */
+    fn call(&mut self, barn: Noun, tail: bool) -> Option<()> {
+        let barn = barn.as_cell().ok()?;
+        let formula = barn.tail();
+        match unsafe { axis_of(barn.head(), formula) } {
+            Some(axis) => {
+                let k = self.compiler.konst(D(axis));
+                self.compiler.emit(if tail {
+                    Ins::Jmp(1, k)
+                } else {
+                    Ins::Cal(3, 1, k)
+                });
+            }
+            None => {
+                let k = self.compiler.konst(formula);
+                self.compiler.emit(Ins::Imm(2, k));
+                self.compiler.emit(if tail {
+                    Ins::Lnt(1, 2)
+                } else {
+                    Ins::Lnk(3, 1, 2)
+                });
+            }
+        }
+        Some(())
+    }
+
+    /** Build the subject of a call into `r1`, from its arguments and what the code table knows of
+     * the rest */
+    fn subject(&mut self, barn: Noun, args: &[(Noun, Ssa)]) -> Option<()> {
+        let sock = barn.as_cell().ok()?.head();
+        let mut parts = Vec::with_capacity(args.len());
+        for (axis, val) in args {
+            let axis = axis.as_atom().ok()?.as_u64().ok()?;
+            if axis == 0 {
+                return None;
+            }
+            parts.push((axis, *val));
+        }
+        let slot = self.part(Part::Sock(sock), 1, &parts)?;
+        self.compiler.emit(Ins::Lod(1, slot));
+        Some(())
+    }
+
+    /** Build the part of a callee's subject at an axis into a slot, giving the slot */
+    fn part(&mut self, part: Part, at: u64, parts: &[(u64, Ssa)]) -> Option<u16> {
+        if let Some(&(_, val)) = parts.iter().find(|(axis, _)| *axis == at) {
+            return self.slot(val);
+        }
+        let inner = parts.iter().any(|(axis, _)| inside(*axis, at));
+        let (head, tail) = match part {
+            Part::Known(noun) if !inner => return self.imm(noun),
+            Part::Known(noun) => {
+                let cell = noun.as_cell().ok()?;
+                (Part::Known(cell.head()), Part::Known(cell.tail()))
+            }
+            Part::Sock(sock) => {
+                let sock = sock.as_cell().ok()?;
+                match sock.head().direct().map(|tag| tag.data()) {
+                    Some(tas!(b"know")) => return self.part(Part::Known(sock.tail()), at, parts),
+                    Some(tas!(b"bets")) => {
+                        let bets = sock.tail().as_cell().ok()?;
+                        (Part::Sock(bets.head()), Part::Sock(bets.tail()))
+                    }
+                    _ => return self.part(Part::Unknown, at, parts),
+                }
+            }
+            Part::Unknown if !inner => return self.imm(D(0)),
+            Part::Unknown => (Part::Unknown, Part::Unknown),
+        };
+        let at = at.checked_mul(2)?;
+        let head = self.part(head, at, parts)?;
+        let tail = self.part(tail, at + 1, parts)?;
+        self.compiler.emit(Ins::Lod(1, head));
+        self.compiler.emit(Ins::Lod(2, tail));
+        self.compiler.emit(Ins::Con(1, 1, 2));
+        let slot = self.temp()?;
+        self.compiler.emit(Ins::Sto(1, slot));
+        Some(slot)
+    }
+
+    fn imm(&mut self, noun: Noun) -> Option<u16> {
+        let k = self.compiler.konst(noun);
+        self.compiler.emit(Ins::Imm(1, k));
+        let slot = self.temp()?;
+        self.compiler.emit(Ins::Sto(1, slot));
+        Some(slot)
+    }
+}
+
+/** Whether an axis is below another */
+fn inside(axis: u64, at: u64) -> bool {
+    let (bits, at_bits) = (64 - axis.leading_zeros(), 64 - at.leading_zeros());
+    bits > at_bits && axis >> (bits - at_bits) == at
+}
+
+/** The axis of a formula in the parts of a subject a `sock` knows, or `None` if it isn't found
+ * within a few thousand nouns. Pointers are compared, since the code generator took the formula
+ * from the subject. */
+unsafe fn axis_of(sock: Noun, formula: Noun) -> Option<u64> {
+    let mut budget = 1 << 12;
+    //  a sock, or a noun it knows, and its axis
+    let mut todo = vec![(sock, 1u64, false)];
+    while let Some((noun, axis, known)) = todo.pop() {
+        budget -= 1;
+        if budget == 0 || axis >= 1 << 62 {
+            return None;
+        }
+        if known {
+            if noun.raw_equals(formula) {
+                return Some(axis);
+            }
+            if let Ok(cell) = noun.as_cell() {
+                todo.push((cell.tail(), axis * 2 + 1, true));
+                todo.push((cell.head(), axis * 2, true));
+            }
+            continue;
+        }
+        let Ok(sock) = noun.as_cell() else {
+            return None;
+        };
+        match sock.head().direct().map(|tag| tag.data()) {
+            Some(tas!(b"know")) => todo.push((sock.tail(), axis, true)),
+            Some(tas!(b"bets")) => {
+                let bets = sock.tail().as_cell().ok()?;
+                todo.push((bets.tail(), axis * 2 + 1, false));
+                todo.push((bets.head(), axis * 2, false));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A value the compiled code computes
+type Val = usize;
+
+/// A position in the code, resolved when it is assembled
+type Label = usize;
+
+/// The subject, in `r0` on entry
+const SUBJECT: Val = 0;
+
+/** An instruction, with its labels not yet resolved */
+enum Ins {
+    Imm(u8, u32),
+    Mov(u8, u8),
+    Inc(u8, u8),
+    Con(u8, u8, u8),
+    Hed(u8, u8),
+    Tal(u8, u8),
+    Hud(u8, u8),
+    Tul(u8, u8),
+    Clq(u8, Label, Label),
+    Eqq(u8, u8, Label, Label),
+    Brn(u8, Label, Label),
+    Hop(Label),
+    Cal(u8, u8, u32),
+    Lnk(u8, u8, u8),
+    Jmp(u8, u32),
+    Lnt(u8, u8),
+    Spy(u8, u8, u8),
+    /// Destination, subject, clue, phase, `[hint body]`, and where to go if the hint produced
+    /// the result
+    Hnt(u8, u8, u8, u8, u32, Option<Label>),
+    Bom,
+    Don(u8),
+    Lod(u8, u16),
+    Sto(u8, u16),
+    /// The position of a label, which takes no space
+    Label(Label),
+}
+
+impl Ins {
+    fn labels(&self) -> [Option<Label>; 2] {
+        match *self {
+            Ins::Clq(_, yes, no) | Ins::Eqq(_, _, yes, no) | Ins::Brn(_, yes, no) => {
+                [Some(yes), Some(no)]
+            }
+            Ins::Hop(label) => [Some(label), None],
+            Ins::Hnt(_, _, _, _, _, label) => [label, None],
+            _ => [None, None],
+        }
+    }
+
+    /** Encode all but the labels */
+    fn encode(&self, out: &mut Vec<u8>) {
+        let mut op = |op: u8, a: u8| out.push(op | a << 5);
+        match *self {
+            Ins::Imm(d, k) => {
+                op(IMM, d);
+                out.extend_from_slice(&k.to_le_bytes());
+            }
+            Ins::Mov(d, s) => {
+                op(MOV, d);
+                out.push(s);
+            }
+            Ins::Inc(d, s) => {
+                op(INC, d);
+                out.push(s);
+            }
+            Ins::Con(d, h, t) => {
+                op(CON, d);
+                out.push(h | t << 3);
+            }
+            Ins::Hed(d, s) => {
+                op(HED, d);
+                out.push(s);
+            }
+            Ins::Tal(d, s) => {
+                op(TAL, d);
+                out.push(s);
+            }
+            Ins::Hud(d, s) => {
+                op(HUD, d);
+                out.push(s);
+            }
+            Ins::Tul(d, s) => {
+                op(TUL, d);
+                out.push(s);
+            }
+            Ins::Clq(s, _, _) => op(CLQ, s),
+            Ins::Eqq(a, b, _, _) => {
+                op(EQQ, a);
+                out.push(b);
+            }
+            Ins::Brn(s, _, _) => op(BRN, s),
+            Ins::Hop(_) => op(HOP, 0),
+            Ins::Cal(d, core, k) => {
+                op(CAL, d);
+                out.push(core);
+                out.extend_from_slice(&k.to_le_bytes());
+            }
+            Ins::Lnk(d, s, f) => {
+                op(LNK, d);
+                out.push(s | f << 3);
+            }
+            Ins::Jmp(core, k) => {
+                op(JMP, core);
+                out.extend_from_slice(&k.to_le_bytes());
+            }
+            Ins::Lnt(s, f) => {
+                op(LNT, s);
+                out.push(f);
+            }
+            Ins::Spy(d, r, p) => {
+                op(SPY, d);
+                out.push(r | p << 3);
+            }
+            Ins::Hnt(d, s, c, phase, k, _) => {
+                op(HNT, d);
+                out.push(s | c << 3 | phase << 6);
+                out.extend_from_slice(&k.to_le_bytes());
+            }
+            Ins::Bom => op(BOM, 0),
+            Ins::Don(s) => op(DON, s),
+            Ins::Lod(d, slot) => {
+                op(LOD, d);
+                out.extend_from_slice(&slot.to_le_bytes());
+            }
+            Ins::Sto(s, slot) => {
+                op(STO, s);
+                out.extend_from_slice(&slot.to_le_bytes());
+            }
+            Ins::Label(_) => {}
+        }
+    }
+}
+
+/** Encode instructions, giving each label the fewest bytes that reach its target */
+fn assemble(code: &[Ins], labels: usize) -> Vec<u8> {
+    let mut fixed = Vec::new();
+    let mut ranges = Vec::with_capacity(code.len());
+    for ins in code {
+        let start = fixed.len();
+        ins.encode(&mut fixed);
+        ranges.push(start..fixed.len());
+    }
+
+    //  labels only grow, so this settles
+    let mut widths = vec![[0; 2]; code.len()];
+    let mut ends = vec![0; code.len()];
+    let mut targets = vec![0; labels];
+    loop {
+        let mut pos = 0;
+        for (i, ins) in code.iter().enumerate() {
+            if let Ins::Label(label) = *ins {
+                targets[label] = pos;
+            }
+            pos += ranges[i].len() + widths[i][0] + widths[i][1];
+            ends[i] = pos;
+        }
+        let mut grown = false;
+        for (i, ins) in code.iter().enumerate() {
+            for (j, label) in ins.labels().iter().enumerate() {
+                if let Some(label) = *label {
+                    let width = label_width(targets[label] - ends[i]);
+                    if width > widths[i][j] {
+                        widths[i][j] = width;
+                        grown = true;
+                    }
+                }
+            }
+        }
+        if !grown {
+            break;
+        }
+    }
+
+    let mut out = Vec::with_capacity(ends.last().copied().unwrap_or(0));
+    for (i, ins) in code.iter().enumerate() {
+        out.extend_from_slice(&fixed[ranges[i].clone()]);
+        for (j, label) in ins.labels().iter().enumerate() {
+            if let Some(label) = *label {
+                let offset = targets[label] - ends[i];
+                match widths[i][j] {
+                    1 => out.push(0),
+                    2 => out.extend_from_slice(&((offset << 2 | 1) as u16).to_le_bytes()),
+                    _ => out.extend_from_slice(&((offset << 2 | 3) as u64).to_le_bytes()[..7]),
+                }
+            }
+        }
+    }
+    out
+}
+
+/** Bytes a label needs to reach `offset`: a fall-through is one byte, and other offsets take 13
+ * or 48 bits after two flag bits */
+fn label_width(offset: usize) -> usize {
+    if offset == 0 {
+        1
+    } else if offset < 1 << 13 {
+        2
+    } else {
+        7
+    }
+}
+
+/** Register allocation at a point in the code */
+#[derive(Clone)]
+struct Alloc {
+    /// The value each register holds
+    regs: [Option<Val>; REGS],
+    /// Registers holding no value, the most recently freed last
+    free: Vec<u8>,
+    /// When each register was last used, to spill the least recently used
+    used: [usize; REGS],
+    tick: usize,
+    /// Register and spill slot of each value; neither, once it is dead
+    vals: Vec<(Option<u8>, Option<u16>)>,
+    /// Spill slots below `next_slot` which are not in use
+    free_slots: Vec<u16>,
+    next_slot: u16,
+    /// Registers holding operands of the next instruction, which must not be spilled
+    pinned: u8,
+}
+
+/** Compiles a formula to instructions
+ *
+ * Values are either subjects, live for the formula they are the subject of, or temporaries,
+ * freed by the instruction which uses them. Values are spilled to the frame's slots when the
+ * registers run out, and stay in their slot once it is assigned. Wherever paths through the code
+ * join, each one leaves its result in the same register, and reloads the values it spilled into
+ * the registers they had where the paths split.
+ */
+struct Compiler {
+    code: Vec<Ins>,
+    consts: Vec<Noun>,
+    /// Constants which are direct atoms, which recur often, and their indices
+    directs: HashMap<u64, u32>,
+    labels: usize,
+    alloc: Alloc,
+    /// Spill slots needed
+    slots: usize,
+    /// Nesting of the formula being compiled
+    depth: usize,
+}
+
+impl Compiler {
+    fn new() -> Compiler {
+        let mut regs = [None; REGS];
+        regs[0] = Some(SUBJECT);
+        Compiler {
+            code: Vec::new(),
+            consts: Vec::new(),
+            directs: HashMap::new(),
+            labels: 0,
+            alloc: Alloc {
+                regs,
+                free: (1..REGS as u8).rev().collect(),
+                used: [0; REGS],
+                tick: 0,
+                vals: vec![(Some(0), None)],
+                free_slots: Vec::new(),
+                next_slot: 0,
+                pinned: 0,
+            },
+            slots: 0,
+            depth: 0,
+        }
+    }
+
+    /** Assemble the instructions, allocating the program on the NockStack */
+    fn finish(self, stack: &mut NockStack) -> Program {
+        let code = assemble(&self.code, self.labels);
+        let consts = self.consts;
+        unsafe {
+            let words = word_size_of::<ProgramMem>() + consts.len() + code.len().div_ceil(8);
+            let program = Program(stack.struct_alloc::<u64>(words) as *mut ProgramMem);
+            *program.0 = ProgramMem {
+                slots: self.slots,
+                consts: consts.len(),
+                len: code.len(),
+                runs: 0,
+                line: std::ptr::null_mut(),
+                sock: D(0),
+            };
+            copy_nonoverlapping(consts.as_ptr(), program.consts(), consts.len());
+            copy_nonoverlapping(code.as_ptr(), program.code() as *mut u8, code.len());
+            program
+        }
+    }
+
+    fn emit(&mut self, ins: Ins) {
+        self.alloc.pinned = 0;
+        self.code.push(ins);
+    }
+
+    fn label(&mut self) -> Label {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    fn konst(&mut self, noun: Noun) -> u32 {
+        let direct = noun.direct().map(|direct| direct.data());
+        if let Some(k) = direct.and_then(|direct| self.directs.get(&direct)) {
+            return *k;
+        }
+        let k = self.consts.len() as u32;
+        self.consts.push(noun);
+        if let Some(direct) = direct {
+            self.directs.insert(direct, k);
+        }
+        k
+    }
+
+    fn touch(&mut self, reg: u8) {
+        self.alloc.tick += 1;
+        self.alloc.used[reg as usize] = self.alloc.tick;
+    }
+
+    /** Free the least recently used register which is not pinned, storing its value in a slot
+     * unless it already has one */
+    fn spill(&mut self) {
+        let alloc = &mut self.alloc;
+        let reg = (0..REGS as u8)
+            .filter(|reg| alloc.pinned & 1 << reg == 0)
+            .min_by_key(|reg| alloc.used[*reg as usize])
+            .expect("bytecode: every register is pinned");
+        let val = alloc.regs[reg as usize]
+            .take()
+            .expect("bytecode: spilling a free register");
+        if alloc.vals[val].1.is_none() {
+            let slot = match alloc.free_slots.pop() {
+                Some(slot) => slot,
+                None => {
+                    alloc.next_slot += 1;
+                    alloc.next_slot - 1
+                }
+            };
+            self.slots = self.slots.max(alloc.next_slot as usize);
+            alloc.vals[val].1 = Some(slot);
+            self.code.push(Ins::Sto(reg, slot));
+        }
+        alloc.vals[val].0 = None;
+        alloc.free.push(reg);
+    }
+
+    /** A free register, which stays free */
+    fn free(&mut self) -> u8 {
+        if self.alloc.free.is_empty() {
+            self.spill();
+        }
+        *self.alloc.free.last().unwrap()
+    }
+
+    /** Take a free register */
+    fn take(&mut self) -> u8 {
+        if self.alloc.free.is_empty() {
+            self.spill();
+        }
+        self.alloc.free.pop().unwrap()
+    }
+
+    /** A new value in `reg`, which must be free */
+    fn claim(&mut self, reg: u8) -> Val {
+        self.alloc.free.retain(|free| *free != reg);
+        let val = self.alloc.vals.len();
+        self.alloc.vals.push((Some(reg), None));
+        self.alloc.regs[reg as usize] = Some(val);
+        self.touch(reg);
+        val
+    }
+
+    /** A new value, and the register to compute it into */
+    fn def(&mut self) -> (Val, u8) {
+        let reg = self.take();
+        (self.claim(reg), reg)
+    }
+
+    /** The register holding a value, reloading it if it was spilled. The register is pinned
+     * until the next instruction is emitted, so all of an instruction's operands must be had
+     * before any of them is killed. */
+    fn reg(&mut self, val: Val) -> u8 {
+        let reg = match self.alloc.vals[val] {
+            (Some(reg), _) => reg,
+            (None, Some(slot)) => {
+                let reg = self.take();
+                self.code.push(Ins::Lod(reg, slot));
+                self.alloc.vals[val].0 = Some(reg);
+                self.alloc.regs[reg as usize] = Some(val);
+                reg
+            }
+            (None, None) => panic!("bytecode: use of a dead value"),
+        };
+        self.alloc.pinned |= 1 << reg;
+        self.touch(reg);
+        reg
+    }
+
+    fn kill(&mut self, val: Val) {
+        let (reg, slot) = std::mem::take(&mut self.alloc.vals[val]);
+        if let Some(reg) = reg {
+            self.alloc.regs[reg as usize] = None;
+            self.alloc.free.push(reg);
+        }
+        if let Some(slot) = slot {
+            self.alloc.free_slots.push(slot);
+        }
+    }
+
+    /** Return to the allocation `to`, from a point where no values newer than it are live */
+    fn reset(&mut self, to: &Alloc) {
+        self.alloc = to.clone();
+        self.alloc.pinned = 0;
+    }
+
+    /** Reload the values `to` holds in registers, but which have been spilled since, and return
+     * to it */
+    fn restore(&mut self, to: &Alloc) {
+        for (val, (reg, _)) in to.vals.iter().enumerate() {
+            if let Some(reg) = *reg {
+                if self.alloc.vals[val].0 != Some(reg) {
+                    let slot = self.alloc.vals[val]
+                        .1
+                        .expect("bytecode: spilled value has no slot");
+                    self.code.push(Ins::Lod(reg, slot));
+                }
+            }
+        }
+        self.reset(to);
+    }
+
+    /** End a path to a join: move its result to `dest`, which is free in `to`, and return to
+     * `to` */
+    fn join(&mut self, val: Val, dest: u8, to: &Alloc) {
+        match self.alloc.vals[val] {
+            (Some(reg), _) if reg == dest => {}
+            (Some(reg), _) => self.code.push(Ins::Mov(dest, reg)),
+            (None, Some(slot)) => self.code.push(Ins::Lod(dest, slot)),
+            (None, None) => panic!("bytecode: join of a dead value"),
+        }
+        self.kill(val);
+        debug_assert!(self.alloc.vals[to.vals.len()..]
+            .iter()
+            .all(|loc| *loc == (None, None)));
+        self.restore(to);
+    }
+
+    /** In tail position, return a value; otherwise, leave it for the enclosing formula */
+    fn ret(&mut self, val: Val, tail: bool) -> Option<Val> {
+        if !tail {
+            return Some(val);
+        }
+        let reg = self.reg(val);
+        self.kill(val);
+        self.emit(Ins::Don(reg));
+        None
+    }
+
+    /** Crash, where the tree walker would find the formula invalid */
+    fn bom(&mut self, tail: bool) -> Option<Val> {
+        self.emit(Ins::Bom);
+        if tail {
+            None
+        } else {
+            Some(self.def().0)
+        }
+    }
+
+    /** Compute a formula's product into a new value */
+    fn value(&mut self, formula: Noun, subject: Val) -> Val {
+        self.formula(formula, subject, false)
+            .expect("bytecode: no value outside tail position")
+    }
+
+    /** Compile a formula against a subject. In tail position, the code returns the product;
+     * otherwise the product is left in a new value. */
+    fn formula(&mut self, formula: Noun, subject: Val, tail: bool) -> Option<Val> {
+        if self.depth == MAX_DEPTH {
+            return self.link(formula, subject, tail);
+        }
+        self.depth += 1;
+        let res = self.nock(formula, subject, tail);
+        self.depth -= 1;
+        res
+    }
+
+    /** Evaluate a formula as a program of its own */
+    fn link(&mut self, formula: Noun, subject: Val, tail: bool) -> Option<Val> {
+        let (form, reg) = self.def();
+        let k = self.konst(formula);
+        self.emit(Ins::Imm(reg, k));
+        let (rs, rf) = (self.reg(subject), self.reg(form));
+        self.kill(form);
+        if tail {
+            self.emit(Ins::Lnt(rs, rf));
+            None
+        } else {
+            let (val, reg) = self.def();
+            self.emit(Ins::Lnk(reg, rs, rf));
+            Some(val)
+        }
+    }
+
+    fn nock(&mut self, formula: Noun, subject: Val, tail: bool) -> Option<Val> {
+        let Some(cell) = formula.cell() else {
+            return self.bom(tail);
+        };
+        let arg = cell.tail();
+        let op = match cell.head().as_either_atom_cell() {
+            Left(op) => match op.direct() {
+                Some(op) => op.data(),
+                None => return self.bom(tail),
+            },
+            Right(_) => {
+                let head = self.value(cell.head(), subject);
+                let tail_val = self.value(arg, subject);
+                let (rh, rt) = (self.reg(head), self.reg(tail_val));
+                self.kill(head);
+                self.kill(tail_val);
+                let (val, reg) = self.def();
+                self.emit(Ins::Con(reg, rh, rt));
+                return self.ret(val, tail);
+            }
+        };
+
+        match op {
+            0 => {
+                let Some(axis) = arg.atom() else {
+                    return self.bom(tail);
+                };
+                let bits = axis.as_bitslice();
+                let Some(top) = bits.last_one() else {
+                    return self.bom(tail);
+                };
+                if top == 0 && tail {
+                    let reg = self.reg(subject);
+                    self.emit(Ins::Don(reg));
+                    return None;
+                }
+                if top == 0 {
+                    let from = self.reg(subject);
+                    let (val, reg) = self.def();
+                    self.emit(Ins::Mov(reg, from));
+                    return Some(val);
+                }
+                let mut cur = subject;
+                for i in (0..top).rev() {
+                    let from = self.reg(cur);
+                    if cur != subject {
+                        self.kill(cur);
+                    }
+                    let (val, reg) = self.def();
+                    self.emit(if bits[i] {
+                        Ins::Tal(reg, from)
+                    } else {
+                        Ins::Hed(reg, from)
+                    });
+                    cur = val;
+                }
+                self.ret(cur, tail)
+            }
+            1 => {
+                let (val, reg) = self.def();
+                let k = self.konst(arg);
+                self.emit(Ins::Imm(reg, k));
+                self.ret(val, tail)
+            }
+            2 => {
+                let Some(arg) = arg.cell() else {
+                    return self.bom(tail);
+                };
+                let sub = self.value(arg.head(), subject);
+                let form = self.value(arg.tail(), subject);
+                let (rs, rf) = (self.reg(sub), self.reg(form));
+                self.kill(sub);
+                self.kill(form);
+                if tail {
+                    self.emit(Ins::Lnt(rs, rf));
+                    None
+                } else {
+                    let (val, reg) = self.def();
+                    self.emit(Ins::Lnk(reg, rs, rf));
+                    Some(val)
+                }
+            }
+            3 => {
+                let val = self.value(arg, subject);
+                let reg = self.reg(val);
+                self.kill(val);
+                self.loobean(tail, |yes, no| Ins::Clq(reg, yes, no))
+            }
+            4 => {
+                let val = self.value(arg, subject);
+                let from = self.reg(val);
+                self.kill(val);
+                let (val, reg) = self.def();
+                self.emit(Ins::Inc(reg, from));
+                self.ret(val, tail)
+            }
+            5 => {
+                let Some(arg) = arg.cell() else {
+                    return self.bom(tail);
+                };
+                let a = self.value(arg.head(), subject);
+                let b = self.value(arg.tail(), subject);
+                let (ra, rb) = (self.reg(a), self.reg(b));
+                self.kill(a);
+                self.kill(b);
+                self.loobean(tail, |yes, no| Ins::Eqq(ra, rb, yes, no))
+            }
+            6 => {
+                let Some(arg) = arg.cell() else {
+                    return self.bom(tail);
+                };
+                let Some(arms) = arg.tail().cell() else {
+                    return self.bom(tail);
+                };
+                self.branch(arg.head(), arms.head(), arms.tail(), subject, tail)
+            }
+            7 => {
+                let Some(arg) = arg.cell() else {
+                    return self.bom(tail);
+                };
+                let sub = self.value(arg.head(), subject);
+                let res = self.formula(arg.tail(), sub, tail);
+                self.kill(sub);
+                res
+            }
+            8 => {
+                let Some(arg) = arg.cell() else {
+                    return self.bom(tail);
+                };
+                let pin = self.value(arg.head(), subject);
+                let (rp, rs) = (self.reg(pin), self.reg(subject));
+                self.kill(pin);
+                let (sub, reg) = self.def();
+                self.emit(Ins::Con(reg, rp, rs));
+                let res = self.formula(arg.tail(), sub, tail);
+                self.kill(sub);
+                res
+            }
+            9 => {
+                let Some(arg) = arg.cell() else {
+                    return self.bom(tail);
+                };
+                let Some(axis) = arg.head().atom() else {
+                    return self.bom(tail);
+                };
+                let core = self.value(arg.tail(), subject);
+                let rc = self.reg(core);
+                self.kill(core);
+                let k = self.konst(axis.as_noun());
+                if tail {
+                    self.emit(Ins::Jmp(rc, k));
+                    None
+                } else {
+                    let (val, reg) = self.def();
+                    self.emit(Ins::Cal(reg, rc, k));
+                    Some(val)
+                }
+            }
+            10 => {
+                let Some(arg) = arg.cell() else {
+                    return self.bom(tail);
+                };
+                let Some(patch) = arg.head().cell() else {
+                    return self.bom(tail);
+                };
+                let Some(axis) = patch.head().atom() else {
+                    return self.bom(tail);
+                };
+                let tree = self.value(arg.tail(), subject);
+                let val = self.value(patch.tail(), subject);
+                self.edit(axis, tree, val, tail)
+            }
+            11 => {
+                let Some(arg) = arg.cell() else {
+                    return self.bom(tail);
+                };
+                let k = self.konst(arg.as_noun());
+                match arg.head().as_either_atom_cell() {
+                    Left(tag) => self.hint(tag, None, arg.tail(), k, subject, tail),
+                    Right(hint) => {
+                        let Some(tag) = hint.head().atom() else {
+                            return self.bom(tail);
+                        };
+                        self.hint(tag, Some(hint.tail()), arg.tail(), k, subject, tail)
+                    }
+                }
+            }
+            12 => {
+                let Some(arg) = arg.cell() else {
+                    return self.bom(tail);
+                };
+                let reff = self.value(arg.head(), subject);
+                let path = self.value(arg.tail(), subject);
+                let (rr, rp) = (self.reg(reff), self.reg(path));
+                self.kill(reff);
+                self.kill(path);
+                let (val, reg) = self.def();
+                self.emit(Ins::Spy(reg, rr, rp));
+                self.ret(val, tail)
+            }
+            _ => self.bom(tail),
+        }
+    }
+
+    /** Compute a loobean from a branch instruction */
+    fn loobean<F>(&mut self, tail: bool, branch: F) -> Option<Val>
+    where
+        F: FnOnce(Label, Label) -> Ins,
+    {
+        let (yes, no, end) = (self.label(), self.label(), self.label());
+        let (val, reg) = self.def();
+        self.emit(branch(yes, no));
+        let (k0, k1) = (self.konst(D(0)), self.konst(D(1)));
+        self.emit(Ins::Label(yes));
+        self.emit(Ins::Imm(reg, k0));
+        self.emit(Ins::Hop(end));
+        self.emit(Ins::Label(no));
+        self.emit(Ins::Imm(reg, k1));
+        self.emit(Ins::Label(end));
+        self.ret(val, tail)
+    }
+
+    /** Nock 6, branching directly on the tests of Nock 3 and 5 */
+    fn branch(
+        &mut self,
+        test: Noun,
+        zero: Noun,
+        once: Noun,
+        subject: Val,
+        tail: bool,
+    ) -> Option<Val> {
+        let (yes, no, end) = (self.label(), self.label(), self.label());
+        let special = test
+            .cell()
+            .and_then(|test| Some((test.head().direct()?.data(), test.tail())));
+        let ins = match special {
+            Some((1, arg)) if unsafe { arg.raw_equals(D(0)) } => {
+                return self.formula(zero, subject, tail);
+            }
+            Some((1, arg)) if unsafe { arg.raw_equals(D(1)) } => {
+                return self.formula(once, subject, tail);
+            }
+            Some((3, arg)) => {
+                let val = self.value(arg, subject);
+                let reg = self.reg(val);
+                self.kill(val);
+                Ins::Clq(reg, yes, no)
+            }
+            Some((5, arg)) if arg.is_cell() => {
+                let arg = arg.cell().unwrap();
+                let a = self.value(arg.head(), subject);
+                let b = self.value(arg.tail(), subject);
+                let (ra, rb) = (self.reg(a), self.reg(b));
+                self.kill(a);
+                self.kill(b);
+                Ins::Eqq(ra, rb, yes, no)
+            }
+            _ => {
+                let val = self.value(test, subject);
+                let reg = self.reg(val);
+                self.kill(val);
+                Ins::Brn(reg, yes, no)
+            }
+        };
+
+        let dest = self.free();
+        self.emit(ins);
+        let split = self.alloc.clone();
+        self.emit(Ins::Label(yes));
+        if let Some(val) = self.formula(zero, subject, tail) {
+            self.join(val, dest, &split);
+            self.emit(Ins::Hop(end));
+        }
+        self.reset(&split);
+        self.emit(Ins::Label(no));
+        if let Some(val) = self.formula(once, subject, tail) {
+            self.join(val, dest, &split);
+        }
+        self.emit(Ins::Label(end));
+        self.reset(&split);
+        if tail {
+            None
+        } else {
+            Some(self.claim(dest))
+        }
+    }
+
+    /** Nock 10: take the tree apart along the axis, checking that each step is a cell, and
+     * build it back up around the new value */
+    fn edit(&mut self, axis: Atom, tree: Val, val: Val, tail: bool) -> Option<Val> {
+        let bits = axis.as_bitslice();
+        let Some(top) = bits.last_one() else {
+            self.kill(tree);
+            self.kill(val);
+            return self.bom(tail);
+        };
+
+        let mut siblings = Vec::with_capacity(top);
+        let mut cur = tree;
+        for i in (0..top).rev() {
+            let from = self.reg(cur);
+            let (sibling, reg) = self.def();
+            self.emit(if bits[i] {
+                Ins::Hed(reg, from)
+            } else {
+                Ins::Tal(reg, from)
+            });
+            siblings.push(sibling);
+            if i == 0 {
+                break;
+            }
+            let from = self.reg(cur);
+            self.kill(cur);
+            let (next, reg) = self.def();
+            self.emit(if bits[i] {
+                Ins::Tul(reg, from)
+            } else {
+                Ins::Hud(reg, from)
+            });
+            cur = next;
+        }
+        self.kill(cur);
+
+        let mut acc = val;
+        for (i, sibling) in siblings.into_iter().rev().enumerate() {
+            let (ra, rs) = (self.reg(acc), self.reg(sibling));
+            self.kill(acc);
+            self.kill(sibling);
+            let (val, reg) = self.def();
+            self.emit(if bits[i] {
+                Ins::Con(reg, rs, ra)
+            } else {
+                Ins::Con(reg, ra, rs)
+            });
+            acc = val;
+        }
+        self.ret(acc, tail)
+    }
+
+    /** Nock 11. Where the hint produces the result itself, `hnt` jumps past the body. */
+    fn hint(
+        &mut self,
+        tag: Atom,
+        form: Option<Noun>,
+        body: Noun,
+        k: u32,
+        subject: Val,
+        tail: bool,
+    ) -> Option<Val> {
+        if tail && hint::is_tail(tag) {
+            let (pre, nock) = (self.label(), self.label());
+            let mut pre_reg = 0;
+            if form.is_some() {
+                pre_reg = self.reg(subject);
+                self.emit(Ins::Hnt(pre_reg, pre_reg, 0, PRE, k, Some(pre)));
+            }
+            let clue = form.map(|form| self.value(form, subject));
+            let rs = self.reg(subject);
+            let rc = match clue {
+                Some(clue) => self.reg(clue),
+                None => 0,
+            };
+            self.emit(Ins::Hnt(rs, rs, rc, NOCK, k, Some(nock)));
+            if let Some(clue) = clue {
+                self.kill(clue);
+            }
+            self.formula(body, subject, true);
+            self.emit(Ins::Label(pre));
+            if form.is_some() {
+                self.emit(Ins::Don(pre_reg));
+            }
+            self.emit(Ins::Label(nock));
+            self.emit(Ins::Don(rs));
+            return None;
+        }
+
+        let (nock, end) = (self.label(), self.label());
+        let rs = self.reg(subject);
+        let dest = self.free();
+        if form.is_some() {
+            self.emit(Ins::Hnt(dest, rs, 0, PRE, k, Some(end)));
+        }
+        let split = self.alloc.clone();
+        let clue = form.map(|form| self.value(form, subject));
+        let rs = self.reg(subject);
+        let rc = match clue {
+            Some(clue) => self.reg(clue),
+            None => 0,
+        };
+        self.emit(Ins::Hnt(dest, rs, rc, NOCK, k, Some(nock)));
+        let hit = self.alloc.clone();
+
+        let res = self.value(body, subject);
+        let (rr, rs) = (self.reg(res), self.reg(subject));
+        let rc = match clue {
+            Some(clue) => self.reg(clue),
+            None => 0,
+        };
+        self.emit(Ins::Hnt(rr, rs, rc, POST, k, None));
+        if let Some(clue) = clue {
+            self.kill(clue);
+        }
+        self.join(res, dest, &split);
+        self.emit(Ins::Hop(end));
+
+        self.reset(&hit);
+        self.emit(Ins::Label(nock));
+        if let Some(clue) = clue {
+            self.kill(clue);
+        }
+        self.restore(&split);
+        self.emit(Ins::Label(end));
+        self.reset(&split);
+        let val = self.claim(dest);
+        self.ret(val, tail)
+    }
+}
+
+/** A frame of bytecode, saved on the caller's lightweight stack during a call */
+#[derive(Copy, Clone)]
+struct Frame {
+    program: Program,
+    /// Offset of the next instruction
+    pc: usize,
+    regs: [Noun; REGS],
+    /// Spill slots, and how many there are
+    slots: *mut Noun,
+    slots_len: usize,
+    /// Register to return the callee's product to
+    dest: usize,
+}
+
+impl Frame {
+    /** Start running a program in the current NockStack frame, reusing the spill slots if there
+     * are enough */
+    unsafe fn enter(&mut self, stack: &mut NockStack, program: Program, subject: Noun) {
+        self.program = program;
+        self.pc = 0;
+        self.regs[0] = subject;
+        if program.slots() > self.slots_len {
+            self.slots = stack.struct_alloc(program.slots());
+            self.slots_len = program.slots();
+        }
+    }
+}
+
+/** Decode a label, returning its offset */
+#[inline(always)]
+unsafe fn label(code: *const u8, pc: &mut usize) -> usize {
+    let byte = *code.add(*pc);
+    if byte & 1 == 0 {
+        *pc += 1;
+        0
+    } else if byte & 2 == 0 {
+        let word = u16::from_le_bytes([byte, *code.add(*pc + 1)]);
+        *pc += 2;
+        (word >> 2) as usize
+    } else {
+        let mut bytes = [0; 8];
+        copy_nonoverlapping(code.add(*pc), bytes.as_mut_ptr(), 7);
+        *pc += 7;
+        (u64::from_le_bytes(bytes) >> 2) as usize
+    }
+}
+
+#[inline(always)]
+unsafe fn konst(code: *const u8, pc: usize) -> usize {
+    let mut bytes = [0; 4];
+    copy_nonoverlapping(code.add(pc), bytes.as_mut_ptr(), 4);
+    u32::from_le_bytes(bytes) as usize
+}
+
+#[inline(always)]
+unsafe fn slot(code: *const u8, pc: usize) -> usize {
+    u16::from_le_bytes([*code.add(pc), *code.add(pc + 1)]) as usize
+}
+
+/** The register operands packed into an instruction's second byte */
+#[inline(always)]
+unsafe fn operands(code: *const u8, pc: usize) -> (usize, usize) {
+    let byte = *code.add(pc + 1);
+    ((byte & 7) as usize, (byte >> 3 & 7) as usize)
+}
+
+/** The formula of an arm and the core to run it on, or the product of its jet, where it has one
+ * which doesn't punt */
+unsafe fn arm(
+    context: &mut Context,
+    mut core: Noun,
+    axis: Noun,
+) -> result::Result<Either<Noun, (Noun, Noun)>, Error> {
+    // Axis into core must be atom
+    let mut formula = core.slot_atom(axis.as_atom()?).or(BAIL_EXIT)?;
+    if !cfg!(feature = "sham_hints") {
+        if let Some((jet, _path)) =
+            context
+                .warm
+                .find_jet(&mut context.stack, &mut core, &mut formula)
+        {
+            match jet(context, core) {
+                Ok(jet_res) => return Ok(Left(jet_res)),
+                Err(JetErr::Punt) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+    Ok(Right((core, formula)))
+}
+
+/** Trace a call into a core, as [interpret] does for Nock 9 */
+unsafe fn trace(context: &mut Context, core: &mut Noun) {
+    if context.traces_calls() {
+        if let Some(path) = context.cold.matches(&mut context.stack, core) {
+            append_trace(&mut context.stack, path);
+        }
+    }
+}
+
+/** Push a NockStack frame for a call, saving the running frame on it to return to */
+unsafe fn call(context: &mut Context, frame: &mut Frame, depth: &mut usize, dest: usize) {
+    frame.dest = dest;
+    mean_frame_push(&mut context.stack, 0);
+    *context.stack.push::<Frame>() = *frame;
+    frame.slots_len = 0;
+    *depth += 1;
+}
+
+/** Evaluate a formula as bytecode, in the frame [interpret] pushed for it */
+pub(crate) unsafe fn run(context: &mut Context, subject: Noun, mut formula: Noun) -> Result {
+    let terminator = Arc::clone(&TERMINATOR);
+    let timeout = Arc::clone(&TIMEOUT);
+    let mut frame = Frame {
+        program: program(context, &mut formula).select(&mut context.stack, subject),
+        pc: 0,
+        regs: [D(0); REGS],
+        slots: std::ptr::null_mut(),
+        slots_len: 0,
+        dest: 0,
+    };
+    frame.enter(&mut context.stack, frame.program, subject);
+    //  calls in progress, each in a frame above the one interpret pushed
+    let mut depth = 0usize;
+
+    loop {
+        let code = frame.program.code();
+        let consts = frame.program.consts();
+        let pc = frame.pc;
+        let byte = *code.add(pc);
+        let op = byte & 0x1f;
+        let a = (byte >> 5) as usize;
+        let mut res = match op {
+            IMM => {
+                frame.regs[a] = *consts.add(konst(code, pc + 1));
+                frame.pc += 5;
+                continue;
+            }
+            MOV => {
+                let (b, _) = operands(code, pc);
+                frame.regs[a] = frame.regs[b];
+                frame.pc += 2;
+                continue;
+            }
+            INC => {
+                let (b, _) = operands(code, pc);
+                let Ok(atom) = frame.regs[b].as_atom() else {
+                    // Cannot increment (Nock 4) a cell
+                    return BAIL_EXIT;
+                };
+                frame.regs[a] = inc(&mut context.stack, atom).as_noun();
+                frame.pc += 2;
+                continue;
+            }
+            UNC => {
+                let (b, _) = operands(code, pc);
+                let atom = frame.regs[b].as_atom().unwrap_unchecked();
+                frame.regs[a] = inc(&mut context.stack, atom).as_noun();
+                frame.pc += 2;
+                continue;
+            }
+            CON => {
+                let (b, c) = operands(code, pc);
+                frame.regs[a] =
+                    Cell::new(&mut context.stack, frame.regs[b], frame.regs[c]).as_noun();
+                frame.pc += 2;
+                continue;
+            }
+            HED | TAL => {
+                let (b, _) = operands(code, pc);
+                let Ok(cell) = frame.regs[b].as_cell() else {
+                    // Axis invalid for input Noun
+                    return BAIL_EXIT;
+                };
+                frame.regs[a] = if op == HED { cell.head() } else { cell.tail() };
+                frame.pc += 2;
+                continue;
+            }
+            HUD | TUL => {
+                let (b, _) = operands(code, pc);
+                let cell = frame.regs[b].as_cell().unwrap_unchecked();
+                frame.regs[a] = if op == HUD { cell.head() } else { cell.tail() };
+                frame.pc += 2;
+                continue;
+            }
+            CLQ => {
+                let mut pc = pc + 1;
+                let yes = label(code, &mut pc);
+                let no = label(code, &mut pc);
+                frame.pc = pc + if frame.regs[a].is_cell() { yes } else { no };
+                continue;
+            }
+            EQQ => {
+                let (b, _) = operands(code, pc);
+                let mut pc = pc + 2;
+                let yes = label(code, &mut pc);
+                let no = label(code, &mut pc);
+                let regs = frame.regs.as_mut_ptr();
+                let equal = unifying_equality(&mut context.stack, regs.add(a), regs.add(b));
+                frame.pc = pc + if equal { yes } else { no };
+                continue;
+            }
+            BRN => {
+                let mut pc = pc + 1;
+                let zero = label(code, &mut pc);
+                let once = label(code, &mut pc);
+                frame.pc = pc
+                    + match frame.regs[a].direct().map(|direct| direct.data()) {
+                        Some(0) => zero,
+                        Some(1) => once,
+                        // Test branch of Nock 6 must return 0 or 1
+                        _ => return BAIL_EXIT,
+                    };
+                continue;
+            }
+            HOP => {
+                let mut pc = pc + 1;
+                let offset = label(code, &mut pc);
+                frame.pc = pc + offset;
+                continue;
+            }
+            CAL => {
+                if (*terminator).load(Ordering::Relaxed) || (*timeout).load(Ordering::Relaxed) {
+                    return BAIL_INTR;
+                }
+                profile::poll(context);
+                let (b, _) = operands(code, pc);
+                let axis = *consts.add(konst(code, pc + 2));
+                frame.pc += 6;
+                match arm(context, frame.regs[b], axis)? {
+                    Left(jet_res) => frame.regs[a] = jet_res,
+                    Right((mut core, mut formula)) => {
+                        let program = program(context, &mut formula);
+                        let program = heat(context, program, core, formula)?;
+                        call(context, &mut frame, &mut depth, a);
+                        trace(context, &mut core);
+                        frame.enter(&mut context.stack, program, core);
+                    }
+                }
+                continue;
+            }
+            JMP => {
+                if (*terminator).load(Ordering::Relaxed) || (*timeout).load(Ordering::Relaxed) {
+                    return BAIL_INTR;
+                }
+                profile::poll(context);
+                let axis = *consts.add(konst(code, pc + 1));
+                match arm(context, frame.regs[a], axis)? {
+                    Left(jet_res) => jet_res,
+                    Right((mut core, mut formula)) => {
+                        trace(context, &mut core);
+                        let program = program(context, &mut formula);
+                        let program = heat(context, program, core, formula)?;
+                        frame.enter(&mut context.stack, program, core);
+                        continue;
+                    }
+                }
+            }
+            LNK => {
+                if (*terminator).load(Ordering::Relaxed) || (*timeout).load(Ordering::Relaxed) {
+                    return BAIL_INTR;
+                }
+                profile::poll(context);
+                let (b, c) = operands(code, pc);
+                let program = program(context, &mut frame.regs[c]);
+                let subject = frame.regs[b];
+                let program = heat(context, program, subject, frame.regs[c])?;
+                frame.pc += 2;
+                call(context, &mut frame, &mut depth, a);
+                frame.enter(&mut context.stack, program, subject);
+                continue;
+            }
+            LNT => {
+                if (*terminator).load(Ordering::Relaxed) || (*timeout).load(Ordering::Relaxed) {
+                    return BAIL_INTR;
+                }
+                profile::poll(context);
+                let (b, _) = operands(code, pc);
+                let program = program(context, &mut frame.regs[b]);
+                let subject = frame.regs[a];
+                let program = heat(context, program, subject, frame.regs[b])?;
+                frame.enter(&mut context.stack, program, subject);
+                continue;
+            }
+            SPY => {
+                let (b, c) = operands(code, pc);
+                frame.regs[a] = scry(context, frame.regs[b], frame.regs[c])?;
+                frame.pc += 2;
+                continue;
+            }
+            HNT => {
+                let byte = *code.add(pc + 1);
+                let (s, c, phase) = ((byte & 7) as usize, (byte >> 3 & 7) as usize, byte >> 6);
+                let arg = (*consts.add(konst(code, pc + 2)))
+                    .as_cell()
+                    .unwrap_unchecked();
+                let mut pc = pc + 6;
+                let (tag, form) = match arg.head().as_either_atom_cell() {
+                    Left(tag) => (tag, None),
+                    Right(hint) => (hint.head().as_atom().unwrap_unchecked(), Some(hint.tail())),
+                };
+                let body = arg.tail();
+                let subject = frame.regs[s];
+                let found = match phase {
+                    PRE => {
+                        hint::match_pre_hint(context, subject, tag, form.unwrap_unchecked(), body)
+                    }
+                    NOCK => {
+                        let clue = form.map(|form| (form, frame.regs[c]));
+                        hint::match_pre_nock(context, subject, tag, clue, body)
+                    }
+                    _ => {
+                        let clue = form.map(|_| frame.regs[c]);
+                        if let Some(found) =
+                            hint::match_post_nock(context, subject, tag, clue, body, frame.regs[a])
+                        {
+                            frame.regs[a] = found;
+                        }
+                        frame.pc = pc;
+                        continue;
+                    }
+                };
+                let offset = label(code, &mut pc);
+                frame.pc = pc;
+                if let Some(found) = found {
+                    frame.regs[a] = found?;
+                    frame.pc += offset;
+                }
+                continue;
+            }
+            BOM => {
+                // Invalid formula
+                return BAIL_EXIT;
+            }
+            DON => frame.regs[a],
+            LOD => {
+                frame.regs[a] = *frame.slots.add(slot(code, pc + 1));
+                frame.pc += 3;
+                continue;
+            }
+            STO => {
+                *frame.slots.add(slot(code, pc + 1)) = frame.regs[a];
+                frame.pc += 3;
+                continue;
+            }
+            _ => unreachable!("bytecode: invalid opcode {}", op),
+        };
+
+        //  read before preserving, which reuses the lightweight stack
+        let caller = if depth > 0 {
+            Some(*context.stack.top::<Frame>())
+        } else {
+            None
+        };
+
+        write_trace(context);
+
+        let stack = &mut context.stack;
+        debug_assertions(stack, res);
+
+        stack.preserve(&mut context.cache);
+        stack.preserve(&mut context.cold);
+        stack.preserve(&mut context.warm);
+        stack.preserve(&mut context.programs);
+        stack.preserve(&mut context.codegen);
+        stack.preserve(&mut res);
+        stack.frame_pop();
+
+        debug_assertions(stack, res);
+
+        let Some(caller) = caller else {
+            return Ok(res);
+        };
+        depth -= 1;
+        frame = caller;
+        frame.regs[frame.dest] = res;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::{interpret, Mote};
+    use crate::jets::util::test::init_context;
+    use crate::noun::T;
+    use crate::serialization::{cue_file, jam};
+    use ares_macros::tas;
+    use std::fs::read_dir;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_interrupt_links() {
+        let context = &mut init_context();
+        context.bytecode = true;
+        let timeout = Arc::clone(&TIMEOUT);
+
+        //  [2 [0 1] [0 1]] on itself tail calls itself forever, and [[2 [0 1] [0 1]] 1 0] on
+        //  itself recurses until the NockStack is exhausted
+        let axis = T(&mut context.stack, &[D(0), D(1)]);
+        let tail = T(&mut context.stack, &[D(2), axis, axis]);
+        let recur = T(&mut context.stack, &[tail, D(1), D(0)]);
+        for formula in [tail, recur] {
+            (*timeout).store(true, Ordering::Relaxed);
+            let res = interpret(context, formula, formula);
+            (*timeout).store(false, Ordering::Relaxed);
+            assert!(matches!(res, Err(Error::NonDeterministic(Mote::Intr, _))));
+        }
+    }
+
+    #[test]
+    fn test_programs_kept() {
+        let context = &mut init_context();
+        context.bytecode = true;
+
+        //  [4 0 1], increment the subject
+        let axis = T(&mut context.stack, &[D(0), D(1)]);
+        let mut formula = T(&mut context.stack, &[D(4), axis]);
+        let res = interpret(context, D(41), formula);
+        assert!(matches!(res, Ok(product) if unsafe { product.raw_equals(D(42)) }));
+
+        //  the memo cache is emptied every event, but the program is kept until relocation
+        context.cache_reset();
+        assert!(context
+            .programs
+            .lookup(&mut context.stack, &mut formula)
+            .is_some());
+        context.programs_reset();
+        assert!(context
+            .programs
+            .lookup(&mut context.stack, &mut formula)
+            .is_none());
+
+        //  once full, the programs are dropped rather than grown
+        context.programs_max = 1;
+        interpret(context, D(41), formula).unwrap();
+        let mut other = T(&mut context.stack, &[D(4), D(4), axis]);
+        interpret(context, D(40), other).unwrap();
+        assert_eq!(context.programs_len, 1);
+        assert!(context
+            .programs
+            .lookup(&mut context.stack, &mut formula)
+            .is_none());
+        assert!(context
+            .programs
+            .lookup(&mut context.stack, &mut other)
+            .is_some());
+    }
+
+    fn block(bend: Germ, body: Vec<Bran>) -> Block {
+        Block {
+            label: D(0),
+            body,
+            bend,
+        }
+    }
+
+    fn arm(blocks: Vec<Block>) -> Arm {
+        Arm {
+            says: D(0),
+            blocks,
+            vent: 0,
+            dole: 0,
+            uses: Vec::new(),
+            lump: 0,
+        }
+    }
+
+    #[test]
+    fn test_lowered() {
+        let context = &mut init_context();
+        context.bytecode = true;
+        let stack = &mut context.stack;
+
+        //  the formula [0 3] gives the payload of a core whose battery is [[4 0 3] 0], and the
+        //  lowered arm calls the arm at axis 4 of the core on its payload, giving it one more
+        let formula = T(stack, &[D(0), D(3)]);
+        let inc = T(stack, &[D(4), formula]);
+        let battery = T(stack, &[inc, D(0)]);
+        let toss = T(stack, &[D(tas!(b"toss")), D(0)]);
+        let know = T(stack, &[D(tas!(b"know")), battery]);
+        let sock = T(stack, &[D(tas!(b"bets")), know, toss]);
+        let barn = T(stack, &[sock, inc]);
+        let core = T(stack, &[battery, D(41)]);
+        let other = T(stack, &[D(0), D(0)]);
+        let other = T(stack, &[other, D(41)]);
+
+        let called = arm(vec![
+            block(
+                Germ::Cal(barn, vec![(D(3), 1)], 2, 1),
+                vec![Bran::Tal(0, 1)],
+            ),
+            block(Germ::Don(2), vec![]),
+        ]);
+        let program = lower(stack, &called).unwrap();
+        //  the battery is known, so the call goes through the arm's axis in the core
+        let code = unsafe { std::slice::from_raw_parts(program.code(), (*program.0).len) };
+        assert!(code.iter().any(|byte| byte & 0x1f == CAL));
+        unsafe { install(context, formula, sock, program) };
+
+        let res = interpret(context, core, formula).unwrap();
+        assert!(unsafe { res.raw_equals(D(42)) });
+        let res = interpret(context, other, formula).unwrap();
+        assert!(unsafe { res.raw_equals(D(41)) });
+    }
+
+    #[test]
+    fn test_not_lowered() {
+        let context = &mut init_context();
+        let stack = &mut context.stack;
+        let hint = T(stack, &[D(tas!(b"hi")), D(0)]);
+
+        let loops = arm(vec![block(Germ::Hop(0), vec![])]);
+        assert!(lower(stack, &loops).is_none());
+        let undefined = arm(vec![
+            block(Germ::Clq(0, 1, 2), vec![]),
+            block(Germ::Hop(2), vec![Bran::Hed(0, 1)]),
+            block(Germ::Don(1), vec![]),
+        ]);
+        assert!(lower(stack, &undefined).is_none());
+        let hinted = arm(vec![
+            block(Germ::Hnt(1, 1), vec![Bran::Imm(hint, 1)]),
+            block(Germ::Don(0), vec![]),
+        ]);
+        assert!(lower(stack, &hinted).is_none());
+        let defined = arm(vec![
+            block(Germ::Clq(0, 1, 2), vec![]),
+            block(Germ::Hop(3), vec![Bran::Hed(0, 1)]),
+            block(Germ::Hop(3), vec![Bran::Imm(D(0), 1)]),
+            block(Germ::Don(1), vec![]),
+        ]);
+        assert!(lower(stack, &defined).is_some());
+    }
+
+    /// Builds the subject and formula of a case on a context's NockStack
+    type Case = fn(&mut NockStack) -> (Noun, Noun);
+
+    /** What a run came to, jammed so that runs in different contexts can be compared */
+    #[derive(Debug, PartialEq)]
+    enum Outcome {
+        Product(Vec<u8>),
+        Bail(&'static str, u64, Vec<u8>),
+        ScryBlocked(Vec<u8>),
+        ScryCrashed(Vec<u8>),
+    }
+
+    /** A scry gate which produces `[~ ~ 42]` for the path `%ok`, `[~ ~]` for `%none`, and `~`
+     * for `%block`, and crashes on any other path */
+    fn scry_gate(stack: &mut NockStack) -> Noun {
+        let path = T(stack, &[D(0), D(13)]);
+        let is = |stack: &mut NockStack, name| {
+            let name = T(stack, &[D(1), D(name)]);
+            T(stack, &[D(5), name, path])
+        };
+        let ok = is(stack, tas!(b"ok"));
+        let none = is(stack, tas!(b"none"));
+        let block = is(stack, tas!(b"block"));
+        let found = T(stack, &[D(1), D(0), D(0), D(42)]);
+        let empty = T(stack, &[D(1), D(0), D(0)]);
+        let blocked = T(stack, &[D(1), D(0)]);
+        let crash = T(stack, &[D(0), D(0)]);
+        let battery = T(stack, &[D(6), block, blocked, crash]);
+        let battery = T(stack, &[D(6), none, empty, battery]);
+        let battery = T(stack, &[D(6), ok, found, battery]);
+        T(stack, &[battery, D(0), D(0)])
+    }
+
+    /** Run a case with the bytecode VM or the tree walker, in a context of its own */
+    fn run<F: Fn(&mut NockStack) -> (Noun, Noun)>(case: &F, bytecode: bool) -> Outcome {
+        let context = &mut init_context();
+        context.bytecode = bytecode;
+        let gate = scry_gate(&mut context.stack);
+        context.scry_stack = T(&mut context.stack, &[gate, D(0)]);
+
+        let (subject, formula) = case(&mut context.stack);
+        let res = interpret(context, subject, formula);
+        let stack = &mut context.stack;
+        let mut jammed = |noun| jam(stack, noun).as_bytes().to_vec();
+        match res {
+            Ok(product) => Outcome::Product(jammed(product)),
+            Err(Error::Deterministic(mote, trace)) => {
+                Outcome::Bail("deterministic", mote as u64, jammed(trace))
+            }
+            Err(Error::NonDeterministic(Mote::Meme, _)) => {
+                //  where the NockStack runs out, and so the trace, depends on how each uses it
+                Outcome::Bail("nondeterministic", Mote::Meme as u64, Vec::new())
+            }
+            Err(Error::NonDeterministic(mote, trace)) => {
+                Outcome::Bail("nondeterministic", mote as u64, jammed(trace))
+            }
+            Err(Error::ScryBlocked(path)) => Outcome::ScryBlocked(jammed(path)),
+            Err(Error::ScryCrashed(trace)) => Outcome::ScryCrashed(jammed(trace)),
+        }
+    }
+
+    /** Run a case both ways, and check that they come to the same thing */
+    fn differ<F: Fn(&mut NockStack) -> (Noun, Noun)>(name: &str, case: F) -> Outcome {
+        let tree = run(&case, false);
+        let code = run(&case, true);
+        assert_eq!(
+            tree, code,
+            "{}: bytecode differs from the tree walker",
+            name
+        );
+        code
+    }
+
+    /// Cases in `resources/jam` which take minutes without their jets, for [test_slow_jam_cases]
+    const SLOW_JAM_CASES: &[&str] = &["shax.jam"];
+
+    /** Run the cases in `resources/jam` which are, or are not, slow */
+    fn jam_cases(slow: bool) {
+        let dir = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../resources/jam"));
+        let mut paths: Vec<PathBuf> = read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                SLOW_JAM_CASES.contains(&name) == slow
+            })
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+        for path in paths {
+            differ(&path.display().to_string(), |stack| {
+                let cell = cue_file(stack, &path).unwrap().as_cell().unwrap();
+                (cell.head(), cell.tail())
+            });
+        }
+    }
+
+    #[test]
+    fn test_jam_cases() {
+        jam_cases(false);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_slow_jam_cases() {
+        jam_cases(true);
+    }
+
+    #[test]
+    fn test_hints() {
+        let cases: &[(&str, Case)] = &[
+            ("static", |stack| {
+                (D(0), T(stack, &[D(11), D(tas!(b"foo")), D(1), D(42)]))
+            }),
+            ("slog", |stack| {
+                let tank = T(stack, &[D(1), D(0), D(tas!(b"leaf"))]);
+                let hint = T(stack, &[D(tas!(b"slog")), tank]);
+                (D(0), T(stack, &[D(11), hint, D(1), D(42)]))
+            }),
+            ("memo", |stack| {
+                let clue = T(stack, &[D(1), D(0)]);
+                let hint = T(stack, &[D(tas!(b"memo")), clue]);
+                let inc = T(stack, &[D(4), D(0), D(1)]);
+                let memo = T(stack, &[D(11), hint, inc]);
+                (D(41), T(stack, &[memo, memo]))
+            }),
+            ("bout", |stack| {
+                let clue = T(stack, &[D(1), D(0)]);
+                let hint = T(stack, &[D(tas!(b"bout")), clue]);
+                (D(0), T(stack, &[D(11), hint, D(1), D(42)]))
+            }),
+            ("crashed clue", |stack| {
+                let clue = T(stack, &[D(0), D(0)]);
+                let hint = T(stack, &[D(tas!(b"slog")), clue]);
+                (D(0), T(stack, &[D(11), hint, D(1), D(42)]))
+            }),
+            ("mean", |stack| {
+                let clue = T(stack, &[D(1), D(tas!(b"leaf")), D(tas!(b"boom"))]);
+                let hint = T(stack, &[D(tas!(b"mean")), clue]);
+                (D(0), T(stack, &[D(11), hint, D(0), D(0)]))
+            }),
+            ("spot in a call", |stack| {
+                //  [11 [%spot 1 %here] 0 0] as an arm, called under a %mean
+                let spot = T(stack, &[D(1), D(tas!(b"here"))]);
+                let spot = T(stack, &[D(tas!(b"spot")), spot]);
+                let arm = T(stack, &[D(11), spot, D(0), D(0)]);
+                let mean = T(stack, &[D(1), D(tas!(b"call"))]);
+                let mean = T(stack, &[D(tas!(b"mean")), mean]);
+                let call = T(stack, &[D(11), mean, D(9), D(2), D(0), D(1)]);
+                (T(stack, &[arm, D(0)]), call)
+            }),
+            ("popped mean", |stack| {
+                let clue = T(stack, &[D(1), D(tas!(b"gone"))]);
+                let hint = T(stack, &[D(tas!(b"mean")), clue]);
+                let done = T(stack, &[D(11), hint, D(1), D(0)]);
+                (D(0), T(stack, &[D(7), done, D(0), D(0)]))
+            }),
+            ("hunk, lose and hand", |stack| {
+                let mut formula = T(stack, &[D(0), D(0)]);
+                for tag in [tas!(b"hunk"), tas!(b"lose"), tas!(b"hand")] {
+                    let clue = T(stack, &[D(1), D(tag)]);
+                    let hint = T(stack, &[D(tag), clue]);
+                    formula = T(stack, &[D(11), hint, formula]);
+                }
+                (D(0), formula)
+            }),
+        ];
+        for (name, case) in cases {
+            differ(name, *case);
+        }
+    }
+
+    #[test]
+    fn test_scry() {
+        let cases: &[(&str, Case)] = &[
+            ("found", |stack| {
+                let path = T(stack, &[D(1), D(tas!(b"ok"))]);
+                let reff = T(stack, &[D(1), D(0)]);
+                (D(0), T(stack, &[D(12), reff, path]))
+            }),
+            ("empty", |stack| {
+                let path = T(stack, &[D(1), D(tas!(b"none"))]);
+                let reff = T(stack, &[D(1), D(0)]);
+                (D(0), T(stack, &[D(12), reff, path]))
+            }),
+            ("blocked", |stack| {
+                let path = T(stack, &[D(1), D(tas!(b"block"))]);
+                let reff = T(stack, &[D(1), D(0)]);
+                (D(0), T(stack, &[D(12), reff, path]))
+            }),
+            ("crashed", |stack| {
+                let path = T(stack, &[D(1), D(tas!(b"crash"))]);
+                let reff = T(stack, &[D(1), D(0)]);
+                (D(0), T(stack, &[D(12), reff, path]))
+            }),
+            ("under a mean", |stack| {
+                let path = T(stack, &[D(1), D(tas!(b"none"))]);
+                let clue = T(stack, &[D(1), D(tas!(b"scry"))]);
+                let hint = T(stack, &[D(tas!(b"mean")), clue]);
+                let reff = T(stack, &[D(1), D(0)]);
+                let scry = T(stack, &[D(12), reff, path]);
+                (D(0), T(stack, &[D(11), hint, scry]))
+            }),
+        ];
+        let found = differ(cases[0].0, cases[0].1);
+        assert_eq!(found, run(&|stack| (D(0), T(stack, &[D(1), D(42)])), false));
+        for (name, case) in &cases[1..] {
+            differ(name, *case);
+        }
+    }
+
+    /** A gate registered as `dec` in the hot state, as the kernel's is, with `battery`, and a
+     * formula building it */
+    fn dec_gate(stack: &mut NockStack, battery: Noun) -> Noun {
+        let fast = |stack: &mut NockStack, chum, parent| {
+            let clue = T(stack, &[D(1), chum, parent, D(0)]);
+            T(stack, &[D(tas!(b"fast")), clue])
+        };
+        //  the root core, %k.139
+        let chum = T(stack, &[D(tas!(b"k")), D(139)]);
+        let parent = T(stack, &[D(1), D(0)]);
+        let hint = fast(stack, chum, parent);
+        let root = T(stack, &[D(1), D(0), D(139)]);
+        let root = T(stack, &[D(11), hint, root]);
+        //  %one, whose parent is its payload
+        let parent = T(stack, &[D(0), D(3)]);
+        let hint = fast(stack, D(tas!(b"one")), parent);
+        let one = T(stack, &[D(1), D(1)]);
+        let one = T(stack, &[D(11), hint, one, root]);
+        //  %dec, whose parent is its context
+        let parent = T(stack, &[D(0), D(7)]);
+        let hint = fast(stack, D(tas!(b"dec")), parent);
+        let battery = T(stack, &[D(1), battery]);
+        let sample = T(stack, &[D(1), D(0)]);
+        T(stack, &[D(11), hint, battery, sample, one])
+    }
+
+    /** Slam the gate [dec_gate] builds with a sample */
+    fn slam_dec(stack: &mut NockStack, battery: Noun, sample: u64) -> Noun {
+        let gate = dec_gate(stack, battery);
+        let sample = T(stack, &[D(6), D(1), D(sample)]);
+        T(stack, &[D(8), gate, D(9), D(2), D(10), sample, D(0), D(2)])
+    }
+
+    #[test]
+    fn test_jets() {
+        let cases: &[(&str, Case)] = &[
+            //  the battery is not dec, so only the jet produces 4
+            ("dec", |stack| {
+                let battery = T(stack, &[D(1), D(tas!(b"nock"))]);
+                (D(0), slam_dec(stack, battery, 5))
+            }),
+            ("dec crashes", |stack| {
+                let battery = T(stack, &[D(1), D(tas!(b"nock"))]);
+                (D(0), slam_dec(stack, battery, 0))
+            }),
+            ("dec under a spot", |stack| {
+                let battery = T(stack, &[D(1), D(tas!(b"nock"))]);
+                let slam = slam_dec(stack, battery, 0);
+                let clue = T(stack, &[D(1), D(tas!(b"dec"))]);
+                let hint = T(stack, &[D(tas!(b"spot")), clue]);
+                (D(0), T(stack, &[D(11), hint, slam]))
+            }),
+        ];
+        let dec = differ(cases[0].0, cases[0].1);
+        assert_eq!(dec, run(&|stack| (D(0), T(stack, &[D(1), D(4)])), false));
+        for (name, case) in &cases[1..] {
+            differ(name, *case);
+        }
+    }
+
+    #[test]
+    fn test_crashes() {
+        let cases: &[(&str, Case)] = &[
+            ("axis 0", |stack| (D(0), T(stack, &[D(0), D(0)]))),
+            ("axis into an atom", |stack| (D(7), T(stack, &[D(0), D(4)]))),
+            ("bad branch", |stack| {
+                let test = T(stack, &[D(1), D(2)]);
+                let yes = T(stack, &[D(1), D(0)]);
+                let no = T(stack, &[D(1), D(1)]);
+                (D(0), T(stack, &[D(6), test, yes, no]))
+            }),
+            ("increment a cell", |stack| {
+                let cell = T(stack, &[D(1), D(1), D(2)]);
+                (D(0), T(stack, &[D(4), cell]))
+            }),
+            ("arm of an atom", |stack| {
+                let atom = T(stack, &[D(1), D(0)]);
+                (D(0), T(stack, &[D(9), D(2), atom]))
+            }),
+            ("edit an atom", |stack| {
+                let value = T(stack, &[D(1), D(5)]);
+                let target = T(stack, &[D(1), D(0)]);
+                let edit = T(stack, &[D(2), value]);
+                (D(0), T(stack, &[D(10), edit, target]))
+            }),
+            ("edit axis 0", |stack| {
+                let value = T(stack, &[D(1), D(5)]);
+                let edit = T(stack, &[D(0), value]);
+                (D(0), T(stack, &[D(10), edit, D(0), D(1)]))
+            }),
+            ("bad opcode", |stack| (D(0), T(stack, &[D(13), D(0)]))),
+            ("formula is an atom", |stack| {
+                let subject = T(stack, &[D(1), D(0)]);
+                let formula = T(stack, &[D(1), D(7)]);
+                (D(0), T(stack, &[D(2), subject, formula]))
+            }),
+            ("stack overflow", |stack| {
+                //  [[2 [0 1] [0 1]] 1 0] on itself recurses without end
+                let axis = T(stack, &[D(0), D(1)]);
+                let recur = T(stack, &[D(2), axis, axis]);
+                let formula = T(stack, &[recur, D(1), D(0)]);
+                (formula, formula)
+            }),
+        ];
+        for (name, case) in cases {
+            differ(name, *case);
+        }
+    }
+}
@@ -0,0 +1,572 @@
+/** Codegen: run the Hoon code generator of `hoon/codegen` from the runtime
+ *
+ * The code generator is a jammed gate, built from `hoon/codegen/lib/bootstrap.hoon`. It takes the
+ * code table built so far (a `town`, in `sur/gene.hoon`) and an arm (a `barn`: what is known of
+ * its subject, and its formula), and produces the subject knowledge analysis of the arm and the
+ * code table with the arm's linearized IR added. The gate is run by [interpret] like any other
+ * Nock, and [line] decodes the IR of the arm into an [Arm], which [bytecode::lower] lowers to a
+ * program for the bytecode VM.
+ *
+ * The serf loads the gate from Arvo at [SCRY_SPUR] where that is bound, or else from the file
+ * `ARES_CODEGEN` names. An arm is linearized once the bytecode VM has called its formula
+ * `ARES_CODEGEN_HOT` times, with the battery of the core it was called on known. The code table
+ * is kept across events, but not saved in the PMA.
+ */
+use crate::bytecode;
+use crate::flog;
+use crate::interpreter::{interpret, Context, Error};
+use crate::mem::{NockStack, Preserve};
+use crate::noun::{Noun, D, T};
+use crate::serialization::cue_file;
+use crate::unifying_equality::unifying_equality;
+use ares_macros::tas;
+use assert_no_alloc::permit_alloc;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::result;
+
+crate::gdb!();
+
+/// Path in the %base desk at which Arvo may bind an upgraded codegen gate, jammed
+pub const SCRY_SPUR: [u64; 3] = [tas!(b"lib"), tas!(b"codegen"), tas!(b"jam")];
+
+/** The code generator, and the code table it has built */
+#[derive(Copy, Clone)]
+pub struct Codegen {
+    /// The codegen gate, or 0 if there is none
+    gate: Noun,
+    /// The `town` built so far
+    town: Noun,
+    /// Calls of a formula after which its arm is linearized, or 0 for never
+    pub hot: usize,
+}
+
+impl Codegen {
+    /// No code generator, so nothing is linearized
+    pub fn none() -> Codegen {
+        Codegen {
+            gate: D(0),
+            town: D(0),
+            hot: 0,
+        }
+    }
+
+    /// A code generator with an empty code table
+    pub fn new(stack: &mut NockStack, gate: Noun, hot: usize) -> Codegen {
+        let town = T(stack, &[D(0), D(0)]);
+        Codegen { gate, town, hot }
+    }
+
+    /// Load a jammed codegen gate from a file
+    pub fn load(stack: &mut NockStack, path: &Path, hot: usize) -> io::Result<Codegen> {
+        let gate = cue_file(stack, path)?;
+        if !is_gate(gate) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("codegen: {} is not a gate", path.display()),
+            ));
+        }
+        Ok(Codegen::new(stack, gate, hot))
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.gate.is_cell()
+    }
+}
+
+impl Preserve for Codegen {
+    unsafe fn assert_in_stack(&self, stack: &NockStack) {
+        self.gate.assert_in_stack(stack);
+        self.town.assert_in_stack(stack);
+    }
+
+    unsafe fn preserve(&mut self, stack: &mut NockStack) {
+        stack.preserve(&mut self.gate);
+        stack.preserve(&mut self.town);
+    }
+}
+
+/** Whether a noun is shaped like a gate, `[battery sample context]` */
+pub fn is_gate(noun: Noun) -> bool {
+    noun.as_cell().is_ok_and(|gate| gate.tail().is_cell())
+}
+
+/** Why an arm was not linearized */
+pub enum LineErr {
+    /// The code generator crashed
+    Nock(Error),
+    /// It produced something other than a code table holding the arm
+    Malformed,
+}
+
+impl From<Error> for LineErr {
+    fn from(e: Error) -> Self {
+        LineErr::Nock(e)
+    }
+}
+
+impl From<crate::noun::Error> for LineErr {
+    fn from(_: crate::noun::Error) -> Self {
+        LineErr::Malformed
+    }
+}
+
+impl fmt::Display for LineErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineErr::Nock(Error::Deterministic(mote, _) | Error::NonDeterministic(mote, _)) => {
+                write!(f, "crashed: {:?}", mote)
+            }
+            LineErr::Nock(Error::ScryBlocked(_) | Error::ScryCrashed(_)) => {
+                write!(f, "crashed in a scry")
+            }
+            LineErr::Malformed => write!(f, "produced a malformed code table"),
+        }
+    }
+}
+
+/** Linearize an arm with the code generator, given a `sock` of what is known of its subject,
+ * and decode its IR from the code table. Formulas are not counted toward linearizing them while
+ * the code generator runs, so it never linearizes its own arms.
+ */
+pub fn line(context: &mut Context, sock: Noun, formula: Noun) -> result::Result<Arm, LineErr> {
+    assert!(context.codegen.is_on(), "codegen: no code generator");
+    let gate = context.codegen.gate.as_cell()?;
+    let stack = &mut context.stack;
+    let barn = T(stack, &[sock, formula]);
+    let sample = T(stack, &[context.codegen.town, barn]);
+    let core = T(stack, &[gate.head(), sample, gate.tail().as_cell()?.tail()]);
+    let kick = T(stack, &[D(9), D(2), D(0), D(1)]);
+
+    let hot = std::mem::replace(&mut context.codegen.hot, 0);
+    let res = interpret(context, core, kick);
+    context.codegen.hot = hot;
+
+    let product = res?.as_cell()?;
+    let town = product.tail();
+    context.codegen.town = town;
+    let land = town.as_cell()?.head();
+    permit_alloc(|| {
+        let sack = map_get(&mut context.stack, land, barn)?
+            .ok_or(LineErr::Malformed)?
+            .as_cell()?;
+        Arm::decode(&mut context.stack, land, sack.head(), sack.tail())
+    })
+}
+
+/** Linearize a formula which the bytecode VM has found hot, with the battery of the core it is
+ * called on known, and lower its IR to a program the VM runs in place of the formula's own when
+ * the subject has that battery. Arms which can't be lowered are left as they were. If the code
+ * generator crashes, or its code table is malformed, it is switched off rather than failing the
+ * event; only an interrupt or running out of memory is an error.
+ */
+pub fn hot(context: &mut Context, subject: Noun, formula: Noun) -> result::Result<(), Error> {
+    let stack = &mut context.stack;
+    let toss = T(stack, &[D(tas!(b"toss")), D(0)]);
+    let sock = match subject.as_cell() {
+        Ok(core) => {
+            let know = T(stack, &[D(tas!(b"know")), core.head()]);
+            T(stack, &[D(tas!(b"bets")), know, toss])
+        }
+        Err(_) => toss,
+    };
+    match line(context, sock, formula) {
+        Ok(arm) => {
+            if let Some(program) = bytecode::lower(&mut context.stack, &arm) {
+                unsafe { bytecode::install(context, formula, sock, program) };
+            }
+            permit_alloc(|| drop(arm));
+            Ok(())
+        }
+        Err(LineErr::Nock(e @ Error::NonDeterministic(..))) => Err(e),
+        Err(e) => {
+            context.codegen = Codegen::none();
+            permit_alloc(|| {
+                flog!(context, "\r codegen: {}; switched off", e);
+            });
+            Ok(())
+        }
+    }
+}
+
+/** The value for a key in a Hoon `map`. The key is compared with every entry, rather than found
+ * by the mug order of the treap, so that no mugs need be computed. */
+fn map_get(
+    stack: &mut NockStack,
+    map: Noun,
+    mut key: Noun,
+) -> result::Result<Option<Noun>, LineErr> {
+    for (mut k, v) in map_entries(map)? {
+        if unsafe { unifying_equality(stack, &mut k, &mut key) } {
+            return Ok(Some(v));
+        }
+    }
+    Ok(None)
+}
+
+/** The keys and values of a Hoon `map` */
+fn map_entries(map: Noun) -> result::Result<Vec<(Noun, Noun)>, LineErr> {
+    let mut entries = Vec::new();
+    let mut nodes = vec![map];
+    while let Some(node) = nodes.pop() {
+        if unsafe { node.raw_equals(D(0)) } {
+            continue;
+        }
+        let node = node.as_cell()?;
+        let pair = node.head().as_cell()?;
+        let branches = node.tail().as_cell()?;
+        entries.push((pair.head(), pair.tail()));
+        nodes.push(branches.tail());
+        nodes.push(branches.head());
+    }
+    Ok(entries)
+}
+
+/** The items of a Hoon `list` */
+fn list_items(mut list: Noun) -> result::Result<Vec<Noun>, LineErr> {
+    let mut items = Vec::new();
+    while let Ok(cell) = list.as_cell() {
+        items.push(cell.head());
+        list = cell.tail();
+    }
+    if unsafe { list.raw_equals(D(0)) } {
+        Ok(items)
+    } else {
+        Err(LineErr::Malformed)
+    }
+}
+
+/// An SSA value of the IR
+pub type Ssa = u64;
+
+fn ssa(noun: Noun) -> result::Result<Ssa, LineErr> {
+    Ok(noun.as_atom()?.as_u64()?)
+}
+
+fn loobean(noun: Noun) -> result::Result<bool, LineErr> {
+    match noun.as_atom()?.as_u64()? {
+        0 => Ok(true),
+        1 => Ok(false),
+        _ => Err(LineErr::Malformed),
+    }
+}
+
+/** The linearized IR of an arm, decoded from a `sack` of the code table */
+pub struct Arm {
+    /// What is known of the arm's product: a `boot`
+    pub says: Noun,
+    /// Basic blocks, which branches and jumps name by index
+    pub blocks: Vec<Block>,
+    /// The block entered with the parts of the subject in the SSA values given by [uses]
+    pub vent: usize,
+    /// The block entered with the whole subject in [lump]
+    pub dole: usize,
+    /// Each part of the subject the arm uses: its axis, SSA value, and whether that is safe
+    pub uses: Vec<(Noun, Ssa, bool)>,
+    /// The SSA value the whole subject is in, entering at [dole]
+    pub lump: Ssa,
+}
+
+/** A basic block: instructions, ended by a control transfer */
+pub struct Block {
+    /// The block's label, a `berm`: what is known of the subject, the formula, the axis of the
+    /// formula it was generated for, and a tag
+    pub label: Noun,
+    pub body: Vec<Bran>,
+    pub bend: Germ,
+}
+
+/** An instruction in a basic block, `bran` in `sur/gene.hoon` */
+pub enum Bran {
+    Imm(Noun, Ssa),
+    Mov(Ssa, Ssa),
+    Inc(Ssa, Ssa),
+    Unc(Ssa, Ssa),
+    Con(Ssa, Ssa, Ssa),
+    Hed(Ssa, Ssa),
+    Hud(Ssa, Ssa),
+    Tal(Ssa, Ssa),
+    Tul(Ssa, Ssa),
+}
+
+/** An instruction ending a basic block, `germ` in `sur/gene.hoon`. Blocks of the arm are given
+ * by index, and other arms by their `barn`. `lnk` and `lnt` take the formula before the subject,
+ * and the arguments of `cal` and `jmp` are paired with the axes of the callee's subject they are.
+ */
+pub enum Germ {
+    Clq(Ssa, usize, usize),
+    Eqq(Ssa, Ssa, usize, usize),
+    Brn(Ssa, usize, usize),
+    Hop(usize),
+    Lnk(Ssa, Ssa, Ssa, usize),
+    Cal(Noun, Vec<(Noun, Ssa)>, Ssa, usize),
+    Bec(Noun, Ssa, Ssa, usize),
+    Lnt(Ssa, Ssa),
+    Jmp(Noun, Vec<(Noun, Ssa)>),
+    Eye(Noun, Ssa),
+    Spy(Ssa, Ssa, Ssa, usize),
+    Hnt(Ssa, usize),
+    Don(Ssa),
+    Bom,
+}
+
+impl Arm {
+    /** Decode the `rice` and `boot` of an arm's entry in the `land` of the code table */
+    fn decode(
+        stack: &mut NockStack,
+        land: Noun,
+        rice: Noun,
+        says: Noun,
+    ) -> result::Result<Arm, LineErr> {
+        let rice = rice.as_cell()?;
+        let rest = rice.tail().as_cell()?;
+        let lake = map_entries(rice.head())?;
+        let mut labels: Vec<Noun> = lake.iter().map(|(label, _)| *label).collect();
+
+        let mut blocks = Vec::with_capacity(lake.len());
+        for (label, lock) in lake {
+            let lock = lock.as_cell()?;
+            let body = list_items(lock.head())?
+                .into_iter()
+                .map(Bran::decode)
+                .collect::<result::Result<_, _>>()?;
+            let bend = Germ::decode(stack, land, &mut labels, lock.tail())?;
+            blocks.push(Block { label, body, bend });
+        }
+
+        let entry = |tag: u64| {
+            blocks
+                .iter()
+                .position(|block| block.tag() == Some(tag))
+                .ok_or(LineErr::Malformed)
+        };
+        let vent = entry(tas!(b"vent"))?;
+        let dole = entry(tas!(b"dole"))?;
+
+        Ok(Arm {
+            says,
+            blocks,
+            vent,
+            dole,
+            uses: pool(rest.head())?,
+            lump: ssa(rest.tail())?,
+        })
+    }
+}
+
+/** Decode the `pool` of the parts of the subject an arm uses */
+fn pool(pool: Noun) -> result::Result<Vec<(Noun, Ssa, bool)>, LineErr> {
+    let mut uses = Vec::new();
+    for part in list_items(pool)? {
+        let part = part.as_cell()?;
+        let rest = part.tail().as_cell()?;
+        uses.push((part.head(), ssa(rest.head())?, loobean(rest.tail())?));
+    }
+    Ok(uses)
+}
+
+/** The arguments of a call to another arm in the code table, each the SSA value of a part of the
+ * callee's subject, paired with the axis of that part */
+fn args(
+    stack: &mut NockStack,
+    land: Noun,
+    barn: Noun,
+    ssas: Noun,
+) -> result::Result<Vec<(Noun, Ssa)>, LineErr> {
+    let sack = map_get(stack, land, barn)?
+        .ok_or(LineErr::Malformed)?
+        .as_cell()?;
+    let uses = pool(sack.head().as_cell()?.tail().as_cell()?.head())?;
+    let ssas = list_items(ssas)?;
+    if uses.len() != ssas.len() {
+        return Err(LineErr::Malformed);
+    }
+    uses.into_iter()
+        .zip(ssas)
+        .map(|((axis, _, _), val)| Ok((axis, ssa(val)?)))
+        .collect()
+}
+
+impl Block {
+    /// The tag of the block's label, e.g. %vent
+    fn tag(&self) -> Option<u64> {
+        let mut label = self.label;
+        for _ in 0..3 {
+            label = label.as_cell().ok()?.tail();
+        }
+        label.as_atom().ok()?.as_u64().ok()
+    }
+}
+
+impl Bran {
+    fn decode(noun: Noun) -> result::Result<Bran, LineErr> {
+        let cell = noun.as_cell()?;
+        let args = cell.tail().as_cell()?;
+        let (x, rest) = (args.head(), args.tail());
+        let one = || -> result::Result<(Ssa, Ssa), LineErr> { Ok((ssa(x)?, ssa(rest)?)) };
+        Ok(match cell.head().as_direct()?.data() {
+            tas!(b"imm") => Bran::Imm(x, ssa(rest)?),
+            tas!(b"mov") => one().map(|(a, b)| Bran::Mov(a, b))?,
+            tas!(b"inc") => one().map(|(a, b)| Bran::Inc(a, b))?,
+            tas!(b"unc") => one().map(|(a, b)| Bran::Unc(a, b))?,
+            tas!(b"con") => {
+                let rest = rest.as_cell()?;
+                Bran::Con(ssa(x)?, ssa(rest.head())?, ssa(rest.tail())?)
+            }
+            tas!(b"hed") => one().map(|(a, b)| Bran::Hed(a, b))?,
+            tas!(b"hud") => one().map(|(a, b)| Bran::Hud(a, b))?,
+            tas!(b"tal") => one().map(|(a, b)| Bran::Tal(a, b))?,
+            tas!(b"tul") => one().map(|(a, b)| Bran::Tul(a, b))?,
+            _ => return Err(LineErr::Malformed),
+        })
+    }
+}
+
+impl Germ {
+    fn decode(
+        stack: &mut NockStack,
+        land: Noun,
+        labels: &mut [Noun],
+        noun: Noun,
+    ) -> result::Result<Germ, LineErr> {
+        let cell = noun.as_cell()?;
+        let tag = cell.head().as_direct()?.data();
+        //  the fields after the tag, the last one taking the rest of the noun
+        let mut rest = cell.tail();
+        let mut field = || -> result::Result<Noun, LineErr> {
+            let cell = rest.as_cell()?;
+            rest = cell.tail();
+            Ok(cell.head())
+        };
+        Ok(match tag {
+            tas!(b"clq") => Germ::Clq(ssa(field()?)?, block(stack, labels, field()?)?, {
+                block(stack, labels, rest)?
+            }),
+            tas!(b"eqq") => Germ::Eqq(
+                ssa(field()?)?,
+                ssa(field()?)?,
+                block(stack, labels, field()?)?,
+                block(stack, labels, rest)?,
+            ),
+            tas!(b"brn") => Germ::Brn(ssa(field()?)?, block(stack, labels, field()?)?, {
+                block(stack, labels, rest)?
+            }),
+            tas!(b"hop") => Germ::Hop(block(stack, labels, rest)?),
+            tas!(b"lnk") => Germ::Lnk(
+                ssa(field()?)?,
+                ssa(field()?)?,
+                ssa(field()?)?,
+                block(stack, labels, rest)?,
+            ),
+            tas!(b"cal") => {
+                let barn = field()?;
+                let args = args(stack, land, barn, field()?)?;
+                Germ::Cal(barn, args, ssa(field()?)?, block(stack, labels, rest)?)
+            }
+            tas!(b"bec") => Germ::Bec(
+                field()?,
+                ssa(field()?)?,
+                ssa(field()?)?,
+                block(stack, labels, rest)?,
+            ),
+            tas!(b"lnt") => Germ::Lnt(ssa(field()?)?, ssa(rest)?),
+            tas!(b"jmp") => {
+                let barn = field()?;
+                Germ::Jmp(barn, args(stack, land, barn, rest)?)
+            }
+            tas!(b"eye") => Germ::Eye(field()?, ssa(rest)?),
+            tas!(b"spy") => Germ::Spy(
+                ssa(field()?)?,
+                ssa(field()?)?,
+                ssa(field()?)?,
+                block(stack, labels, rest)?,
+            ),
+            tas!(b"hnt") => Germ::Hnt(ssa(field()?)?, block(stack, labels, rest)?),
+            tas!(b"don") => Germ::Don(ssa(rest)?),
+            tas!(b"bom") => Germ::Bom,
+            _ => return Err(LineErr::Malformed),
+        })
+    }
+}
+
+/** The index of the block a label names */
+fn block(
+    stack: &mut NockStack,
+    labels: &mut [Noun],
+    mut label: Noun,
+) -> result::Result<usize, LineErr> {
+    labels
+        .iter_mut()
+        .position(|other| unsafe { unifying_equality(stack, other, &mut label) })
+        .ok_or(LineErr::Malformed)
+}
+
+impl fmt::Display for Arm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "says: {}", self.says)?;
+        write!(f, "uses:")?;
+        for (axis, ssa, safe) in &self.uses {
+            write!(f, " {}=s{}{}", axis, ssa, if *safe { "" } else { "?" })?;
+        }
+        writeln!(f)?;
+        writeln!(f, "vent: b{}", self.vent)?;
+        writeln!(f, "dole: b{} with s{}", self.dole, self.lump)?;
+        for (i, block) in self.blocks.iter().enumerate() {
+            match block.tag() {
+                Some(tag) => writeln!(f, "b{} ({}):", i, D(tag))?,
+                None => writeln!(f, "b{}:", i)?,
+            }
+            for bran in &block.body {
+                writeln!(f, "  {}", bran)?;
+            }
+            writeln!(f, "  {}", block.bend)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Bran {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Bran::Imm(noun, d) => write!(f, "imm {} s{}", noun, d),
+            Bran::Mov(s, d) => write!(f, "mov s{} s{}", s, d),
+            Bran::Inc(s, d) => write!(f, "inc s{} s{}", s, d),
+            Bran::Unc(s, d) => write!(f, "unc s{} s{}", s, d),
+            Bran::Con(h, t, d) => write!(f, "con s{} s{} s{}", h, t, d),
+            Bran::Hed(s, d) => write!(f, "hed s{} s{}", s, d),
+            Bran::Hud(s, d) => write!(f, "hud s{} s{}", s, d),
+            Bran::Tal(s, d) => write!(f, "tal s{} s{}", s, d),
+            Bran::Tul(s, d) => write!(f, "tul s{} s{}", s, d),
+        }
+    }
+}
+
+impl fmt::Display for Germ {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ssas = |args: &[(Noun, Ssa)]| {
+            args.iter()
+                .map(|(axis, s)| format!("{}=s{}", axis, s))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match self {
+            Germ::Clq(s, y, n) => write!(f, "clq s{} b{} b{}", s, y, n),
+            Germ::Eqq(a, b, y, n) => write!(f, "eqq s{} s{} b{} b{}", a, b, y, n),
+            Germ::Brn(s, y, n) => write!(f, "brn s{} b{} b{}", s, y, n),
+            Germ::Hop(b) => write!(f, "hop b{}", b),
+            Germ::Lnk(fol, s, d, b) => write!(f, "lnk s{} s{} s{} b{}", fol, s, d, b),
+            Germ::Cal(barn, args, d, b) => {
+                write!(f, "cal {} [{}] s{} b{}", barn, ssas(args), d, b)
+            }
+            Germ::Bec(barn, s, d, b) => write!(f, "bec {} s{} s{} b{}", barn, s, d, b),
+            Germ::Lnt(fol, s) => write!(f, "lnt s{} s{}", fol, s),
+            Germ::Jmp(barn, args) => write!(f, "jmp {} [{}]", barn, ssas(args)),
+            Germ::Eye(barn, s) => write!(f, "eye {} s{}", barn, s),
+            Germ::Spy(r, p, d, b) => write!(f, "spy s{} s{} s{} b{}", r, p, d, b),
+            Germ::Hnt(s, b) => write!(f, "hnt s{} b{}", s, b),
+            Germ::Don(s) => write!(f, "don s{}", s),
+            Germ::Bom => write!(f, "bom"),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+/** Eval: run Nock without a king
+ *
+ * `ares eval <input> [<formula>] [--jam <output>] [--loom <bits>] [--bytecode]
+ *  [--line <codegen>] [--profile <dir>]`
+ *
+ * With one input file, it must contain a jammed [subject formula] cell (e.g. the files in
+ * `resources/jam`). With two, the first is the jammed subject and the second the jammed formula.
+ * The product is printed to stdout, or jammed to the output file if `--jam` is given. Timings,
+ * slogs and crash traces go to stderr. As in vere, `--loom` sets the size of the NockStack to
+ * 2^bits bytes. `--bytecode` compiles the formula to bytecode, rather than walking it as a
+ * tree.
+ *
+ * With `--line`, the formula is not run. It is linearized by the jammed codegen gate in the given
+ * file, with the subject known, and the IR printed instead.
+ *
+ * With `--profile`, the run is sampled as vere's `-P` samples events, and the samples written to
+ * the given directory as collapsed stacks.
+ */
+use crate::bytecode::Program;
+use crate::codegen::{self, Codegen, LineErr};
+use crate::hamt::Hamt;
+use crate::interpreter::{interpret, Context, Error};
+use crate::jets::cold::Cold;
+use crate::jets::hot::{Hot, HotEntry};
+use crate::jets::list::util::zing;
+use crate::jets::nock::util::mook;
+use crate::jets::warm::Warm;
+use crate::mem::NockStack;
+use crate::newt::Newt;
+use crate::noun::{Cell, Noun, D, T};
+use crate::profile::{create_profile, write_profile};
+use crate::serialization::{cue_file, jam_to_writer};
+use ares_macros::tas;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+crate::gdb!();
+
+const USAGE: &str =
+    "usage: ares eval <input> [<formula>] [--jam <output>] [--loom <bits>] [--bytecode] [--line <codegen>] [--profile <dir>]";
+
+/// Default NockStack size in bits of bytes, vere's default loom size
+const DEFAULT_LOOM_BITS: u32 = 31;
+
+/// Profiling samples taken per second of CPU time, with `--profile`
+const PROFILE_HZ: u32 = 1000;
+
+struct Args {
+    subject: PathBuf,
+    formula: Option<PathBuf>,
+    output: Option<PathBuf>,
+    loom_bits: u32,
+    /// Compile formulas to bytecode, rather than walking them as trees
+    bytecode: bool,
+    /// Jammed codegen gate to linearize the formula with, rather than running it
+    codegen: Option<PathBuf>,
+    /// Directory to write profiling samples of the run to
+    profile: Option<PathBuf>,
+}
+
+fn parse_args(args: &[String]) -> io::Result<Args> {
+    let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut loom_bits = DEFAULT_LOOM_BITS;
+    let mut bytecode = false;
+    let mut codegen = None;
+    let mut profile = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--jam" {
+            output = Some(PathBuf::from(iter.next().ok_or_else(usage)?));
+        } else if arg == "--loom" {
+            loom_bits = iter
+                .next()
+                .and_then(|bits| bits.parse().ok())
+                .filter(|bits| (20..48).contains(bits))
+                .ok_or_else(usage)?;
+        } else if arg == "--bytecode" {
+            bytecode = true;
+        } else if arg == "--line" {
+            codegen = Some(PathBuf::from(iter.next().ok_or_else(usage)?));
+        } else if arg == "--profile" {
+            profile = Some(PathBuf::from(iter.next().ok_or_else(usage)?));
+        } else {
+            inputs.push(PathBuf::from(arg));
+        }
+    }
+    let mut inputs = inputs.into_iter();
+    let subject = inputs.next().ok_or_else(usage)?;
+    let formula = inputs.next();
+    if inputs.next().is_some() {
+        return Err(usage());
+    }
+    Ok(Args {
+        subject,
+        formula,
+        output,
+        loom_bits,
+        bytecode,
+        codegen,
+        profile,
+    })
+}
+
+pub fn eval(args: &[String], constant_hot_state: &[HotEntry]) -> io::Result<()> {
+    let args = parse_args(args)?;
+
+    let mut stack = NockStack::new(1 << (args.loom_bits - 3), 0);
+    let newt = Newt::new_mock();
+    let cold = Cold::new(&mut stack);
+    let warm = Warm::new(&mut stack);
+    let hot = Hot::init(&mut stack, constant_hot_state);
+    let cache = Hamt::<Noun>::new(&mut stack);
+    let programs = Hamt::<Program>::new(&mut stack);
+    let mut context = Context {
+        stack,
+        newt,
+        cold,
+        warm,
+        hot,
+        cache,
+        cache_max: usize::MAX,
+        cache_len: 0,
+        scry_stack: D(0),
+        trace_info: None,
+        bytecode: args.bytecode,
+        programs,
+        programs_max: usize::MAX,
+        programs_len: 0,
+        codegen: Codegen::none(),
+        bouts: Vec::new(),
+        hela: None,
+        profile: None,
+    };
+
+    let start = Instant::now();
+    let (subject, formula) = match args.formula {
+        Some(formula_path) => {
+            let subject = cue_file(&mut context.stack, &args.subject)?;
+            let formula = cue_file(&mut context.stack, &formula_path)?;
+            (subject, formula)
+        }
+        None => {
+            let input = cue_file(&mut context.stack, &args.subject)?
+                .as_cell()
+                .map_err(|_e| {
+                    io::Error::new(io::ErrorKind::InvalidData, "eval: input is not a cell")
+                })?;
+            (input.head(), input.tail())
+        }
+    };
+    eprintln!("eval: cue: {:?}", start.elapsed());
+
+    if let Some(codegen_path) = args.codegen {
+        return line(&mut context, &codegen_path, subject, formula);
+    }
+
+    if let Some(profile_path) = args.profile {
+        context.profile = Some(create_profile(&profile_path, PROFILE_HZ)?);
+    }
+
+    let start = Instant::now();
+    let res = interpret(&mut context, subject, formula);
+    eprintln!("eval: nock: {:?}", start.elapsed());
+
+    if let Some(profile) = context.profile.as_mut() {
+        write_profile(profile)?;
+    }
+
+    match res {
+        Ok(product) => match args.output {
+            Some(output_path) => {
+                let start = Instant::now();
+                let mut file = File::create(output_path)?;
+                let bytes = jam_to_writer(&mut context.stack, product, &mut file)?;
+                eprintln!("eval: jam: {:?}, {} bytes", start.elapsed(), bytes);
+                Ok(())
+            }
+            None => {
+                println!("{}", product);
+                Ok(())
+            }
+        },
+        Err(Error::Deterministic(mote, traces) | Error::NonDeterministic(mote, traces)) => {
+            print_trace(&mut context, traces);
+            Err(io::Error::other(format!("eval: bail: {:?}", mote)))
+        }
+        Err(Error::ScryBlocked(_) | Error::ScryCrashed(_)) => {
+            Err(io::Error::other("eval: .^ invalid outside of virtual Nock"))
+        }
+    }
+}
+
+/** Linearize a formula with a codegen gate, the subject known, and print its IR */
+fn line(
+    context: &mut Context,
+    codegen_path: &Path,
+    subject: Noun,
+    formula: Noun,
+) -> io::Result<()> {
+    context.codegen = Codegen::load(&mut context.stack, codegen_path, 0)?;
+    let sock = T(&mut context.stack, &[D(tas!(b"know")), subject]);
+
+    let start = Instant::now();
+    let res = codegen::line(context, sock, formula);
+    eprintln!("eval: line: {:?}", start.elapsed());
+
+    match res {
+        Ok(arm) => {
+            print!("{}", arm);
+            Ok(())
+        }
+        Err(LineErr::Nock(
+            Error::Deterministic(mote, traces) | Error::NonDeterministic(mote, traces),
+        )) => {
+            print_trace(context, traces);
+            Err(io::Error::other(format!("eval: codegen bail: {:?}", mote)))
+        }
+        Err(e) => Err(io::Error::other(format!("eval: codegen {}", e))),
+    }
+}
+
+/** Render a crash trace with +mook and slog it, innermost frame last. */
+fn print_trace(context: &mut Context, traces: Noun) {
+    let Ok(trace) = zing(&mut context.stack, traces) else {
+        return;
+    };
+    let tone = Cell::new(&mut context.stack, D(2), trace);
+    let Ok(tang) = mook(context, tone, true) else {
+        eprintln!("eval: +mook crashed rendering the trace");
+        return;
+    };
+    let mut tang = tang.tail();
+    while let Ok(cell) = tang.as_cell() {
+        context.newt.slog(&mut context.stack, 0, cell.head());
+        tang = cell.tail();
+    }
+}
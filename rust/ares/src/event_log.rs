@@ -0,0 +1,496 @@
+/** An append-only log of the events ares runs, so a pier can be replayed without vere's LMDB
+ *
+ * The log is kept in segments, `$pier/.urb/log/ares-<N>.log` where `N` is the first event of the
+ * segment, each a sequence of records:
+ *
+ * ```text
+ * [sum: u32] [len: u64] [event: u64] [mug: u32] [jam: len bytes]
+ * ```
+ *
+ * `mug` is the mug of Arvo after the event, `jam` the jammed event, and `sum` the murmur3 hash of
+ * the rest of the record. Numbers are little-endian. A segment is appended to until it reaches the
+ * segment size, or until an event does not follow its last one; then a new segment is started.
+ *
+ * Records are only durable once [EventLog::sync] returns. A record torn by a crash, or which fails
+ * its checksum, ends the log, and is cut off when the log is opened.
+ */
+use murmur3::murmur3_32_of_slice;
+use std::convert::TryInto;
+use std::fs::{create_dir_all, read, read_dir, remove_file, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Size of a record's header: its checksum, length, event number, and mug
+const HEADER: usize = 24;
+
+/** An event read back from the log */
+pub struct Event {
+    pub event_num: u64,
+    /// Mug of Arvo after the event
+    pub mug: u32,
+    /// The jammed event
+    pub jam: Vec<u8>,
+}
+
+struct Segment {
+    first: u64,
+    path: PathBuf,
+}
+
+pub struct EventLog {
+    dir: PathBuf,
+    segment_bytes: u64,
+    /// Segments in order of their first events
+    segments: Vec<Segment>,
+    /// The last segment, open for appending, and its length
+    tail: Option<(File, u64)>,
+    /// The last event in the log, or 0 if it is empty
+    last: u64,
+}
+
+impl EventLog {
+    /** Open the event log of a pier, creating it if there is none. New segments are started once
+     * the last one holds `segment_bytes`. */
+    pub fn open(pier_path: &Path, segment_bytes: u64) -> io::Result<EventLog> {
+        let mut dir = pier_path.to_path_buf();
+        dir.push(".urb");
+        dir.push("log");
+        create_dir_all(&dir)?;
+
+        let mut segments = Vec::new();
+        for entry in read_dir(&dir)? {
+            let path = entry?.path();
+            let first = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("ares-")?.strip_suffix(".log"))
+                .and_then(|first| first.parse::<u64>().ok());
+            if let Some(first) = first {
+                segments.push(Segment { first, path });
+            }
+        }
+        segments.sort_by_key(|segment| segment.first);
+
+        let mut log = EventLog {
+            dir,
+            segment_bytes,
+            segments,
+            tail: None,
+            last: 0,
+        };
+
+        //  only the last segment can have been torn, and only it needs reading to find the end
+        log.cut(u64::MAX)?;
+        Ok(log)
+    }
+
+    /** The first event in the log, or 0 if it is empty */
+    pub fn first_event(&self) -> u64 {
+        match self.segments.first() {
+            Some(segment) if self.last >= segment.first => segment.first,
+            _ => 0,
+        }
+    }
+
+    /** The last event in the log, or 0 if it is empty */
+    pub fn last_event(&self) -> u64 {
+        self.last
+    }
+
+    /** Append an event, given the mug of Arvo after it. Events must be appended in order, but
+     * may skip ahead of the log, e.g. if it was not kept for a while. */
+    pub fn append(&mut self, event_num: u64, mug: u32, jam: &[u8]) -> io::Result<()> {
+        if event_num <= self.last {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "event log: event {} is not after the last, {}",
+                    event_num, self.last
+                ),
+            ));
+        }
+
+        let full = match self.tail {
+            Some((_, len)) => len >= self.segment_bytes,
+            None => true,
+        };
+        if full || event_num != self.last + 1 {
+            self.start_segment(event_num)?;
+        }
+
+        let mut record = vec![0; HEADER];
+        record[4..12].copy_from_slice(&(jam.len() as u64).to_le_bytes());
+        record[12..20].copy_from_slice(&event_num.to_le_bytes());
+        record[20..24].copy_from_slice(&mug.to_le_bytes());
+        record.extend_from_slice(jam);
+        let sum = murmur3_32_of_slice(&record[4..], 0);
+        record[0..4].copy_from_slice(&sum.to_le_bytes());
+
+        let (file, len) = self
+            .tail
+            .as_mut()
+            .expect("event log: no segment to append to");
+        file.write_all(&record)?;
+        *len += record.len() as u64;
+        self.last = event_num;
+        Ok(())
+    }
+
+    /** Make appended events durable */
+    pub fn sync(&mut self) -> io::Result<()> {
+        match self.tail {
+            Some((ref file, _)) => file.sync_data(),
+            None => Ok(()),
+        }
+    }
+
+    /** Remove every event after `event_num` */
+    pub fn truncate(&mut self, event_num: u64) -> io::Result<()> {
+        if event_num >= self.last {
+            return Ok(());
+        }
+        self.cut(event_num)
+    }
+
+    /** Read the events after `event_num`, in order */
+    pub fn events_after(&self, event_num: u64) -> Events<'_> {
+        //  the segment holding the next event, or the first after it
+        let segment = self
+            .segments
+            .iter()
+            .rposition(|segment| segment.first <= event_num + 1)
+            .unwrap_or(0);
+        Events {
+            log: self,
+            after: event_num,
+            segment,
+            bytes: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /** End the log at the last good record up to `event_num`, removing the segments left empty,
+     * and open its last segment for appending */
+    fn cut(&mut self, event_num: u64) -> io::Result<()> {
+        self.tail = None;
+        self.last = 0;
+        while let Some(segment) = self.segments.last() {
+            let bytes = if segment.first <= event_num {
+                read(&segment.path)?
+            } else {
+                Vec::new()
+            };
+            let (Some(last), len) = scan(&bytes, event_num) else {
+                remove_file(&segment.path)?;
+                self.segments.pop();
+                continue;
+            };
+
+            let file = OpenOptions::new().append(true).open(&segment.path)?;
+            if len < bytes.len() {
+                file.set_len(len as u64)?;
+                file.sync_all()?;
+            }
+            self.tail = Some((file, len as u64));
+            self.last = last;
+            break;
+        }
+        Ok(())
+    }
+
+    fn start_segment(&mut self, first: u64) -> io::Result<()> {
+        if let Some((file, _)) = self.tail.take() {
+            file.sync_data()?;
+        }
+        let mut path = self.dir.clone();
+        path.push(format!("ares-{:020}.log", first));
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&path)?;
+        self.segments.push(Segment { first, path });
+        self.tail = Some((file, 0));
+        Ok(())
+    }
+}
+
+/** Iterator over the events of a log, from [EventLog::events_after] */
+pub struct Events<'a> {
+    log: &'a EventLog,
+    after: u64,
+    /// Index of the segment being read
+    segment: usize,
+    /// Contents of that segment, once read
+    bytes: Vec<u8>,
+    offset: usize,
+}
+
+impl Iterator for Events<'_> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        loop {
+            if self.offset == 0 && self.bytes.is_empty() {
+                let segment = self.log.segments.get(self.segment)?;
+                match read(&segment.path) {
+                    Ok(bytes) if bytes.is_empty() => {
+                        self.segment += 1;
+                        continue;
+                    }
+                    Ok(bytes) => self.bytes = bytes,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+
+            let Some((event, size)) = record(&self.bytes[self.offset..]) else {
+                let path = &self.log.segments[self.segment].path;
+                return Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: bad record at byte {}", path.display(), self.offset),
+                )));
+            };
+            self.offset += size;
+            if self.offset == self.bytes.len() {
+                self.segment += 1;
+                self.bytes = Vec::new();
+                self.offset = 0;
+            }
+            if event.event_num > self.after {
+                return Some(Ok(event));
+            }
+        }
+    }
+}
+
+/** The last event of a segment's good records up to `event_num`, and their length */
+fn scan(bytes: &[u8], event_num: u64) -> (Option<u64>, usize) {
+    let mut last = None;
+    let mut offset = 0;
+    while let Some((event, size)) = record(&bytes[offset..]) {
+        if event.event_num > event_num || last.is_some_and(|last| event.event_num != last + 1) {
+            break;
+        }
+        last = Some(event.event_num);
+        offset += size;
+    }
+    (last, offset)
+}
+
+/** Parse the record at the start of `bytes`, and its size, if it is whole and its checksum
+ * matches */
+fn record(bytes: &[u8]) -> Option<(Event, usize)> {
+    let header = bytes.get(..HEADER)?;
+    let len: usize = u64::from_le_bytes(header[4..12].try_into().unwrap())
+        .try_into()
+        .ok()?;
+    let size = HEADER.checked_add(len)?;
+    let whole = bytes.get(..size)?;
+    if murmur3_32_of_slice(&whole[4..], 0) != u32::from_le_bytes(header[0..4].try_into().unwrap()) {
+        return None;
+    }
+    let event = Event {
+        event_num: u64::from_le_bytes(header[12..20].try_into().unwrap()),
+        mug: u32::from_le_bytes(header[20..24].try_into().unwrap()),
+        jam: whole[HEADER..].to_vec(),
+    };
+    Some((event, size))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::remove_dir_all;
+
+    /// Size of the records [append] writes
+    const RECORD: u64 = HEADER as u64 + 8;
+
+    /** An empty pier directory for a test */
+    fn pier(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ares-log-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path).unwrap();
+        path
+    }
+
+    /** Append events `first` to `last`, each jamming to its event number and mugging to
+     * `mug_base` plus it */
+    fn append(log: &mut EventLog, first: u64, last: u64, mug_base: u32) {
+        for event_num in first..=last {
+            log.append(
+                event_num,
+                mug_base + event_num as u32,
+                &event_num.to_le_bytes(),
+            )
+            .unwrap();
+        }
+        log.sync().unwrap();
+    }
+
+    /** The events after `event_num`, and their mugs */
+    fn events(log: &EventLog, event_num: u64) -> Vec<(u64, u32)> {
+        log.events_after(event_num)
+            .map(|event| {
+                let event = event.unwrap();
+                assert_eq!(event.jam, event.event_num.to_le_bytes());
+                (event.event_num, event.mug)
+            })
+            .collect()
+    }
+
+    /** The first events of the segments on disk */
+    fn segments(pier_path: &Path) -> Vec<u64> {
+        let mut firsts: Vec<u64> = read_dir(pier_path.join(".urb/log"))
+            .unwrap()
+            .map(|entry| {
+                let name = entry.unwrap().file_name().into_string().unwrap();
+                name["ares-".len()..name.len() - ".log".len()]
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        firsts.sort();
+        firsts
+    }
+
+    fn segment_path(pier_path: &Path, first: u64) -> PathBuf {
+        pier_path.join(format!(".urb/log/ares-{:020}.log", first))
+    }
+
+    #[test]
+    fn test_append_reopen() {
+        let path = pier("reopen");
+        let mut log = EventLog::open(&path, 1 << 20).unwrap();
+        assert_eq!((log.first_event(), log.last_event()), (0, 0));
+        assert!(events(&log, 0).is_empty());
+
+        append(&mut log, 1, 5, 0);
+        assert!(log.append(5, 0, &[]).is_err());
+        drop(log);
+
+        let mut log = EventLog::open(&path, 1 << 20).unwrap();
+        assert_eq!((log.first_event(), log.last_event()), (1, 5));
+        assert_eq!(
+            events(&log, 0),
+            (1..=5).map(|e| (e, e as u32)).collect::<Vec<_>>()
+        );
+        assert_eq!(events(&log, 3), vec![(4, 4), (5, 5)]);
+        assert!(events(&log, 5).is_empty());
+
+        append(&mut log, 6, 6, 0);
+        assert_eq!(segments(&path), vec![1]);
+        let _ = remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_torn_tail() {
+        let path = pier("torn");
+        append(&mut EventLog::open(&path, 1 << 20).unwrap(), 1, 5, 0);
+
+        //  a crash partway through writing the last record
+        let file = OpenOptions::new()
+            .write(true)
+            .open(segment_path(&path, 1))
+            .unwrap();
+        file.set_len(5 * RECORD - 3).unwrap();
+        drop(file);
+
+        let mut log = EventLog::open(&path, 1 << 20).unwrap();
+        assert_eq!(log.last_event(), 4);
+        assert_eq!(
+            std::fs::metadata(segment_path(&path, 1)).unwrap().len(),
+            4 * RECORD
+        );
+        append(&mut log, 5, 6, 100);
+        assert_eq!(events(&log, 3), vec![(4, 4), (5, 105), (6, 106)]);
+        let _ = remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        let path = pier("checksum");
+        append(&mut EventLog::open(&path, 1 << 20).unwrap(), 1, 5, 0);
+
+        //  corrupt the jam of event 4, which ends the log there
+        let mut bytes = read(segment_path(&path, 1)).unwrap();
+        bytes[(3 * RECORD) as usize + HEADER] ^= 1;
+        std::fs::write(segment_path(&path, 1), bytes).unwrap();
+
+        let log = EventLog::open(&path, 1 << 20).unwrap();
+        assert_eq!(log.last_event(), 3);
+        assert_eq!(events(&log, 0), vec![(1, 1), (2, 2), (3, 3)]);
+        let _ = remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let path = pier("rotate");
+        let mut log = EventLog::open(&path, 2 * RECORD).unwrap();
+        append(&mut log, 1, 5, 0);
+        assert_eq!(segments(&path), vec![1, 3, 5]);
+
+        //  skipping ahead starts a new segment too
+        append(&mut log, 10, 10, 0);
+        assert_eq!(segments(&path), vec![1, 3, 5, 10]);
+        drop(log);
+
+        let log = EventLog::open(&path, 2 * RECORD).unwrap();
+        assert_eq!((log.first_event(), log.last_event()), (1, 10));
+        assert_eq!(events(&log, 2), vec![(3, 3), (4, 4), (5, 5), (10, 10)]);
+        assert_eq!(events(&log, 5), vec![(10, 10)]);
+        let _ = remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_truncate_across_segments() {
+        let path = pier("truncate");
+        let mut log = EventLog::open(&path, 2 * RECORD).unwrap();
+        append(&mut log, 1, 7, 0);
+        assert_eq!(segments(&path), vec![1, 3, 5, 7]);
+
+        //  into the middle of a segment, removing those after it
+        log.truncate(3).unwrap();
+        assert_eq!(log.last_event(), 3);
+        assert_eq!(segments(&path), vec![1, 3]);
+        assert_eq!(
+            std::fs::metadata(segment_path(&path, 3)).unwrap().len(),
+            RECORD
+        );
+
+        //  to the end of a segment, leaving the next event to start another
+        log.truncate(2).unwrap();
+        assert_eq!(segments(&path), vec![1]);
+        append(&mut log, 3, 4, 100);
+        assert_eq!(segments(&path), vec![1, 3]);
+        drop(log);
+
+        let mut log = EventLog::open(&path, 2 * RECORD).unwrap();
+        assert_eq!(events(&log, 0), vec![(1, 1), (2, 2), (3, 103), (4, 104)]);
+
+        //  to before the log
+        log.truncate(0).unwrap();
+        assert_eq!((log.first_event(), log.last_event()), (0, 0));
+        assert!(segments(&path).is_empty());
+        let _ = remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_trim_after_rollback() {
+        let path = pier("rollback");
+        append(&mut EventLog::open(&path, 2 * RECORD).unwrap(), 1, 8, 0);
+
+        //  as the serf opens the log, once the state is rolled back to event 4
+        let mut log = EventLog::open(&path, 2 * RECORD).unwrap();
+        log.truncate(4).unwrap();
+        assert_eq!(log.last_event(), 4);
+        assert_eq!(segments(&path), vec![1, 3]);
+
+        //  the events after it are run again, with different results
+        append(&mut log, 5, 6, 100);
+        drop(log);
+        let log = EventLog::open(&path, 2 * RECORD).unwrap();
+        assert_eq!(log.last_event(), 6);
+        assert_eq!(events(&log, 3), vec![(4, 4), (5, 105), (6, 106)]);
+        let _ = remove_dir_all(&path);
+    }
+}
@@ -1,10 +1,30 @@
-use crate::interpreter::{Error, Mote, Result};
-use crate::noun::D;
+use crate::interpreter::Result;
 use ares_guard::*;
 use assert_no_alloc::permit_alloc;
 use std::ffi::c_void;
 use std::marker::PhantomData;
 
+/// The end of the NockStack which ran out of memory
+#[derive(Clone, Copy, Debug)]
+pub enum Side {
+    /// Stack frames, i.e. deep recursion
+    Stack,
+    /// Allocations, i.e. large or many nouns
+    Alloc,
+    /// There was no room left to begin with
+    Unknown,
+}
+
+impl From<u32> for Side {
+    fn from(value: u32) -> Self {
+        match value {
+            GUARD_SIDE_STACK => Self::Stack,
+            GUARD_SIDE_ALLOC => Self::Alloc,
+            _ => Self::Unknown,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum GuardError {
     MemoryProtection,
@@ -61,14 +81,18 @@ impl<'closure> CCallback<'closure> {
     }
 }
 
+/** Run a closure with a guard page between the NockStack's stack and alloc pointers. If it runs
+ * out of memory, the side of the NockStack which did is returned instead of its result.
+ */
 pub fn call_with_guard<F: FnMut() -> Result>(
     stack_pp: *const *const u64,
     alloc_pp: *const *const u64,
     closure: &mut F,
-) -> Result {
+) -> std::result::Result<Result, Side> {
     let cb = CCallback::new(closure);
     let mut ret_p: *mut c_void = std::ptr::null_mut();
     let ret_pp = &mut ret_p as *mut *mut c_void;
+    let mut side: u32 = GUARD_SIDE_NONE;
 
     unsafe {
         let res = guard(
@@ -77,17 +101,18 @@ pub fn call_with_guard<F: FnMut() -> Result>(
             stack_pp as *const usize,
             alloc_pp as *const usize,
             ret_pp,
+            &mut side,
         );
 
         if res == 0 {
             permit_alloc(|| {
                 let result_box = Box::from_raw(ret_p as *mut Result);
-                *result_box
+                Ok(*result_box)
             })
         } else {
             let err = GuardError::from(res);
             match err {
-                GuardError::OutOfMemory => Err(Error::NonDeterministic(Mote::Meme, D(0))),
+                GuardError::OutOfMemory => Err(Side::from(side)),
                 _ => {
                     panic!("serf: guard: unexpected error {:?} {}", err, res);
                 }
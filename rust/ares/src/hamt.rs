@@ -1,3 +1,4 @@
+use crate::mass::{measure_struct, Measure, Seen};
 use crate::mem::{NockStack, Preserve};
 use crate::mug::mug_u32;
 use crate::noun::Noun;
@@ -595,6 +596,35 @@ impl<T: Copy + Preserve> Preserve for Hamt<T> {
     }
 }
 
+impl<T: Copy + Measure> Measure for Hamt<T> {
+    unsafe fn measure(&self, seen: &mut Seen) -> usize {
+        let mut bytes = measure_struct(self.0, 1, seen);
+        let mut stems = vec![*self.0];
+        while let Some(stem) = stems.pop() {
+            if stem.buffer.is_null() || seen.contains(&(stem.buffer as usize)) {
+                continue;
+            }
+            bytes += measure_struct(stem.buffer, stem.size(), seen);
+            for chunk in 0..32 {
+                match stem.entry(chunk) {
+                    None => {}
+                    Some((Left(next_stem), _)) => stems.push(next_stem),
+                    Some((Right(leaf), _)) => {
+                        if leaf.buffer.is_null() || seen.contains(&(leaf.buffer as usize)) {
+                            continue;
+                        }
+                        bytes += measure_struct(leaf.buffer, leaf.len, seen);
+                        for (key, value) in leaf.to_mut_slice().iter() {
+                            bytes += key.measure(seen) + value.measure(seen);
+                        }
+                    }
+                }
+            }
+        }
+        bytes
+    }
+}
+
 impl<T: Copy + Persist> Persist for Hamt<T> {
     unsafe fn space_needed(&mut self, stack: &mut NockStack) -> usize {
         if pma_contains(self.0, 1) {
@@ -1,30 +1,34 @@
 use crate::assert_acyclic;
 use crate::assert_no_forwarding_pointers;
 use crate::assert_no_junior_pointers;
+use crate::bytecode::{self, Program};
+use crate::codegen::Codegen;
 use crate::flog;
-use crate::guard::call_with_guard;
+use crate::guard::{call_with_guard, Side};
 use crate::hamt::Hamt;
 use crate::jets::cold;
 use crate::jets::cold::Cold;
 use crate::jets::hot::Hot;
 use crate::jets::warm::Warm;
 use crate::jets::JetErr;
+use crate::mass::mass_fmt;
 use crate::mem::NockStack;
 use crate::mem::Preserve;
 use crate::newt::Newt;
 use crate::noun;
-use crate::noun::{Atom, Cell, IndirectAtom, Noun, Slots, D, T};
-use crate::serf::TERMINATOR;
+use crate::noun::{Atom, Cell, IndirectAtom, Noun, Slots, D, NO, T, YES};
+use crate::profile::{self, Profile};
+use crate::serf::{TERMINATOR, TIMEOUT};
 use crate::trace::{write_nock_trace, TraceInfo, TraceStack};
 use crate::unifying_equality::unifying_equality;
 use ares_macros::tas;
-use assert_no_alloc::{assert_no_alloc, ensure_alloc_counters};
+use assert_no_alloc::{assert_no_alloc, ensure_alloc_counters, permit_alloc};
 use bitvec::prelude::{BitSlice, Lsb0};
 use either::*;
 use std::result;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 crate::gdb!();
 
@@ -260,6 +264,11 @@ enum NockWork {
 pub struct ContextSnapshot {
     cold: Cold,
     warm: Warm,
+    programs: Hamt<Program>,
+    programs_len: usize,
+    codegen: Codegen,
+    bouts: usize,
+    hela: Option<Noun>,
 }
 
 pub struct Context {
@@ -269,8 +278,30 @@ pub struct Context {
     pub warm: Warm,
     pub hot: Hot,
     pub cache: Hamt<Noun>,
+    /// Most entries the memo cache takes before it is next reset; more are not memoized
+    pub cache_max: usize,
+    /// Entries inserted into the memo cache since it was last reset
+    pub cache_len: usize,
     pub scry_stack: Noun,
     pub trace_info: Option<TraceInfo>,
+    /// Run formulas as bytecode, rather than walking them as trees
+    pub bytecode: bool,
+    /// Bytecode compiled for formulas, kept across events until the nouns are relocated
+    pub programs: Hamt<Program>,
+    /// Most formulas compiled before the bytecode is dropped and compiled afresh
+    pub programs_max: usize,
+    /// Formulas compiled since the bytecode was last dropped
+    pub programs_len: usize,
+    /// The code generator which linearizes hot arms, if there is one
+    pub codegen: Codegen,
+    /// When each %bout hint being run started, innermost last
+    pub bouts: Vec<Instant>,
+    /// The mean stacks of the interpret calls the running one is nested in, innermost first, each
+    /// as [virtual mean] where virtual is whether the call nested in it virtualized. None when no
+    /// interpret call is running.
+    pub hela: Option<Noun>,
+    /// Samples of the stacks being run on each profiling timer tick, if profiling
+    pub profile: Option<Profile>,
 }
 
 impl Context {
@@ -278,17 +309,57 @@ impl Context {
         ContextSnapshot {
             cold: self.cold,
             warm: self.warm,
+            programs: self.programs,
+            programs_len: self.programs_len,
+            codegen: self.codegen,
+            bouts: self.bouts.len(),
+            hela: self.hela,
         }
     }
 
     pub fn restore(&mut self, saved: &ContextSnapshot) {
         self.cold = saved.cold;
         self.warm = saved.warm;
+        self.programs = saved.programs;
+        self.programs_len = saved.programs_len;
+        self.codegen = saved.codegen;
+        //  the %bouts a crash unwound never finish
+        self.bouts.truncate(saved.bouts);
+        self.hela = saved.hela;
+    }
+
+    /** Whether calls into %fast-hinted cores are pushed onto the trace stack */
+    pub fn traces_calls(&self) -> bool {
+        self.trace_info.is_some() || self.profile.is_some()
+    }
+
+    /** Memoize a product, unless the memo cache is full */
+    pub fn cache_insert(&mut self, key: &mut Noun, value: Noun) {
+        if self.cache_len < self.cache_max {
+            self.cache = self.cache.insert(&mut self.stack, key, value);
+            self.cache_len += 1;
+        }
+    }
+
+    /** Empty the memo cache */
+    pub fn cache_reset(&mut self) {
+        self.cache = Hamt::new(&mut self.stack);
+        self.cache_len = 0;
+    }
+
+    /** Drop the compiled bytecode, which must be done whenever the formulas it was compiled from
+     * may have moved, e.g. when the PMA is relocated or the NockStack replaced */
+    pub fn programs_reset(&mut self) {
+        self.programs = Hamt::new(&mut self.stack);
+        self.programs_len = 0;
     }
 
     /**
      * For jets that need a stack frame internally.
      *
+     * The frame carries the mean stack down like an interpreter frame, so that interpret calls
+     * made from inside it can link to it for %hela. Its locals start after the mean and trace.
+     *
      * This ensures that the frame is cleaned up even if the closure short-circuites to an error
      * result using e.g. the ? syntax. We need this method separately from with_frame to allow the
      * jet to use the entire context without the borrow checker complaining about the mutable
@@ -299,12 +370,14 @@ impl Context {
         F: FnOnce(&mut Context) -> O,
         O: Preserve,
     {
-        self.stack.frame_push(slots);
+        mean_frame_push(&mut self.stack, slots);
         let mut ret = f(self);
         ret.preserve(&mut self.stack);
         self.cache.preserve(&mut self.stack);
         self.cold.preserve(&mut self.stack);
         self.warm.preserve(&mut self.stack);
+        self.programs.preserve(&mut self.stack);
+        self.codegen.preserve(&mut self.stack);
         self.stack.frame_pop();
         ret
     }
@@ -316,6 +389,7 @@ pub enum Mote {
     Fail = tas!(b"fail") as isize,
     Intr = tas!(b"intr") as isize,
     Meme = tas!(b"meme") as isize,
+    Evil = tas!(b"evil") as isize, // decryption failure; bail without a %crud
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -360,20 +434,30 @@ impl From<cold::Error> for Error {
 
 pub type Result = result::Result<Noun, Error>;
 
-const BAIL_EXIT: Result = Err(Error::Deterministic(Mote::Exit, D(0)));
+pub(crate) const BAIL_EXIT: Result = Err(Error::Deterministic(Mote::Exit, D(0)));
 const BAIL_FAIL: Result = Err(Error::NonDeterministic(Mote::Fail, D(0)));
-const BAIL_INTR: Result = Err(Error::NonDeterministic(Mote::Intr, D(0)));
+pub(crate) const BAIL_INTR: Result = Err(Error::NonDeterministic(Mote::Intr, D(0)));
 
 #[allow(unused_variables)]
-fn debug_assertions(stack: &mut NockStack, noun: Noun) {
+pub(crate) fn debug_assertions(stack: &mut NockStack, noun: Noun) {
     assert_acyclic!(noun);
     assert_no_forwarding_pointers!(noun);
     assert_no_junior_pointers!(stack, noun);
 }
 
 /** Interpret nock */
-pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Result {
+pub fn interpret(context: &mut Context, subject: Noun, formula: Noun) -> Result {
+    nest(context, subject, formula, false)
+}
+
+/** Interpret nock as a new virtualization level, which a %nara trace stops at */
+pub fn interpret_virtual(context: &mut Context, subject: Noun, formula: Noun) -> Result {
+    nest(context, subject, formula, true)
+}
+
+fn nest(context: &mut Context, mut subject: Noun, formula: Noun, virt: bool) -> Result {
     let terminator = Arc::clone(&TERMINATOR);
+    let timeout = Arc::clone(&TIMEOUT);
     let orig_subject = subject; // for debugging
     let snapshot = context.save();
     let virtual_frame: *const u64 = context.stack.get_frame_pointer();
@@ -381,12 +465,30 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
 
     // Setup stack for Nock computation
     unsafe {
+        //  if nested, we were called from a frame carrying the mean and trace stacks of the
+        //  enclosing call
+        let outer = context
+            .hela
+            .map(|hela| (hela, *(context.stack.local_noun_pointer(0))));
+        let trace = match outer {
+            Some(_) => *(context.stack.local_noun_pointer(1) as *const *const TraceStack),
+            None => std::ptr::null(),
+        };
+
         context.stack.frame_push(2);
 
+        context.hela = Some(match outer {
+            Some((hela, mean)) => {
+                let link = T(&mut context.stack, &[if virt { YES } else { NO }, mean]);
+                T(&mut context.stack, &[link, hela])
+            }
+            None => D(0),
+        });
+
         // Bottom of mean stack
         *(context.stack.local_noun_pointer(0)) = D(0);
         // Bottom of trace stack
-        *(context.stack.local_noun_pointer(1) as *mut *const TraceStack) = std::ptr::null();
+        *(context.stack.local_noun_pointer(1) as *mut *const TraceStack) = trace;
 
         *(context.stack.push()) = NockWork::Done;
     };
@@ -407,6 +509,10 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
             let stack_pp = context.stack.get_stack_pointer_pointer() as *const *const u64;
             let alloc_pp = context.stack.get_alloc_pointer_pointer() as *const *const u64;
             let work_f = &mut || unsafe {
+                if context.bytecode {
+                    return bytecode::run(context, subject, formula);
+                }
+
                 push_formula(&mut context.stack, formula, true)?;
 
                 loop {
@@ -423,6 +529,8 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                             stack.preserve(&mut context.cache);
                             stack.preserve(&mut context.cold);
                             stack.preserve(&mut context.warm);
+                            stack.preserve(&mut context.programs);
+                            stack.preserve(&mut context.codegen);
                             stack.preserve(&mut res);
                             stack.frame_pop();
 
@@ -442,6 +550,8 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                             stack.preserve(&mut context.cache);
                             stack.preserve(&mut context.cold);
                             stack.preserve(&mut context.warm);
+                            stack.preserve(&mut context.programs);
+                            stack.preserve(&mut context.codegen);
                             stack.preserve(&mut res);
                             stack.frame_pop();
 
@@ -480,9 +590,12 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                             context.stack.pop::<NockWork>();
                         }
                         NockWork::Work2(mut vale) => {
-                            if (*terminator).load(Ordering::Relaxed) {
+                            if (*terminator).load(Ordering::Relaxed)
+                                || (*timeout).load(Ordering::Relaxed)
+                            {
                                 break BAIL_INTR;
                             }
+                            profile::poll(context);
 
                             match vale.todo {
                                 Todo2::ComputeSubject => {
@@ -653,9 +766,12 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                             }
                         },
                         NockWork::Work9(mut kale) => {
-                            if (*terminator).load(Ordering::Relaxed) {
+                            if (*terminator).load(Ordering::Relaxed)
+                                || (*timeout).load(Ordering::Relaxed)
+                            {
                                 break BAIL_INTR;
                             }
+                            profile::poll(context);
 
                             match kale.todo {
                                 Todo9::ComputeCore => {
@@ -685,6 +801,7 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                                             }
                                         };
 
+                                        let traces_calls = context.traces_calls();
                                         let stack = &mut context.stack;
                                         if kale.tail {
                                             stack.pop::<NockWork>();
@@ -692,7 +809,7 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                                             // We could trace on 2 as well, but 2 only comes from Hoon via
                                             // '.*', so we can assume it's never directly used to invoke
                                             // jetted code.
-                                            if context.trace_info.is_some() {
+                                            if traces_calls {
                                                 if let Some(path) =
                                                     context.cold.matches(stack, &mut res)
                                                 {
@@ -719,7 +836,7 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                                             // We could trace on 2 as well, but 2 only comes from Hoon via
                                             // '.*', so we can assume it's never directly used to invoke
                                             // jetted code.
-                                            if context.trace_info.is_some() {
+                                            if traces_calls {
                                                 if let Some(path) =
                                                     context.cold.matches(stack, &mut res)
                                                 {
@@ -763,7 +880,7 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                                         diet.axis.as_bitslice(),
                                         res,
                                         diet.tree,
-                                    );
+                                    )?;
                                     context.stack.pop::<NockWork>();
                                 }
                             }
@@ -877,78 +994,15 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                                 *stack.top() = NockWork::Work12(scry);
                                 push_formula(stack, scry.path, false)?;
                             }
-                            Todo12::Scry => {
-                                if let Some(cell) = context.scry_stack.cell() {
-                                    scry.path = res;
-                                    let scry_stack = context.scry_stack;
-                                    let scry_handler = cell.head();
-                                    let scry_gate = scry_handler.as_cell()?;
-                                    let payload = T(&mut context.stack, &[scry.reff, res]);
-                                    let scry_core = T(
-                                        &mut context.stack,
-                                        &[
-                                            scry_gate.head(),
-                                            payload,
-                                            scry_gate.tail().as_cell()?.tail(),
-                                        ],
-                                    );
-                                    let scry_form =
-                                        T(&mut context.stack, &[D(9), D(2), D(1), scry_core]);
-
-                                    context.scry_stack = cell.tail();
-                                    // Alternately, we could use scry_core as the subject and [9 2 0 1] as
-                                    // the formula. It's unclear if performance will be better with a purely
-                                    // static formula.
-                                    match interpret(context, D(0), scry_form) {
-                                        Ok(noun) => match noun.as_either_atom_cell() {
-                                            Left(atom) => {
-                                                if atom.as_noun().raw_equals(D(0)) {
-                                                    break Err(Error::ScryBlocked(scry.path));
-                                                } else {
-                                                    break Err(Error::ScryCrashed(D(0)));
-                                                }
-                                            }
-                                            Right(cell) => {
-                                                match cell.tail().as_either_atom_cell() {
-                                                    Left(_) => {
-                                                        let stack = &mut context.stack;
-                                                        let hunk = T(
-                                                            stack,
-                                                            &[
-                                                                D(tas!(b"hunk")),
-                                                                scry.reff,
-                                                                scry.path,
-                                                            ],
-                                                        );
-                                                        mean_push(stack, hunk);
-                                                        break Err(Error::ScryCrashed(D(0)));
-                                                    }
-                                                    Right(cell) => {
-                                                        res = cell.tail();
-                                                        context.scry_stack = scry_stack;
-                                                        context.stack.pop::<NockWork>();
-                                                    }
-                                                }
-                                            }
-                                        },
-                                        Err(error) => match error {
-                                            Error::Deterministic(_, trace)
-                                            | Error::ScryCrashed(trace) => {
-                                                break Err(Error::ScryCrashed(trace));
-                                            }
-                                            Error::NonDeterministic(_, _) => {
-                                                break Err(error);
-                                            }
-                                            Error::ScryBlocked(_) => {
-                                                break BAIL_FAIL;
-                                            }
-                                        },
-                                    }
-                                } else {
-                                    // No scry handler
-                                    break BAIL_EXIT;
+                            Todo12::Scry => match self::scry(context, scry.reff, res) {
+                                Ok(noun) => {
+                                    res = noun;
+                                    context.stack.pop::<NockWork>();
                                 }
-                            }
+                                Err(err) => {
+                                    break Err(err);
+                                }
+                            },
                         },
                     };
                 }
@@ -959,8 +1013,12 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
     });
 
     match nock {
-        Ok(res) => Ok(res),
-        Err(err) => Err(exit(context, &snapshot, virtual_frame, err)),
+        Ok(Ok(res)) => {
+            context.hela = snapshot.hela;
+            Ok(res)
+        }
+        Ok(Err(err)) => Err(exit(context, &snapshot, virtual_frame, err)),
+        Err(side) => Err(meme(context, &snapshot, virtual_frame, side)),
     }
 }
 
@@ -1177,6 +1235,63 @@ fn push_formula(stack: &mut NockStack, formula: Noun, tail: bool) -> Result {
     Ok(D(0))
 }
 
+/** Scry with the innermost scry handler, as Nock 12 */
+pub(crate) fn scry(context: &mut Context, reff: Noun, path: Noun) -> Result {
+    if let Some(cell) = context.scry_stack.cell() {
+        let scry_stack = context.scry_stack;
+        let scry_handler = cell.head();
+        let scry_gate = scry_handler.as_cell()?;
+        let payload = T(&mut context.stack, &[reff, path]);
+        let scry_core = T(
+            &mut context.stack,
+            &[
+                scry_gate.head(),
+                payload,
+                scry_gate.tail().as_cell()?.tail(),
+            ],
+        );
+        let scry_form = T(&mut context.stack, &[D(9), D(2), D(1), scry_core]);
+
+        context.scry_stack = cell.tail();
+        // Alternately, we could use scry_core as the subject and [9 2 0 1] as
+        // the formula. It's unclear if performance will be better with a purely
+        // static formula.
+        match interpret(context, D(0), scry_form) {
+            Ok(noun) => match noun.as_either_atom_cell() {
+                Left(atom) => {
+                    if unsafe { atom.as_noun().raw_equals(D(0)) } {
+                        Err(Error::ScryBlocked(path))
+                    } else {
+                        Err(Error::ScryCrashed(D(0)))
+                    }
+                }
+                Right(cell) => match cell.tail().as_either_atom_cell() {
+                    Left(_) => {
+                        let stack = &mut context.stack;
+                        let hunk = T(stack, &[D(tas!(b"hunk")), reff, path]);
+                        mean_push(stack, hunk);
+                        Err(Error::ScryCrashed(D(0)))
+                    }
+                    Right(cell) => {
+                        context.scry_stack = scry_stack;
+                        Ok(cell.tail())
+                    }
+                },
+            },
+            Err(error) => match error {
+                Error::Deterministic(_, trace) | Error::ScryCrashed(trace) => {
+                    Err(Error::ScryCrashed(trace))
+                }
+                Error::NonDeterministic(_, _) => Err(error),
+                Error::ScryBlocked(_) => BAIL_FAIL,
+            },
+        }
+    } else {
+        // No scry handler
+        BAIL_EXIT
+    }
+}
+
 fn exit(
     context: &mut Context,
     snapshot: &ContextSnapshot,
@@ -1217,14 +1332,62 @@ fn exit(
     }
 }
 
-/** Push frame onto NockStack while preserving the mean stack.
+/** Unwind after the NockStack ran out of memory, adding to the trace which side of it ran out,
+ * how many frames deep it was, and how much of it was in use.
+ */
+fn meme(
+    context: &mut Context,
+    snapshot: &ContextSnapshot,
+    virtual_frame: *const u64,
+    side: Side,
+) -> Error {
+    //  measure before unwinding, which frees what was in use
+    let depth = context.stack.frame_depth();
+    let used = (context.stack.size() - context.stack.free()) << 3;
+    let size = context.stack.size() << 3;
+    let error = exit(
+        context,
+        snapshot,
+        virtual_frame,
+        Error::NonDeterministic(Mote::Meme, D(0)),
+    );
+    let Error::NonDeterministic(_, trace) = error else {
+        unreachable!("exit changed the error");
+    };
+
+    let what = match side {
+        Side::Stack => "stack overflow",
+        Side::Alloc => "heap exhausted",
+        Side::Unknown => "out of memory",
+    };
+    let report = permit_alloc(|| {
+        flog::nock_fmt(
+            context,
+            format_args!(
+                "{}: {} frames deep, {} of the {} NockStack in use",
+                what,
+                depth,
+                mass_fmt(used),
+                mass_fmt(size)
+            ),
+        )
+    })
+    .expect("meme: could not format report");
+    let stack = &mut context.stack;
+    let mean = T(stack, &[D(tas!(b"mean")), report.as_noun()]);
+    let means = T(stack, &[mean, D(0)]);
+    Error::NonDeterministic(Mote::Meme, T(stack, &[means, trace]))
+}
+
+/** Push frame onto NockStack while preserving the mean and trace stacks.
  */
-fn mean_frame_push(stack: &mut NockStack, slots: usize) {
+pub(crate) fn mean_frame_push(stack: &mut NockStack, slots: usize) {
     unsafe {
-        let trace = *(stack.local_noun_pointer(0));
+        let mean = *(stack.local_noun_pointer(0));
+        let trace = *(stack.local_noun_pointer(1) as *const *const TraceStack);
         stack.frame_push(slots + 2);
-        *(stack.local_noun_pointer(0)) = trace;
-        *(stack.local_noun_pointer(1) as *mut *const TraceStack) = std::ptr::null();
+        *(stack.local_noun_pointer(0)) = mean;
+        *(stack.local_noun_pointer(1) as *mut *const TraceStack) = trace;
     }
 }
 
@@ -1249,17 +1412,75 @@ fn mean_pop(stack: &mut NockStack) {
     }
 }
 
+/** The entries of the mean stack, then those of the interpret calls this one is nested in, down
+ * to the root, or for %nara, down to the innermost virtualization level. Innermost first.
+ */
+pub(crate) struct MeanStacks {
+    mean: Noun,
+    hela: Noun,
+    nara: bool,
+}
+
+impl Iterator for MeanStacks {
+    type Item = Noun;
+
+    fn next(&mut self) -> Option<Noun> {
+        loop {
+            if let Ok(cell) = self.mean.as_cell() {
+                self.mean = cell.tail();
+                return Some(cell.head());
+            }
+
+            let link = self.hela.cell()?;
+            let outer = link.head().cell()?;
+            if self.nara && unsafe { outer.head().raw_equals(YES) } {
+                return None;
+            }
+            self.mean = outer.tail();
+            self.hela = link.tail();
+        }
+    }
+}
+
+pub(crate) fn mean_stacks(stack: &mut NockStack, hela: Option<Noun>, nara: bool) -> MeanStacks {
+    MeanStacks {
+        mean: unsafe { *(stack.local_noun_pointer(0)) },
+        hela: hela.unwrap_or(D(0)),
+        nara,
+    }
+}
+
+/** The mean stacks for %hela or %nara, welded into one */
+fn mean_hela(context: &mut Context, nara: bool) -> Noun {
+    unsafe {
+        let stack = &mut context.stack;
+        let mut res = D(0);
+        let mut dest = &mut res as *mut Noun;
+
+        for mean in mean_stacks(stack, context.hela, nara) {
+            let (new_cell, new_mem) = Cell::new_raw_mut(stack);
+            (*new_mem).head = mean;
+            *dest = new_cell.as_noun();
+            dest = &mut (*new_mem).tail;
+        }
+        *dest = D(0);
+
+        res
+    }
+}
+
+/** Replace the subtree of `tree` at an axis with `patch`, or crash if there is none */
 fn edit(
     stack: &mut NockStack,
     edit_axis: &BitSlice<u64, Lsb0>,
     patch: Noun,
     mut tree: Noun,
-) -> Noun {
+) -> Result {
     let mut res = patch;
     let mut dest: *mut Noun = &mut res;
-    let mut cursor = edit_axis
-        .last_one()
-        .expect("0 is not allowed as an edit axis");
+    let Some(mut cursor) = edit_axis.last_one() else {
+        return BAIL_EXIT;
+    };
     loop {
         if cursor == 0 {
             unsafe {
@@ -1287,10 +1508,10 @@ fn edit(
                 tree = tree_cell.head();
             }
         } else {
-            panic!("Invalid axis for edit");
+            return BAIL_EXIT;
         };
     }
-    res
+    Ok(res)
 }
 
 pub fn inc(stack: &mut NockStack, atom: Atom) -> Atom {
@@ -1320,13 +1541,14 @@ pub fn inc(stack: &mut NockStack, atom: Atom) -> Atom {
 }
 
 /// Push onto the tracing stack
-fn append_trace(stack: &mut NockStack, path: Noun) {
+pub(crate) fn append_trace(stack: &mut NockStack, path: Noun) {
     unsafe {
         let trace_stack = *(stack.local_noun_pointer(1) as *const *const TraceStack);
         let new_trace_entry = stack.struct_alloc(1);
         *new_trace_entry = TraceStack {
             path,
             start: Instant::now(),
+            frame: stack.get_frame_pointer(),
             next: trace_stack,
         };
         *(stack.local_noun_pointer(1) as *mut *const TraceStack) = new_trace_entry;
@@ -1334,12 +1556,13 @@ fn append_trace(stack: &mut NockStack, path: Noun) {
 }
 
 /// Write fast-hinted traces to trace file
-unsafe fn write_trace(context: &mut Context) {
+pub(crate) unsafe fn write_trace(context: &mut Context) {
     if let Some(ref mut info) = &mut context.trace_info {
         let trace_stack = *(context.stack.local_noun_pointer(1) as *mut *const TraceStack);
+        let frame = context.stack.get_frame_pointer();
         // Abort writing to trace file if we encountered an error. This should
         // result in a well-formed partial trace file.
-        if let Err(_e) = write_nock_trace(&mut context.stack, info, trace_stack) {
+        if let Err(_e) = write_nock_trace(&mut context.stack, info, trace_stack, frame) {
             flog!(
                 context,
                 "\rserf: error writing nock trace to file: {:?}",
@@ -1350,13 +1573,13 @@ unsafe fn write_trace(context: &mut Context) {
     }
 }
 
-mod hint {
+pub(crate) mod hint {
     use super::*;
     use crate::jets;
     use crate::jets::cold;
     use crate::jets::nock::util::{mook, LEAF};
     use crate::noun::{tape, Atom, Cell, Noun, D, T};
-    use crate::serf::TERMINATOR;
+    use crate::serf::{TERMINATOR, TIMEOUT};
     use crate::unifying_equality::unifying_equality;
     use ares_macros::tas;
     use std::sync::atomic::Ordering;
@@ -1369,6 +1592,7 @@ mod hint {
             Some(dtag) => match dtag.data() {
                 tas!(b"fast") => false,
                 tas!(b"memo") => false,
+                tas!(b"bout") => false,
                 _ => true,
             },
             None => true,
@@ -1482,11 +1706,14 @@ mod hint {
                 let pri = slog_cell.head().direct()?.data();
                 let tank = slog_cell.tail();
 
-                newt.slog(stack, pri, tank);
+                if !newt.quiet() {
+                    newt.slog(stack, pri, tank);
+                }
             }
             tas!(b"hand") | tas!(b"hunk") | tas!(b"lose") | tas!(b"mean") | tas!(b"spot") => {
                 let terminator = Arc::clone(&TERMINATOR);
-                if (*terminator).load(Ordering::Relaxed) {
+                let timeout = Arc::clone(&TIMEOUT);
+                if (*terminator).load(Ordering::Relaxed) || (*timeout).load(Ordering::Relaxed) {
                     return Some(BAIL_INTR);
                 }
 
@@ -1495,13 +1722,12 @@ mod hint {
                 let noun = T(stack, &[tag.as_noun(), clue]);
                 mean_push(stack, noun);
             }
-            tas!(b"hela") => {
-                //  XX: This only prints the trace down to the bottom of THIS
-                //      interpret call, making this neither a %nara nor a %hela
-                //      hint, as Vere understands them. We'll need to
-                //      recursively work down frames to get the stack trace all
-                //      the way to the root.
-                let mean = unsafe { *(context.stack.local_noun_pointer(0)) };
+            tas!(b"bout") => {
+                permit_alloc(|| context.bouts.push(Instant::now()));
+            }
+            tas!(b"hela") | tas!(b"nara") => {
+                let nara = tag.direct()?.data() == tas!(b"nara");
+                let mean = mean_hela(context, nara);
                 let tone = Cell::new(&mut context.stack, D(2), mean);
 
                 match mook(context, tone, true) {
@@ -1559,17 +1785,24 @@ mod hint {
         let newt = &mut context.newt;
         let cold = &mut context.cold;
         let hot = &context.hot;
-        let cache = &mut context.cache;
 
         //  XX: handle IndirectAtom tags
         match tag.direct()?.data() {
             tas!(b"memo") => {
                 let mut key = Cell::new(stack, subject, body).as_noun();
-                context.cache = cache.insert(stack, &mut key, res);
+                context.cache_insert(&mut key, res);
             }
             tas!(b"hand") | tas!(b"hunk") | tas!(b"lose") | tas!(b"mean") | tas!(b"spot") => {
                 mean_pop(stack);
             }
+            tas!(b"bout") => {
+                if let Some(start) = context.bouts.pop() {
+                    if !newt.quiet() {
+                        let tape = permit_alloc(|| tape(stack, &took(start.elapsed())));
+                        slog_leaf(stack, newt, tape);
+                    }
+                }
+            }
             tas!(b"fast") => {
                 if !cfg!(feature = "sham_hints") {
                     if let Some(clue) = hint {
@@ -1647,6 +1880,20 @@ mod hint {
         None
     }
 
+    /** The time a %bout took, as vere prints it: `took s/1.234.567`, `took ms/1.234` or
+     * `took µs/123` */
+    fn took(elapsed: Duration) -> String {
+        let micros = elapsed.as_micros();
+        let (sec, mil, mic) = (micros / 1_000_000, micros / 1000 % 1000, micros % 1000);
+        if sec > 0 {
+            format!("took s/{}.{:03}.{:03}", sec, mil, mic)
+        } else if mil > 0 {
+            format!("took ms/{}.{:03}", mil, mic)
+        } else {
+            format!("took \u{b5}s/{}", mic)
+        }
+    }
+
     fn slog_leaf(stack: &mut NockStack, newt: &mut Newt, tape: Noun) {
         let tank = T(stack, &[LEAF, tape]);
         newt.slog(stack, 0u64, tank);
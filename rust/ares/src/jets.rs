@@ -192,6 +192,7 @@ pub mod util {
 
     pub const BAIL_EXIT: JetErr = JetErr::Fail(Error::Deterministic(Mote::Exit, D(0)));
     pub const BAIL_FAIL: JetErr = JetErr::Fail(Error::NonDeterministic(Mote::Fail, D(0)));
+    pub const BAIL_EVIL: JetErr = JetErr::Fail(Error::NonDeterministic(Mote::Evil, D(0)));
 
     /**
      * Address-based size checks.
@@ -307,6 +308,8 @@ pub mod util {
 
     pub mod test {
         use super::*;
+        use crate::bytecode::Program;
+        use crate::codegen::Codegen;
         use crate::hamt::Hamt;
         use crate::mem::NockStack;
         use crate::noun::{Atom, Noun, D, T};
@@ -321,6 +324,7 @@ pub mod util {
             let warm = Warm::new(&mut stack);
             let hot = Hot::init(&mut stack, URBIT_HOT_STATE);
             let cache = Hamt::<Noun>::new(&mut stack);
+            let programs = Hamt::<Program>::new(&mut stack);
 
             Context {
                 stack,
@@ -329,8 +333,18 @@ pub mod util {
                 warm,
                 hot,
                 cache,
+                cache_max: usize::MAX,
+                cache_len: 0,
                 scry_stack: D(0),
                 trace_info: None,
+                bytecode: false,
+                programs,
+                programs_max: usize::MAX,
+                programs_len: 0,
+                codegen: Codegen::none(),
+                bouts: Vec::new(),
+                hela: None,
+                profile: None,
             }
         }
 
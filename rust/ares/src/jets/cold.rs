@@ -1,4 +1,5 @@
 use crate::hamt::Hamt;
+use crate::mass::{measure_struct, Measure, Seen};
 use crate::mem::{NockStack, Preserve};
 use crate::noun;
 use crate::noun::{Atom, DirectAtom, Noun, Slots, D, T};
@@ -144,6 +145,20 @@ impl Iterator for Batteries {
     }
 }
 
+impl Measure for Batteries {
+    unsafe fn measure(&self, seen: &mut Seen) -> usize {
+        let mut bytes = 0;
+        let mut batteries = *self;
+        while !batteries.0.is_null() && !seen.contains(&(batteries.0 as usize)) {
+            bytes += measure_struct(batteries.0, 1, seen);
+            bytes += (*batteries.0).battery.measure(seen);
+            bytes += (*batteries.0).parent_axis.as_noun().measure(seen);
+            batteries = (*batteries.0).parent_batteries;
+        }
+        bytes
+    }
+}
+
 impl Batteries {
     pub fn matches(self, stack: &mut NockStack, mut core: Noun) -> bool {
         let mut root_found: bool = false;
@@ -301,6 +316,19 @@ impl Iterator for BatteriesList {
     }
 }
 
+impl Measure for BatteriesList {
+    unsafe fn measure(&self, seen: &mut Seen) -> usize {
+        let mut bytes = 0;
+        let mut list = *self;
+        while !list.0.is_null() && !seen.contains(&(list.0 as usize)) {
+            bytes += measure_struct(list.0, 1, seen);
+            bytes += (*list.0).batteries.measure(seen);
+            list = (*list.0).next;
+        }
+        bytes
+    }
+}
+
 impl BatteriesList {
     fn matches(mut self, stack: &mut NockStack, core: Noun) -> Option<Batteries> {
         self.find(|&batteries| batteries.matches(stack, core))
@@ -424,6 +452,19 @@ impl Iterator for NounList {
     }
 }
 
+impl Measure for NounList {
+    unsafe fn measure(&self, seen: &mut Seen) -> usize {
+        let mut bytes = 0;
+        let mut list = *self;
+        while !list.0.is_null() && !seen.contains(&(list.0 as usize)) {
+            bytes += measure_struct(list.0, 1, seen);
+            bytes += (*list.0).element.measure(seen);
+            list = (*list.0).next;
+        }
+        bytes
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Cold(*mut ColdMem);
 
@@ -449,6 +490,16 @@ struct ColdMem {
     path_to_batteries: Hamt<BatteriesList>,
 }
 
+impl Measure for Cold {
+    unsafe fn measure(&self, seen: &mut Seen) -> usize {
+        let mut bytes = measure_struct(self.0, 1, seen);
+        bytes += (*self.0).battery_to_paths.measure(seen);
+        bytes += (*self.0).root_to_paths.measure(seen);
+        bytes += (*self.0).path_to_batteries.measure(seen);
+        bytes
+    }
+}
+
 impl Persist for Cold {
     unsafe fn space_needed(&mut self, stack: &mut NockStack) -> usize {
         if pma_contains(self.0, 1) {
@@ -116,7 +116,7 @@ pub fn jet_sivc_de(context: &mut Context, subject: Noun) -> Result {
 mod util {
     use crate::jets::bits::util::met;
     use crate::jets::list;
-    use crate::jets::util::BAIL_FAIL;
+    use crate::jets::util::{BAIL_EVIL, BAIL_FAIL};
     use crate::jets::{JetErr, Result};
     use crate::mem::NockStack;
     use crate::noun::{Atom, IndirectAtom, Noun, D, T};
@@ -233,15 +233,17 @@ mod util {
 
             let (mut out_atom, out_bytes) = IndirectAtom::new_raw_mut_bytes(stack, txt_len);
 
-            match txt_len {
-                0 => {
-                    ac_aes_siv_de::<N>(key, &mut [], siv_data, iv_bytes, &mut [0u8; 0]).unwrap();
-                }
+            let res = match txt_len {
+                0 => ac_aes_siv_de::<N>(key, &mut [], siv_data, iv_bytes, &mut [0u8; 0]),
                 _ => {
                     let (_txt_ida, txt_bytes) = IndirectAtom::new_raw_mut_bytes(stack, txt_len);
                     txt_bytes.copy_from_slice(&txt.as_bytes()[0..txt_len]);
-                    ac_aes_siv_de::<N>(key, txt_bytes, siv_data, iv_bytes, out_bytes).unwrap();
+                    ac_aes_siv_de::<N>(key, txt_bytes, siv_data, iv_bytes, out_bytes)
                 }
+            };
+            //  Bad ciphertext: bail out of the whole event, as vere does with %evil
+            if res.is_err() {
+                return Err(BAIL_EVIL);
             }
 
             Ok(T(stack, &[D(0), out_atom.normalize_as_atom().as_noun()]))
@@ -71,7 +71,7 @@ pub fn jet_mute(context: &mut Context, subject: Noun) -> Result {
 
 pub mod util {
     use crate::hamt::Hamt;
-    use crate::interpreter::{interpret, Context, Error, Mote};
+    use crate::interpreter::{interpret_virtual, Context, Error, Mote};
     use crate::jets;
     use crate::jets::bits::util::rip;
     use crate::jets::form::util::scow;
@@ -152,7 +152,7 @@ pub mod util {
         context.cache = Hamt::<Noun>::new(&mut context.stack);
         context.scry_stack = T(&mut context.stack, &[scry, context.scry_stack]);
 
-        match interpret(context, subject, formula) {
+        match interpret_virtual(context, subject, formula) {
             Ok(res) => {
                 context.cache = cache_snapshot;
                 context.scry_stack = scry_snapshot;
@@ -371,7 +371,9 @@ pub mod util {
                 list = cell.tail();
             }
 
-            *dest = D(0);
+            if !flop {
+                *dest = D(0);
+            }
             let toon = Cell::new(&mut context.stack, D(2), res);
             Ok(toon)
         }
@@ -406,16 +408,17 @@ pub mod util {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::jets::util::test::{assert_jet, init_context};
+    use crate::jets::util::test::{assert_jet, assert_noun_eq, init_context};
     use crate::mem::NockStack;
-    use crate::noun::{D, T};
-    use crate::serf::TERMINATOR;
+    use crate::noun::{tape, Cell, D, T};
+    use crate::serf::{TERMINATOR, TIMEOUT};
+    use ares_macros::tas;
     use std::sync::Arc;
 
     #[test]
     fn init() {
-        // This needs to be done because TERMINATOR is lazy allocated, and if you don't
-        // do it before you call the unit tests it'll get allocated on the Rust heap
+        // This needs to be done because TERMINATOR and TIMEOUT are lazy allocated, and if you
+        // don't do it before you call the unit tests it'll get allocated on the Rust heap
         // inside an assert_no_alloc block.
         //
         // Also Rust has no primitive for pre-test setup / post-test teardown, so we
@@ -423,6 +426,7 @@ mod tests {
         // since we're already using single-threaded test mode to avoid race conditions
         // (because Rust doesn't support test order dependencies either).
         let _ = Arc::clone(&TERMINATOR);
+        let _ = Arc::clone(&TIMEOUT);
     }
 
     #[test]
@@ -539,4 +543,26 @@ mod tests {
 
         assert_jet(context, jet_mink, samp, rest);
     }
+
+    #[test]
+    fn test_mook_flop() {
+        let context = &mut init_context();
+        let stack = &mut context.stack;
+
+        //  [%2 ~[[%mean 'one'] [%mean 'two']]]
+        let one = T(stack, &[D(tas!(b"mean")), D(tas!(b"one"))]);
+        let two = T(stack, &[D(tas!(b"mean")), D(tas!(b"two"))]);
+        let trace = T(stack, &[one, two, D(0)]);
+        let tone = Cell::new(stack, D(2), trace);
+
+        //  [%2 ~[leaf+"two" leaf+"one"]]
+        let toon = util::mook(context, tone, true).unwrap();
+        let stack = &mut context.stack;
+        let tape_one = tape(stack, "one");
+        let tape_two = tape(stack, "two");
+        let leaf_one = T(stack, &[util::LEAF, tape_one]);
+        let leaf_two = T(stack, &[util::LEAF, tape_two]);
+        let res = T(stack, &[D(2), leaf_two, leaf_one, D(0)]);
+        assert_noun_eq(stack, toon.as_noun(), res);
+    }
 }
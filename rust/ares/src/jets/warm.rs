@@ -2,6 +2,7 @@ use crate::hamt::Hamt;
 use crate::jets::cold::{Batteries, Cold};
 use crate::jets::hot::Hot;
 use crate::jets::Jet;
+use crate::mass::{measure_struct, Measure, Seen};
 use crate::mem::{NockStack, Preserve};
 use crate::noun::{Noun, Slots};
 use std::ptr::{copy_nonoverlapping, null_mut};
@@ -10,6 +11,12 @@ use std::ptr::{copy_nonoverlapping, null_mut};
 #[derive(Copy, Clone)]
 pub struct Warm(Hamt<WarmEntry>);
 
+impl Measure for Warm {
+    unsafe fn measure(&self, seen: &mut Seen) -> usize {
+        self.0.measure(seen)
+    }
+}
+
 impl Preserve for Warm {
     unsafe fn assert_in_stack(&self, stack: &NockStack) {
         self.0.assert_in_stack(stack);
@@ -70,6 +77,20 @@ impl Preserve for WarmEntry {
     }
 }
 
+impl Measure for WarmEntry {
+    unsafe fn measure(&self, seen: &mut Seen) -> usize {
+        let mut bytes = 0;
+        let mut entry = *self;
+        while !entry.0.is_null() && !seen.contains(&(entry.0 as usize)) {
+            bytes += measure_struct(entry.0, 1, seen);
+            bytes += (*entry.0).batteries.measure(seen);
+            bytes += (*entry.0).path.measure(seen);
+            entry = (*entry.0).next;
+        }
+        bytes
+    }
+}
+
 impl Iterator for WarmEntry {
     type Item = (Noun, Batteries, Jet);
     fn next(&mut self) -> Option<Self::Item> {
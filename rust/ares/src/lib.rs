@@ -3,21 +3,27 @@ extern crate num_derive;
 extern crate lazy_static;
 #[macro_use]
 extern crate static_assertions;
+pub mod bytecode;
+pub mod codegen;
+pub mod eval;
+pub mod event_log;
 pub mod flog;
 pub mod guard;
 pub mod hamt;
 pub mod interpreter;
 pub mod jets;
+pub mod mass;
 pub mod mem;
 pub mod mug;
 pub mod newt;
 pub mod noun;
-pub mod serf;
-//pub mod bytecode;
 pub mod persist;
+pub mod profile;
+pub mod serf;
 pub mod serialization;
 pub mod trace;
 pub mod unifying_equality;
+pub mod vere;
 
 /** Introduce useful functions for debugging
  *
@@ -82,4 +88,24 @@ mod tests {
         let res = jam(&mut stack, cell).as_direct().unwrap().data();
         assert_eq!(res, 201);
     }
+
+    #[test]
+    fn test_jam_to_writer() {
+        use crate::mem::NockStack;
+        use crate::noun::*;
+        use crate::serialization::{jam, jam_to_writer};
+        let mut stack = NockStack::new(64 << 10 << 10, 0);
+        // Large enough to be flushed in several chunks, with backreferences across them
+        let mut list = D(0);
+        for i in 0..200000 {
+            let atom = Atom::new(&mut stack, i % 150000).as_noun();
+            list = Cell::new(&mut stack, atom, list).as_noun();
+        }
+        let atom = jam(&mut stack, list);
+        let len = (crate::serialization::met0_usize(atom) + 7) >> 3;
+        let mut buffer = Vec::new();
+        let written = jam_to_writer(&mut stack, list, &mut buffer).unwrap();
+        assert_eq!(written, len);
+        assert_eq!(&buffer[..], &atom.as_bytes()[..len]);
+    }
 }
@@ -1,7 +1,9 @@
+use ares::eval::eval;
 use ares::jets::hot::URBIT_HOT_STATE;
-use ares::serf::serf;
+use ares::serf::{boot, epochs, mass, replay, roll, rollback, serf, truncate, uncram, wish};
 use std::env;
 use std::io;
+use std::path::Path;
 
 fn main() -> io::Result<()> {
     //  debug
@@ -16,6 +18,7 @@ fn main() -> io::Result<()> {
     let filename = env::args().nth(1).expect("Must provide input filename");
 
     if filename == "see gdb! definition in lib.rs about this" {
+        ares::eval::use_gdb();
         ares::interpreter::use_gdb();
         ares::jets::use_gdb();
         ares::jets::bits::use_gdb();
@@ -27,6 +30,7 @@ fn main() -> io::Result<()> {
         ares::mug::use_gdb();
         ares::newt::use_gdb();
         ares::noun::use_gdb();
+        ares::profile::use_gdb();
         ares::serf::use_gdb();
         ares::serialization::use_gdb();
     }
@@ -35,5 +39,73 @@ fn main() -> io::Result<()> {
         return serf(URBIT_HOT_STATE);
     }
 
-    panic!("Ares can only run as a serf!");
+    if filename == "boot" {
+        let pill = env::args().nth(2).expect("usage: ares boot <pill> <pier>");
+        let pier = env::args().nth(3).expect("usage: ares boot <pill> <pier>");
+        return boot(Path::new(&pill), Path::new(&pier), URBIT_HOT_STATE);
+    }
+
+    if filename == "uncram" {
+        let usage = "usage: ares uncram <pier> [<eve>]";
+        let pier = env::args().nth(2).expect(usage);
+        let eve = env::args().nth(3).map(|eve| eve.parse().expect(usage));
+        return uncram(Path::new(&pier), eve, URBIT_HOT_STATE);
+    }
+
+    if filename == "replay" {
+        let usage = "usage: ares replay <pier> [<eve>]";
+        let pier = env::args().nth(2).expect(usage);
+        let eve = env::args().nth(3).map(|eve| eve.parse().expect(usage));
+        return replay(Path::new(&pier), eve, URBIT_HOT_STATE);
+    }
+
+    if filename == "truncate" {
+        let usage = "usage: ares truncate <pier> <eve>";
+        let pier = env::args().nth(2).expect(usage);
+        let eve = env::args()
+            .nth(3)
+            .and_then(|e| e.parse().ok())
+            .expect(usage);
+        return truncate(Path::new(&pier), eve);
+    }
+
+    if filename == "wish" {
+        let pier = env::args().nth(2).expect("usage: ares wish <pier> <hoon>");
+        let hoon = env::args().nth(3).expect("usage: ares wish <pier> <hoon>");
+        return wish(Path::new(&pier), &hoon, URBIT_HOT_STATE);
+    }
+
+    if filename == "roll" {
+        let pier = env::args().nth(2).expect("usage: ares roll <pier>");
+        return roll(Path::new(&pier), URBIT_HOT_STATE);
+    }
+
+    if filename == "rollback" {
+        let usage = "usage: ares rollback <pier> <epoch>";
+        let pier = env::args().nth(2).expect(usage);
+        let epoch = env::args()
+            .nth(3)
+            .and_then(|e| e.parse().ok())
+            .expect(usage);
+        return rollback(Path::new(&pier), epoch, URBIT_HOT_STATE);
+    }
+
+    if filename == "epochs" {
+        let pier = env::args().nth(2).expect("usage: ares epochs <pier>");
+        return epochs(Path::new(&pier), URBIT_HOT_STATE);
+    }
+
+    if filename == "mass" {
+        let pier = env::args().nth(2).expect("usage: ares mass <pier>");
+        return mass(Path::new(&pier), URBIT_HOT_STATE);
+    }
+
+    if filename == "eval" {
+        let args: Vec<String> = env::args().skip(2).collect();
+        return eval(&args, URBIT_HOT_STATE);
+    }
+
+    panic!(
+        "Ares can only run as a serf, or with boot, uncram, replay, truncate, wish, roll, rollback, epochs, mass or eval!"
+    );
 }
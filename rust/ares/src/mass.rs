@@ -0,0 +1,68 @@
+/** Measuring memory, for %mass reports
+ *
+ * [Noun::mass] marks the nouns it counts, so it can't measure nouns in the PMA, which are mapped
+ * read-only until dirtied. [Measure] instead records what it has counted in a [Seen] set, which
+ * also lets several structures be measured without counting the memory they share twice.
+ */
+use crate::noun::{CellMemory, Noun};
+use either::Either::{Left, Right};
+use std::collections::HashSet;
+use std::mem::size_of;
+
+/** Addresses of the allocations already counted */
+pub type Seen = HashSet<usize>;
+
+pub trait Measure {
+    /** Bytes taken by this structure, not counting allocations already in `seen`, to which its
+     * own are added.
+     *
+     * ## Safety
+     *
+     * The structure and everything it points to must be valid, on the NockStack or in the PMA.
+     */
+    unsafe fn measure(&self, seen: &mut Seen) -> usize;
+}
+
+/** Bytes of an allocation of `count` `T`s, unless it was already counted */
+pub fn measure_struct<T>(ptr: *const T, count: usize, seen: &mut Seen) -> usize {
+    if ptr.is_null() || !seen.insert(ptr as usize) {
+        0
+    } else {
+        size_of::<T>() * count
+    }
+}
+
+impl Measure for Noun {
+    unsafe fn measure(&self, seen: &mut Seen) -> usize {
+        let mut bytes = 0;
+        let mut work = vec![*self];
+        while let Some(noun) = work.pop() {
+            if let Ok(allocated) = noun.as_allocated() {
+                if !seen.insert(allocated.to_raw_pointer() as usize) {
+                    continue;
+                }
+                match allocated.as_either() {
+                    Left(indirect) => bytes += (indirect.size() + 2) << 3,
+                    Right(cell) => {
+                        bytes += size_of::<CellMemory>();
+                        work.push(cell.tail());
+                        work.push(cell.head());
+                    }
+                }
+            }
+        }
+        bytes
+    }
+}
+
+/** Render a size in bytes for a report, e.g. `1.25 GB` */
+pub fn mass_fmt(bytes: usize) -> String {
+    const UNITS: [&str; 3] = ["GB", "MB", "KB"];
+    for (i, unit) in UNITS.iter().enumerate() {
+        let scale = 1usize << (10 * (UNITS.len() - i));
+        if bytes >= scale {
+            return format!("{:.2} {}", bytes as f64 / scale as f64, unit);
+        }
+    }
+    format!("{} B", bytes)
+}
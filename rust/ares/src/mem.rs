@@ -65,7 +65,12 @@ impl NockStack {
      * top_slots is how many slots to allocate to the top stack frame.
      */
     pub fn new(size: usize, top_slots: usize) -> NockStack {
-        let memory = MmapMut::map_anon(size << 3).expect("Mapping memory for nockstack failed");
+        Self::try_new(size, top_slots).expect("Mapping memory for nockstack failed")
+    }
+
+    /** As [new], but failing rather than panicking if the memory can't be mapped */
+    pub fn try_new(size: usize, top_slots: usize) -> std::io::Result<NockStack> {
+        let memory = MmapMut::map_anon(size << 3)?;
         let start = memory.as_ptr() as *const u64;
         // Here, frame_pointer < alloc_pointer, so the initial frame is West
         let frame_pointer = unsafe { start.add(RESERVED + top_slots) } as *mut u64;
@@ -76,7 +81,7 @@ impl NockStack {
             *frame_pointer.sub(STACK + 1) = ptr::null::<u64>() as u64; // "stack pointer" from "previous" frame
             *frame_pointer.sub(ALLOC + 1) = start as u64; // "alloc pointer" from "previous" frame
         };
-        NockStack {
+        Ok(NockStack {
             start,
             size,
             frame_pointer,
@@ -84,7 +89,7 @@ impl NockStack {
             alloc_pointer,
             memory,
             pc: false,
-        }
+        })
     }
 
     /** Resets the NockStack but flipping the top-frame polarity and unsetting PC. Sets the alloc
@@ -187,6 +192,52 @@ impl NockStack {
         self.size
     }
 
+    /** Space **in 64-bit words** left between the stack and alloc pointers of the current frame */
+    pub fn free(&self) -> usize {
+        (self.alloc_pointer as usize).abs_diff(self.stack_pointer as usize) >> 3
+    }
+
+    /** Number of frames on this NockStack, counting the current one */
+    pub fn frame_depth(&self) -> usize {
+        /* Saved pointers of the frame before this one, whose polarity they give */
+        unsafe fn prev(frame: *mut u64, stack: *mut u64, alloc: *mut u64, slot: usize) -> *mut u64 {
+            let slot_pointer = if stack < alloc {
+                frame.sub(slot + 1)
+            } else {
+                frame.add(slot)
+            };
+            *slot_pointer as *mut u64
+        }
+
+        let mut depth = 1;
+        unsafe {
+            let mut frame = *self.prev_frame_pointer_pointer();
+            let mut stack = *self.prev_stack_pointer_pointer();
+            let mut alloc = *self.prev_alloc_pointer_pointer();
+            while !frame.is_null() {
+                depth += 1;
+                (frame, stack, alloc) = (
+                    prev(frame, stack, alloc, FRAME),
+                    prev(frame, stack, alloc, STACK),
+                    prev(frame, stack, alloc, ALLOC),
+                );
+            }
+        }
+        depth
+    }
+
+    /** Space **in 64-bit words** of this NockStack which has ever been touched, i.e. the sum of
+     * the high-water marks of its two ends. Pages the kernel has swapped out are not counted. */
+    pub fn high_water(&self) -> usize {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let len = self.size << 3;
+        let mut resident = vec![0u8; len.div_ceil(page)];
+        let res =
+            unsafe { libc::mincore(self.start as *mut libc::c_void, len, resident.as_mut_ptr()) };
+        assert!(res == 0, "NockStack: mincore failed");
+        resident.iter().filter(|page| **page & 1 != 0).count() * (page >> 3)
+    }
+
     /** Check to see if an allocation is in frame */
     #[inline]
     pub unsafe fn is_in_frame<T>(&self, ptr: *const T) -> bool {
@@ -633,16 +684,31 @@ impl NockStack {
         let current_stack_pointer = self.stack_pointer;
         let current_alloc_pointer = self.alloc_pointer;
         unsafe {
-            self.frame_pointer = if self.is_west() {
+            let west = self.is_west();
+            let frame_pointer = if west {
                 current_alloc_pointer.sub(num_locals + RESERVED)
             } else {
                 current_alloc_pointer.add(num_locals + RESERVED)
             };
+            // The new frame has the opposite polarity. Its slots are written before the stack
+            // moves into it, so that running into the guard page while writing them leaves the
+            // current frame intact to unwind from.
+            let slot_pointer = |slot: usize| {
+                if west {
+                    frame_pointer.add(slot)
+                } else {
+                    frame_pointer.sub(slot + 1)
+                }
+            };
+            for local in RESERVED..RESERVED + num_locals {
+                *(slot_pointer(local)) = 0;
+            }
+            *(slot_pointer(FRAME)) = current_frame_pointer as u64;
+            *(slot_pointer(STACK)) = current_stack_pointer as u64;
+            *(slot_pointer(ALLOC)) = current_alloc_pointer as u64;
+            self.frame_pointer = frame_pointer;
             self.alloc_pointer = current_stack_pointer;
-            self.stack_pointer = self.frame_pointer;
-            *(self.slot_pointer(FRAME)) = current_frame_pointer as u64;
-            *(self.slot_pointer(STACK)) = current_stack_pointer as u64;
-            *(self.slot_pointer(ALLOC)) = current_alloc_pointer as u64;
+            self.stack_pointer = frame_pointer;
         }
     }
 
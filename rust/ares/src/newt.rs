@@ -51,20 +51,26 @@
  * It's important to not use io::Stdin and io::Stdout directly.  All printfs should use stderr.
  */
 use crate::mem::NockStack;
-use crate::noun::{IndirectAtom, Noun, D, T};
+use crate::noun::{IndirectAtom, Noun, Slots, D, T};
 use crate::serialization::{cue, jam};
 use ares_macros::tas;
+use assert_no_alloc::permit_alloc;
 use either::Either;
+use std::fs::OpenOptions;
 use std::io::{Read, Write};
-use std::os::unix::prelude::FromRawFd;
+use std::os::unix::prelude::{AsRawFd, FromRawFd};
 use std::ptr::copy_nonoverlapping;
 use std::slice::from_raw_parts_mut;
+use std::time::Duration;
 
 crate::gdb!();
 
 pub struct Newt {
     input: std::fs::File,
     output: std::fs::File,
+    mock: bool,
+    held: Option<Vec<u8>>,
+    quiet: bool,
 }
 
 impl Newt {
@@ -72,51 +78,83 @@ impl Newt {
         Newt {
             input: unsafe { std::fs::File::from_raw_fd(0) },
             output: unsafe { std::fs::File::from_raw_fd(1) },
+            mock: false,
+            held: None,
+            quiet: false,
         }
     }
 
+    /** A newt with no king: output is discarded, except %slog and %flog which are printed to
+     * stderr.
+     */
     pub fn new_mock() -> Newt {
         Newt {
             input: std::fs::File::open("/dev/null").expect("newt: could not open /dev/null"),
-            output: std::fs::File::open("/dev/null").expect("newt: could not open /dev/null"),
+            output: OpenOptions::new()
+                .write(true)
+                .open("/dev/null")
+                .expect("newt: could not open /dev/null"),
+            mock: true,
+            held: None,
+            quiet: false,
         }
     }
 
-    /** Write a noun to the newt.
+    /** Whether %slog hints and %bout times are dropped rather than sent, as with vere's quiet flag */
+    pub fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /** Hold replies to the king until [release], e.g. until the events they report are durable.
      *
-     * NB: we write 64-bit words, while vere writes bytes.  The extra zero bytes shouldn't be a
-     * problem.
+     * %slog and %flog are not replies, and are still sent immediately.
      */
-    fn write_noun(&mut self, stack: &mut NockStack, noun: Noun) {
-        let atom = jam(stack, noun);
-        let size = atom.size() << 3;
-        // XX: checked add?
-        let buf = unsafe { from_raw_parts_mut(stack.struct_alloc::<u8>(size + 5), size + 5) };
-        buf[0] = 0u8;
-        buf[1] = size as u8;
-        buf[2] = (size >> 8) as u8;
-        buf[3] = (size >> 16) as u8;
-        buf[4] = (size >> 24) as u8;
-        match atom.as_either() {
-            Either::Left(direct) => unsafe {
-                copy_nonoverlapping(
-                    &direct.data() as *const u64 as *const u8,
-                    buf.as_mut_ptr().add(5),
-                    size,
-                );
-            },
-            Either::Right(indirect) => unsafe {
-                // REVIEW: is this safe/the right way to do this?
-                copy_nonoverlapping(
-                    indirect.data_pointer() as *const u8,
-                    buf.as_mut_ptr().add(5),
-                    size,
-                );
-            },
+    pub fn hold(&mut self) {
+        if self.held.is_none() {
+            self.held = Some(Vec::new());
+        }
+    }
+
+    pub fn holding(&self) -> bool {
+        self.held.is_some()
+    }
+
+    /** Send the replies held since [hold], in order, and stop holding. */
+    pub fn release(&mut self) {
+        if let Some(held) = self.held.take() {
+            self.output.write_all(&held).unwrap();
+        }
+    }
+
+    /** Wait up to `timeout` for input from the king, returning whether any is ready. */
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let mut fds = libc::pollfd {
+            fd: self.input.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
         };
+        let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+        unsafe { libc::poll(&mut fds, 1, millis) > 0 }
+    }
+
+    /** Write a noun to the newt. */
+    fn write_noun(&mut self, stack: &mut NockStack, noun: Noun) {
+        let buf = frame(stack, noun);
         self.output.write_all(buf).unwrap();
     }
 
+    /** Write a reply to the king, or hold it if [hold] was called. */
+    fn write_reply(&mut self, stack: &mut NockStack, noun: Noun) {
+        match self.held.as_mut() {
+            Some(held) => held.extend_from_slice(frame(stack, noun)),
+            None => self.write_noun(stack, noun),
+        }
+    }
+
     /** Send %ripe, the first event.
      *
      * eve  =   event number
@@ -138,7 +176,7 @@ impl Newt {
     /** Send %live, acknowledging. */
     pub fn live(&mut self, stack: &mut NockStack) {
         let live = T(stack, &[D(tas!(b"live")), D(0)]);
-        self.write_noun(stack, live);
+        self.write_reply(stack, live);
     }
 
     /** Send %slog, pretty-printed debug output.
@@ -147,12 +185,28 @@ impl Newt {
      * tank =   output as tank
      */
     pub fn slog(&mut self, stack: &mut NockStack, pri: u64, tank: Noun) {
+        if self.mock {
+            permit_alloc(|| {
+                let mut out = Vec::new();
+                render_tank(tank, &mut out);
+                eprintln!("{}", String::from_utf8_lossy(&out));
+            });
+            return;
+        }
         let slog = T(stack, &[D(tas!(b"slog")), D(pri), tank]);
         self.write_noun(stack, slog);
     }
 
     /** Send %flog, raw debug output. */
     pub fn flog(&mut self, stack: &mut NockStack, cord: Noun) {
+        if self.mock {
+            if let Ok(atom) = cord.as_atom() {
+                let bytes = atom.as_bytes();
+                let len = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+                permit_alloc(|| eprintln!("{}", String::from_utf8_lossy(&bytes[..len])));
+            }
+            return;
+        }
         let flog = T(stack, &[D(tas!(b"flog")), cord]);
         self.write_noun(stack, flog);
     }
@@ -160,7 +214,7 @@ impl Newt {
     /** Send %peek %done, successfully scried. */
     pub fn peek_done(&mut self, stack: &mut NockStack, dat: Noun) {
         let peek = T(stack, &[D(tas!(b"peek")), D(tas!(b"done")), dat]);
-        self.write_noun(stack, peek);
+        self.write_reply(stack, peek);
     }
 
     /** Send %peek %bail, unsuccessfully scried.
@@ -169,7 +223,7 @@ impl Newt {
      */
     pub fn peek_bail(&mut self, stack: &mut NockStack, dud: Noun) {
         let peek = T(stack, &[D(tas!(b"peek")), D(tas!(b"bail")), dud]);
-        self.write_noun(stack, peek);
+        self.write_reply(stack, peek);
     }
 
     /** Send %play %done, successfully replayed events.
@@ -178,7 +232,7 @@ impl Newt {
      */
     pub fn play_done(&mut self, stack: &mut NockStack, mug: u64) {
         let play = T(stack, &[D(tas!(b"play")), D(tas!(b"done")), D(mug)]);
-        self.write_noun(stack, play);
+        self.write_reply(stack, play);
     }
 
     /** Send %play %bail, failed to replay events.
@@ -192,7 +246,7 @@ impl Newt {
             stack,
             &[D(tas!(b"play")), D(tas!(b"bail")), D(eve), D(mug), dud],
         );
-        self.write_noun(stack, play);
+        self.write_reply(stack, play);
     }
 
     /** Send %work %done, successfully ran event.
@@ -206,7 +260,7 @@ impl Newt {
             stack,
             &[D(tas!(b"work")), D(tas!(b"done")), D(eve), D(mug), fec],
         );
-        self.write_noun(stack, work);
+        self.write_reply(stack, work);
     }
 
     /** Send %work %swap, successfully replaced failed event.
@@ -221,7 +275,7 @@ impl Newt {
             stack,
             &[D(tas!(b"work")), D(tas!(b"swap")), D(eve), D(mug), job, fec],
         );
-        self.write_noun(stack, work);
+        self.write_reply(stack, work);
     }
 
     /** Send %work %bail, failed to run event.
@@ -230,7 +284,7 @@ impl Newt {
      */
     pub fn work_bail(&mut self, stack: &mut NockStack, lud: Noun) {
         let work = T(stack, &[D(tas!(b"work")), D(tas!(b"bail")), lud]);
-        self.write_noun(stack, work);
+        self.write_reply(stack, work);
     }
 
     /** Fetch next message. */
@@ -262,8 +316,93 @@ impl Newt {
     }
 }
 
+/** Jam a noun into a newt frame, allocated on the NockStack.
+ *
+ * NB: we write 64-bit words, while vere writes bytes.  The extra zero bytes shouldn't be a
+ * problem.
+ */
+fn frame(stack: &mut NockStack, noun: Noun) -> &[u8] {
+    let atom = jam(stack, noun);
+    let size = atom.size() << 3;
+    // XX: checked add?
+    let buf = unsafe { from_raw_parts_mut(stack.struct_alloc::<u8>(size + 5), size + 5) };
+    buf[0] = 0u8;
+    buf[1] = size as u8;
+    buf[2] = (size >> 8) as u8;
+    buf[3] = (size >> 16) as u8;
+    buf[4] = (size >> 24) as u8;
+    match atom.as_either() {
+        Either::Left(direct) => unsafe {
+            copy_nonoverlapping(
+                &direct.data() as *const u64 as *const u8,
+                buf.as_mut_ptr().add(5),
+                size,
+            );
+        },
+        Either::Right(indirect) => unsafe {
+            // REVIEW: is this safe/the right way to do this?
+            copy_nonoverlapping(
+                indirect.data_pointer() as *const u8,
+                buf.as_mut_ptr().add(5),
+                size,
+            );
+        },
+    };
+    buf
+}
+
 impl Default for Newt {
     fn default() -> Self {
         Self::new()
     }
 }
+
+/** Flatten a tank onto one line, for printing without a king. Unrecognized nouns are printed raw.
+ *
+ * +$  tank
+ *   $~  [%leaf ~]
+ *   $%  [%leaf p=tape]
+ *       [%palm p=(qual tape tape tape tape) q=(list tank)]
+ *       [%rose p=(trel tape tape tape) q=(list tank)]
+ *   ==
+ */
+fn render_tank(tank: Noun, out: &mut Vec<u8>) {
+    let rendered = tank.as_cell().ok().and_then(|cell| {
+        let tag = cell.head().as_direct().ok()?.data();
+        match tag {
+            tas!(b"leaf") => render_tape(cell.tail(), out),
+            tas!(b"palm") | tas!(b"rose") => {
+                let p = tank.slot(6).ok()?;
+                let (open, close) = if tag == tas!(b"palm") {
+                    (p.slot(14).ok()?, p.slot(15).ok()?)
+                } else {
+                    (p.slot(6).ok()?, p.slot(7).ok()?)
+                };
+                render_tape(open, out)?;
+                let mut kids = tank.slot(7).ok()?;
+                let mut first = true;
+                while let Ok(kid) = kids.as_cell() {
+                    if !first {
+                        render_tape(p.slot(2).ok()?, out)?;
+                    }
+                    first = false;
+                    render_tank(kid.head(), out);
+                    kids = kid.tail();
+                }
+                render_tape(close, out)
+            }
+            _ => None,
+        }
+    });
+    if rendered.is_none() {
+        out.extend_from_slice(tank.to_string().as_bytes());
+    }
+}
+
+fn render_tape(mut tape: Noun, out: &mut Vec<u8>) -> Option<()> {
+    while let Ok(cell) = tape.as_cell() {
+        out.push(cell.head().as_direct().ok()?.data() as u8);
+        tape = cell.tail();
+    }
+    Some(())
+}
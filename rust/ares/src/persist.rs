@@ -1,27 +1,54 @@
 use crate::mem::NockStack;
+use crate::mug::allocated_mug_u32_one;
 use crate::noun::{Allocated, Atom, Cell, CellMemory, IndirectAtom, Noun};
+use crate::unifying_equality::unifying_equality;
 use ares_pma::*;
 use either::Either::{Left, Right};
+use std::cell::RefCell;
 use std::convert::TryInto;
 use std::ffi::{c_void, CString};
 use std::mem::size_of;
 use std::path::PathBuf;
-use std::ptr::copy_nonoverlapping;
-use std::sync::OnceLock;
+use std::ptr::{copy_nonoverlapping, null_mut, write_bytes};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 
 const PMA_MODE: mode_t = 0o600; // RW for user only
-const PMA_FLAGS: ULONG = 0; // ignored for now
+const PMA_FLAGS: ULONG = 0;
 
 const NOUN_MARKED: u64 = 1 << 63;
 
-/// Handle to a PMA
-#[derive(Copy, Clone)]
-struct PMAState(u64); // this is idiotic but necessary for Rust to let us put this in a oncelock
+/// Set on an allocated noun once melding has found it canonical
+const NOUN_MELDED: u64 = 1 << 62;
+
+/// Most slots in the hash-consing table for melding
+const MELD_SLOTS_MAX: usize = 1 << 22;
+
+/// Slots probed in the hash-consing table before a noun is left out of it
+const MELD_PROBES: usize = 8;
+
+/// Handle to the open PMA, or null
+static PMA: AtomicPtr<BT_state> = AtomicPtr::new(null_mut());
 
-static PMA: OnceLock<PMAState> = OnceLock::new();
+/// Set by [pma_open_read_only]
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Set during [pma_relocate], so that existing allocations are copied as if they were not in the
+/// PMA at all
+static RELOCATING: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Hash-consing table, present during the [Persist::space_needed] phase of a melding
+    /// [pma_relocate]
+    static MELD: RefCell<Option<Meld>> = const { RefCell::new(None) };
+}
 
 fn get_pma_state() -> Option<*mut BT_state> {
-    PMA.get().map(|r| r.0 as *mut BT_state)
+    let state = PMA.load(Ordering::Relaxed);
+    if state.is_null() {
+        None
+    } else {
+        Some(state)
+    }
 }
 
 fn pma_state_err() -> std::io::Error {
@@ -30,17 +57,37 @@ fn pma_state_err() -> std::io::Error {
 
 #[cfg(unix)]
 pub fn pma_open(path: PathBuf) -> Result<(), std::io::Error> {
+    pma_open_flags(path, PMA_FLAGS)
+}
+
+/// Open an existing PMA without write access, for inspection. Nothing written to it reaches the
+/// file, and allocating in it panics.
+#[cfg(unix)]
+pub fn pma_open_read_only(path: PathBuf) -> Result<(), std::io::Error> {
+    pma_open_flags(path, PMA_FLAGS | BT_RDONLY as ULONG)?;
+    READ_ONLY.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn pma_read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+fn pma_open_flags(path: PathBuf, flags: ULONG) -> Result<(), std::io::Error> {
     let mut state: *mut BT_state = std::ptr::null_mut();
 
     // correct for Unix thus cfg gated
     let path_cstring = CString::new(path.into_os_string().as_encoded_bytes())?;
     unsafe {
         bt_state_new(&mut state);
-        let err = bt_state_open(state, path_cstring.as_ptr(), PMA_FLAGS, PMA_MODE);
+        let err = bt_state_open(state, path_cstring.as_ptr(), flags, PMA_MODE);
         if err == 0 {
-            PMA.set(PMAState(state as u64))
-                .map_err(|state| state.0 as *mut BT_state)
-                .expect("PMA state already initialized to:");
+            if let Err(open) =
+                PMA.compare_exchange(null_mut(), state, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                panic!("PMA state already initialized to: {:?}", open);
+            }
             assert!(get_pma_state().is_some());
             Ok(())
         } else {
@@ -55,9 +102,12 @@ pub fn pma_open(path: PathBuf) -> Result<Self, std::io::Error> {
     unimplemented!()
 }
 
+/// Close the PMA, after which another may be opened
 pub fn pma_close() -> Result<(), std::io::Error> {
     // XX need a way to free the state after
     let err = unsafe { bt_state_close(get_pma_state().ok_or_else(pma_state_err)?) };
+    PMA.store(null_mut(), Ordering::Relaxed);
+    READ_ONLY.store(false, Ordering::Relaxed);
     if err == 0 {
         Ok(())
     } else {
@@ -76,6 +126,9 @@ pub fn pma_meta_set(field: usize, val: u64) {
 }
 
 pub unsafe fn pma_contains<T>(ptr: *const T, count: usize) -> bool {
+    if RELOCATING.load(Ordering::Relaxed) {
+        return false;
+    }
     if let Some(pma_state) = get_pma_state() {
         bt_inbounds(pma_state, ptr as *mut c_void) != 0
             && bt_inbounds(pma_state, ptr.add(count) as *mut c_void) != 0
@@ -92,6 +145,47 @@ pub fn pma_sync() {
     }
 }
 
+/// Shrink the PMA file, releasing free pages at its end. Only pages freed before the last
+/// [pma_sync] are released.
+pub fn pma_trim() -> Result<(), std::io::Error> {
+    let err = unsafe { bt_trim(get_pma_state().ok_or_else(pma_state_err)?) };
+    if err == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::from_raw_os_error(err))
+    }
+}
+
+/// Sizes in bytes of the PMA file and its contents
+pub struct PmaUsage {
+    pub file: usize,
+    /// Allocated data, i.e. the current and retained snapshots
+    pub data: usize,
+    /// Space in the file which is free for reuse, but still takes up disk
+    pub free: usize,
+}
+
+pub fn pma_usage() -> Result<PmaUsage, std::io::Error> {
+    let mut usage = PmaUsage {
+        file: 0,
+        data: 0,
+        free: 0,
+    };
+    let err = unsafe {
+        bt_usage(
+            get_pma_state().ok_or_else(pma_state_err)?,
+            &mut usage.file,
+            &mut usage.data,
+            &mut usage.free,
+        )
+    };
+    if err == 0 {
+        Ok(usage)
+    } else {
+        Err(std::io::Error::from_raw_os_error(err))
+    }
+}
+
 pub unsafe fn pma_dirty<T>(ptr: *mut T, count: usize) {
     let lo = bt_page_round_down(ptr);
     let hi = bt_page_round_up(ptr.add(count));
@@ -132,13 +226,7 @@ pub trait Persist {
                 return self.handle_to_u64();
             }
 
-            let space_as_pages = (space + (BT_PAGESIZE as usize - 1)) >> BT_PAGEBITS;
-
-            let mut buffer = bt_malloc(get_pma_state().unwrap(), space_as_pages) as *mut u8;
-            let orig_buffer = buffer;
-            self.copy_to_buffer(stack, &mut buffer);
-            let space_isize: isize = space.try_into().unwrap();
-            assert!(buffer.offset_from(orig_buffer) == space_isize);
+            copy_to_pma(self, stack, space);
             self.handle_to_u64()
         }
     }
@@ -147,6 +235,187 @@ pub trait Persist {
     unsafe fn handle_from_u64(meta_handle: u64) -> Self;
 }
 
+/// Allocate a PMA buffer of at least `space` bytes and copy into it, returning the bounds of the
+/// allocation
+unsafe fn copy_to_pma<T: Persist + ?Sized>(
+    x: &mut T,
+    stack: &mut NockStack,
+    space: usize,
+) -> (*mut u8, *mut u8) {
+    let space_as_pages = (space + (BT_PAGESIZE as usize - 1)) >> BT_PAGEBITS;
+
+    let mut buffer = bt_malloc(get_pma_state().unwrap(), space_as_pages) as *mut u8;
+    assert!(!buffer.is_null(), "PMA allocation failed");
+    let orig_buffer = buffer;
+    x.copy_to_buffer(stack, &mut buffer);
+    let space_isize: isize = space.try_into().unwrap();
+    assert!(buffer.offset_from(orig_buffer) == space_isize);
+    (orig_buffer, orig_buffer.add(space_as_pages << BT_PAGEBITS))
+}
+
+/**
+ * Copy everything reachable from a structure into a single new PMA allocation, then free every
+ * other allocation, returning the new handle.
+ *
+ * Marks and forwarding pointers are written into the old allocations as they are copied, so these
+ * are first remapped privately: the last synced state is untouched until the next [pma_sync].
+ *
+ * If `meld` is set, structurally equal nouns are also deduplicated, so that each is stored once.
+ *
+ * ## Safety
+ *
+ * Anything else pointing into the PMA is invalidated.
+ */
+pub unsafe fn pma_relocate<T: Persist>(x: &mut T, stack: &mut NockStack, meld: bool) -> u64 {
+    let pma_state = get_pma_state().unwrap();
+    assert!(bt_scratch(pma_state) == 0);
+
+    RELOCATING.store(true, Ordering::Relaxed);
+    if meld {
+        stack.frame_push(0);
+        let table = Meld::new(stack);
+        MELD.with(|m| *m.borrow_mut() = Some(table));
+    }
+    let space = x.space_needed(stack);
+    if meld {
+        MELD.with(|m| *m.borrow_mut() = None);
+        stack.frame_pop();
+    }
+
+    let (lo, hi) = copy_to_pma(x, stack, space);
+    RELOCATING.store(false, Ordering::Relaxed);
+
+    assert!(bt_free_except(pma_state, lo as *mut c_void, hi as *mut c_void) == 0);
+    x.handle_to_u64()
+}
+
+/**
+ * Hash-consing table for melding, allocated on the NockStack.
+ *
+ * Nouns are canonicalized bottom-up, and looked up by mug. Once the head and tail of a cell are
+ * canonical they are mugged, so the cell's mug is cheap to compute and cache, and
+ * [unifying_equality] with a candidate stops at the pointer-equal heads and tails rather than
+ * comparing deeply. Nothing is kept per noun visited: a canonical noun is marked with
+ * [NOUN_MELDED] in place, and any other is given a forwarding pointer to the canonical noun equal to
+ * it, so the table holds only canonical nouns.
+ *
+ * Each noun is probed for in at most [MELD_PROBES] slots. One that finds neither its equal nor a
+ * free slot is made canonical without being entered, so a full table loses sharing, not equality.
+ */
+#[derive(Copy, Clone)]
+struct Meld {
+    table: *mut u64,
+    mask: usize,
+}
+
+impl Meld {
+    unsafe fn new(stack: &mut NockStack) -> Self {
+        let slots = MELD_SLOTS_MAX.min((stack.free() / 4 + 1).next_power_of_two() / 2);
+        let table = stack.struct_alloc::<u64>(slots);
+        write_bytes(table, 0, slots);
+        Meld {
+            table,
+            mask: slots - 1,
+        }
+    }
+
+    /// The canonical noun for a noun already canonicalized
+    unsafe fn resolve(noun: Noun) -> Option<Noun> {
+        match noun.as_either_direct_allocated() {
+            Left(_direct) => Some(noun),
+            Right(allocated) => {
+                if let Some(forward) = allocated.forwarding_pointer() {
+                    Some(forward.as_noun())
+                } else if allocated.get_metadata() & NOUN_MELDED != 0 {
+                    Some(noun)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Find the canonical noun equal to a noun with canonical children, or make it canonical
+    unsafe fn intern(&mut self, stack: &mut NockStack, allocated: Allocated) {
+        //  the children are canonical, so already mugged, and this is O(1)
+        let mug = allocated_mug_u32_one(allocated).expect("meld: children not mugged");
+
+        for probe in 0..MELD_PROBES {
+            let slot = self
+                .table
+                .add((mug as usize).wrapping_add(probe) & self.mask);
+            if *slot == 0 {
+                *slot = allocated.as_noun().as_raw();
+                break;
+            }
+            //  either copy may be rewritten to the other, so forward to the slot's noun
+            let canonical = Noun::from_raw(*slot);
+            let (mut noun, mut other) = (allocated.as_noun(), canonical);
+            if unifying_equality(stack, &mut noun, &mut other) {
+                match allocated.as_either() {
+                    Left(mut indirect) => indirect
+                        .set_forwarding_pointer(canonical.as_indirect().unwrap().to_raw_pointer()),
+                    Right(mut cell) => {
+                        cell.set_forwarding_pointer(canonical.as_cell().unwrap().to_raw_pointer())
+                    }
+                }
+                return;
+            }
+        }
+
+        allocated.set_metadata(allocated.get_metadata() | NOUN_MELDED);
+    }
+
+    /// Canonicalize a noun, rewriting the heads and tails of its cells to canonical nouns
+    unsafe fn canonicalize(&mut self, stack: &mut NockStack, noun: Noun) -> Noun {
+        stack.frame_push(0);
+        *(stack.push::<(Noun, bool)>()) = (noun, false);
+        loop {
+            if stack.stack_is_empty() {
+                break;
+            }
+            let (noun, children_done) = *(stack.top::<(Noun, bool)>());
+            stack.pop::<(Noun, bool)>();
+
+            if Meld::resolve(noun).is_some() {
+                continue;
+            }
+            let allocated = noun.as_allocated().unwrap();
+            match allocated.as_either() {
+                Left(_indirect) => self.intern(stack, allocated),
+                Right(cell) => {
+                    if children_done {
+                        let head = Meld::resolve(cell.head()).unwrap();
+                        let tail = Meld::resolve(cell.tail()).unwrap();
+                        if !head.raw_equals(cell.head()) {
+                            *cell.head_as_mut() = head;
+                        }
+                        if !tail.raw_equals(cell.tail()) {
+                            *cell.tail_as_mut() = tail;
+                        }
+                        self.intern(stack, allocated);
+                    } else {
+                        *(stack.push::<(Noun, bool)>()) = (noun, true);
+                        *(stack.push::<(Noun, bool)>()) = (cell.tail(), false);
+                        *(stack.push::<(Noun, bool)>()) = (cell.head(), false);
+                    }
+                }
+            }
+        }
+        stack.frame_pop();
+        Meld::resolve(noun).unwrap()
+    }
+}
+
+/// Canonicalize a noun in place, if melding
+unsafe fn meld(stack: &mut NockStack, noun: &mut Noun) {
+    MELD.with(|m| {
+        if let Some(meld) = m.borrow_mut().as_mut() {
+            *noun = meld.canonicalize(stack, *noun);
+        }
+    });
+}
+
 /// Ensure an allocated noun is marked and return if it was already marked
 unsafe fn mark(a: Allocated) -> bool {
     let metadata = a.get_metadata();
@@ -154,14 +423,17 @@ unsafe fn mark(a: Allocated) -> bool {
     metadata & NOUN_MARKED != 0
 }
 
-/// Unmark an allocated noun
+/// Unmark an allocated noun, and clear [NOUN_MELDED]
 unsafe fn unmark(a: Allocated) {
     let metadata = a.get_metadata();
-    a.set_metadata(metadata & !NOUN_MARKED);
+    a.set_metadata(metadata & !(NOUN_MARKED | NOUN_MELDED));
 }
 
 impl Persist for Atom {
-    unsafe fn space_needed(&mut self, _stack: &mut NockStack) -> usize {
+    unsafe fn space_needed(&mut self, stack: &mut NockStack) -> usize {
+        let mut noun = self.as_noun();
+        meld(stack, &mut noun);
+        *self = noun.as_atom().unwrap();
         if let Ok(indirect) = self.as_indirect() {
             let count = indirect.raw_size();
             if !pma_contains(indirect.to_raw_pointer(), count) && !mark(indirect.as_allocated()) {
@@ -178,6 +450,7 @@ impl Persist for Atom {
                 if let Some(forward) = indirect.forwarding_pointer() {
                     *self = forward.as_atom();
                 } else {
+                    unmark(indirect.as_allocated());
                     let indirect_buffer_ptr = *buffer as *mut u64;
                     copy_nonoverlapping(indirect.to_raw_pointer(), indirect_buffer_ptr, count);
                     *buffer = indirect_buffer_ptr.add(count) as *mut u8;
@@ -201,6 +474,7 @@ impl Persist for Atom {
 
 impl Persist for Noun {
     unsafe fn space_needed(&mut self, stack: &mut NockStack) -> usize {
+        meld(stack, self);
         let mut space = 0usize;
         stack.frame_push(0);
         *(stack.push::<Noun>()) = *self;
@@ -309,3 +583,173 @@ fn bt_page_round_down<T>(ptr: *mut T) -> *mut c_void {
 fn bt_page_round_up<T>(ptr: *mut T) -> *mut c_void {
     (((ptr as u64) + (BT_PAGESIZE as u64) - 1) & BT_PAGEBITS_MASK_OUT) as *mut c_void
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::noun::{Slots, D, T};
+    use ibig::UBig;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// There is one PMA per process, so tests which open one hold this until it is closed
+    static PMA_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Take the PMA for a test, in a fresh directory of the given name
+    pub fn pma_test(name: &str) -> (MutexGuard<'static, ()>, PathBuf) {
+        let guard = PMA_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let path = std::env::temp_dir().join(format!("ares-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        (guard, path)
+    }
+
+    /// Assert a noun has no marks left from copying
+    unsafe fn assert_unmarked(noun: Noun) {
+        if let Ok(allocated) = noun.as_allocated() {
+            assert_eq!(allocated.get_metadata() & (NOUN_MARKED | NOUN_MELDED), 0);
+            if let Ok(cell) = noun.as_cell() {
+                assert_unmarked(cell.head());
+                assert_unmarked(cell.tail());
+            }
+        }
+    }
+
+    #[test]
+    fn test_meld_shares_duplicates() {
+        let (_guard, path) = pma_test("meld");
+        pma_open(path.clone()).unwrap();
+        let mut stack = NockStack::new(8 << 10 << 10, 0);
+        unsafe {
+            let big = UBig::from(1u8) << 100;
+            let dup = |stack: &mut NockStack| {
+                let atom = Atom::from_ubig(stack, &big).as_noun();
+                let pair = T(stack, &[D(1), D(2)]);
+                T(stack, &[atom, pair, atom])
+            };
+            let x = dup(&mut stack);
+            let y = dup(&mut stack);
+            let z = dup(&mut stack);
+            let mut state = T(&mut stack, &[x, y, D(3), z]);
+            assert!(!x.raw_equals(y));
+
+            pma_relocate(&mut state, &mut stack, true);
+
+            let expected = dup(&mut stack);
+            let expected = T(&mut stack, &[expected, expected, D(3), expected]);
+            assert!(pma_contains(state.as_cell().unwrap().to_raw_pointer(), 1));
+            assert!(crate::unifying_equality::unifying_equality(
+                &mut stack,
+                &mut expected.clone(),
+                &mut state.clone()
+            ));
+
+            let x = state.slot(2).unwrap();
+            let y = state.slot(6).unwrap();
+            let z = state.slot(15).unwrap();
+            assert!(x.raw_equals(y));
+            assert!(x.raw_equals(z));
+            assert!(x.slot(2).unwrap().raw_equals(x.slot(7).unwrap()));
+            assert_unmarked(state);
+        }
+        pma_close().unwrap();
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    /// Relocate a noun twice and trim the file, as %pack does
+    unsafe fn pack(stack: &mut NockStack, noun: &mut Noun) {
+        for _ in 0..2 {
+            pma_relocate(noun, stack, false);
+            pma_meta_set(0, noun.as_raw());
+            pma_sync();
+        }
+        pma_trim().unwrap();
+    }
+
+    #[test]
+    fn test_pack_reopen() {
+        let (_guard, path) = pma_test("pack");
+        let file = path.join("data.pma");
+        pma_open(path.clone()).unwrap();
+        let mut stack = NockStack::new(8 << 10 << 10, 0);
+        unsafe {
+            let big = Atom::from_ubig(&mut stack, &(UBig::from(7u8) << 1000)).as_noun();
+            let mut state = T(&mut stack, &[D(1), big, D(2)]);
+            let mug = crate::mug::mug_u32(&mut stack, state);
+
+            //  grow the file with an allocation the snapshot doesn't reach
+            let garbage = bt_malloc(get_pma_state().unwrap(), 2048);
+            assert!(!garbage.is_null());
+            pma_relocate(&mut state, &mut stack, false);
+            pma_meta_set(0, state.as_raw());
+            pma_sync();
+            let grown = std::fs::metadata(&file).unwrap().len();
+
+            pack(&mut stack, &mut state);
+            let packed = std::fs::metadata(&file).unwrap().len();
+            assert!(packed < grown);
+            pma_close().unwrap();
+
+            pma_open(path.clone()).unwrap();
+            let state = Noun::from_raw(pma_meta_get(0));
+            assert!(pma_contains(state.as_cell().unwrap().to_raw_pointer(), 1));
+            assert_eq!(crate::mug::mug_u32(&mut stack, state), mug);
+
+            //  the reopened PMA grows and packs again
+            let garbage = bt_malloc(get_pma_state().unwrap(), 2048);
+            assert!(!garbage.is_null());
+            let mut state = T(&mut stack, &[state, D(3)]);
+            let mug = crate::mug::mug_u32(&mut stack, state);
+            pma_relocate(&mut state, &mut stack, false);
+            pma_meta_set(0, state.as_raw());
+            pma_sync();
+            pack(&mut stack, &mut state);
+            assert_eq!(crate::mug::mug_u32(&mut stack, state), mug);
+            assert!(std::fs::metadata(&file).unwrap().len() <= packed);
+        }
+        pma_close().unwrap();
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_read_only_unify() {
+        let (_guard, path) = pma_test("rdonly");
+        pma_open(path.clone()).unwrap();
+        let mut stack = NockStack::new(8 << 10 << 10, 0);
+        unsafe {
+            let big = UBig::from(3u8) << 200;
+            let copy = |stack: &mut NockStack| {
+                let atom = Atom::from_ubig(stack, &big).as_noun();
+                T(stack, &[atom, D(4)])
+            };
+            let x = copy(&mut stack);
+            let y = copy(&mut stack);
+            let mut state = T(&mut stack, &[x, y]);
+            pma_relocate(&mut state, &mut stack, false);
+            pma_meta_set(0, state.as_raw());
+            pma_sync();
+            pma_close().unwrap();
+
+            //  unifying the two copies rewrites pointers in the PMA
+            pma_open_read_only(path.clone()).unwrap();
+            let state = Noun::from_raw(pma_meta_get(0)).as_cell().unwrap();
+            assert!(pma_contains(state.to_raw_pointer(), 1));
+            let (x, y) = (state.head(), state.tail());
+            assert!(!x.slot(2).unwrap().raw_equals(y.slot(2).unwrap()));
+            let mut x_mut = x;
+            let mut y_mut = y;
+            assert!(crate::unifying_equality::unifying_equality(
+                &mut stack, &mut x_mut, &mut y_mut
+            ));
+            assert!(x.slot(2).unwrap().raw_equals(y.slot(2).unwrap()));
+            pma_close().unwrap();
+
+            //  which never reach the file
+            pma_open(path.clone()).unwrap();
+            let state = Noun::from_raw(pma_meta_get(0)).as_cell().unwrap();
+            let (x, y) = (state.head(), state.tail());
+            assert!(!x.slot(2).unwrap().raw_equals(y.slot(2).unwrap()));
+        }
+        pma_close().unwrap();
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
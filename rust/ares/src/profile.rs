@@ -0,0 +1,188 @@
+use crate::flog;
+use crate::interpreter::{mean_stacks, Context};
+use crate::mug::met3_usize;
+use crate::noun::{Atom, Noun};
+use crate::trace::{path_to_cord, TraceStack};
+use ares_macros::tas;
+use assert_no_alloc::permit_alloc;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::io::{Error, Write};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::result::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+crate::gdb!();
+
+//  not bound by the libc we pin on linux
+extern "C" {
+    fn setitimer(
+        which: libc::c_int,
+        new_value: *const libc::itimerval,
+        old_value: *mut libc::itimerval,
+    ) -> libc::c_int;
+}
+
+/// Set by SIGPROF, and cleared by the interpreter when it takes the sample
+pub static SAMPLE: AtomicBool = AtomicBool::new(false);
+
+/** Samples of the stacks nock was running on each profiling timer tick, as vere's `-P` takes.
+ *
+ * Samples are counted by their collapsed stack, root first with frames separated by `;`, and
+ * written out after each event to two files flamegraph tools accept: `spot.folded`, of the
+ * %spot and %mean hints, and `fast.folded`, of the %fast-hinted cores called.
+ */
+pub struct Profile {
+    spot_file: File,
+    fast_file: File,
+    spots: HashMap<String, u64>,
+    fasts: HashMap<String, u64>,
+}
+
+/** The first unused directory `.urb/put/profile/<n>` of the pier, to profile into */
+pub fn profile_path(pier_path: &Path) -> PathBuf {
+    let mut profile_dir_path = pier_path.to_path_buf();
+    profile_dir_path.push(".urb");
+    profile_dir_path.push("put");
+    profile_dir_path.push("profile");
+
+    let mut profile_idx = 0u32;
+    loop {
+        let prospective_path = profile_dir_path.join(profile_idx.to_string());
+        if !prospective_path.exists() {
+            return prospective_path;
+        }
+        profile_idx += 1;
+    }
+}
+
+/** Start profiling into the given directory, taking `hz` samples per second of CPU time */
+pub fn create_profile(profile_path: &Path, hz: u32) -> Result<Profile, Error> {
+    create_dir_all(profile_path)?;
+
+    let profile = Profile {
+        spot_file: File::create(profile_path.join("spot.folded"))?,
+        fast_file: File::create(profile_path.join("fast.folded"))?,
+        spots: HashMap::new(),
+        fasts: HashMap::new(),
+    };
+
+    unsafe {
+        signal_hook::low_level::register(signal_hook::consts::SIGPROF, || {
+            SAMPLE.store(true, Ordering::Relaxed)
+        })?;
+    }
+    let usec = 1_000_000 / hz.clamp(1, 1_000_000);
+    let tick = libc::timeval {
+        tv_sec: (usec / 1_000_000) as libc::time_t,
+        tv_usec: (usec % 1_000_000) as libc::suseconds_t,
+    };
+    let timer = libc::itimerval {
+        it_interval: tick,
+        it_value: tick,
+    };
+    if unsafe { setitimer(libc::ITIMER_PROF, &timer, ptr::null_mut()) } != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(profile)
+}
+
+/// Take a sample if the profiling timer has ticked since the last one.
+#[inline]
+pub(crate) fn poll(context: &mut Context) {
+    if SAMPLE.load(Ordering::Relaxed) {
+        SAMPLE.store(false, Ordering::Relaxed);
+        if context.profile.is_some() {
+            unsafe { sample(context) };
+        }
+    }
+}
+
+unsafe fn sample(context: &mut Context) {
+    let stack = &mut context.stack;
+
+    //  innermost first, until reversed
+    let mut spots: Vec<String> = permit_alloc(Vec::new);
+    for mean in mean_stacks(stack, context.hela, false) {
+        let Ok(cell) = mean.as_cell() else {
+            continue;
+        };
+        let Ok(tag) = cell.head().as_direct() else {
+            continue;
+        };
+        let dat = cell.tail();
+        let frame = match tag.data() {
+            tas!(b"spot") => {
+                let Some((path, line)) = spot(dat) else {
+                    continue;
+                };
+                let cord = path_to_cord(stack, path);
+                permit_alloc(|| format!("{}:{}", text(cord), line))
+            }
+            tas!(b"mean") => match dat.as_atom() {
+                //  traps would need running to render, so only cords are sampled
+                Ok(cord) => permit_alloc(|| text(cord)),
+                Err(_) => continue,
+            },
+            _ => continue,
+        };
+        permit_alloc(|| spots.push(frame));
+    }
+
+    let mut fasts: Vec<String> = permit_alloc(Vec::new);
+    let mut trace_stack = *(stack.local_noun_pointer(1) as *const *const TraceStack);
+    while !trace_stack.is_null() {
+        let cord = path_to_cord(stack, (*trace_stack).path);
+        permit_alloc(|| fasts.push(text(cord)));
+        trace_stack = (*trace_stack).next;
+    }
+
+    let profile = context.profile.as_mut().unwrap();
+    permit_alloc(|| {
+        count(&mut profile.spots, spots);
+        count(&mut profile.fasts, fasts);
+    });
+}
+
+/** The path and starting line of a %spot hint's [path pint] */
+fn spot(dat: Noun) -> Option<(Noun, u64)> {
+    let spot = dat.cell()?;
+    let pint = spot.tail().cell()?;
+    let line = pint.head().cell()?.head().direct()?.data();
+    Some((spot.head(), line))
+}
+
+/** A cord as a frame of a collapsed stack, in which `;` separates frames */
+fn text(cord: Atom) -> String {
+    let bytes = &cord.as_bytes()[0..met3_usize(cord)];
+    String::from_utf8_lossy(bytes).replace(';', ":")
+}
+
+fn count(samples: &mut HashMap<String, u64>, mut frames: Vec<String>) {
+    if frames.is_empty() {
+        frames.push(String::from("(none)"));
+    }
+    frames.reverse();
+    *samples.entry(frames.join(";")).or_insert(0) += 1;
+}
+
+/// Append the samples taken since this was last called to the profile files.
+pub fn write_profile(profile: &mut Profile) -> Result<(), Error> {
+    for (stack, samples) in profile.spots.drain() {
+        writeln!(profile.spot_file, "{} {}", stack, samples)?;
+    }
+    for (stack, samples) in profile.fasts.drain() {
+        writeln!(profile.fast_file, "{} {}", stack, samples)?;
+    }
+    Ok(())
+}
+
+/// Stop profiling if the profile files can't be written.
+pub fn write_profile_safe(context: &mut Context) {
+    if let Err(e) = write_profile(context.profile.as_mut().unwrap()) {
+        flog!(context, "\rserf: error writing profile: {:?}", e);
+        context.profile = None;
+    }
+}
@@ -1,3 +1,7 @@
+use crate::bytecode::Program;
+use crate::codegen::{self, Codegen};
+use crate::event_log::EventLog;
+use crate::flog::nock_fmt;
 use crate::hamt::Hamt;
 use crate::interpreter::{inc, interpret, Error, Mote};
 use crate::jets::cold::Cold;
@@ -5,34 +9,218 @@ use crate::jets::hot::{Hot, HotEntry};
 use crate::jets::list::util::{lent, zing};
 use crate::jets::nock::util::mook;
 use crate::jets::warm::Warm;
+use crate::mass::{mass_fmt, Measure, Seen};
 use crate::mem::NockStack;
 use crate::mug::*;
 use crate::newt::Newt;
-use crate::noun::{Atom, Cell, DirectAtom, Noun, Slots, D, T};
+use crate::noun::{tape, Atom, Cell, DirectAtom, IndirectAtom, Noun, Slots, D, T};
 use crate::persist::pma_meta_set;
-use crate::persist::{pma_meta_get, pma_open, pma_sync, Persist};
+use crate::persist::{
+    pma_contains, pma_meta_get, pma_open, pma_open_read_only, pma_read_only, pma_relocate,
+    pma_sync, pma_trim, pma_usage, Persist,
+};
+use crate::profile::{create_profile, profile_path, write_profile_safe};
+use crate::serialization::{cue, cue_file, jam_to_writer};
 use crate::trace::*;
+use crate::vere::event_mug;
 use crate::{flog, interpreter};
 use ares_macros::tas;
+use ibig::UBig;
 use signal_hook;
 use signal_hook::consts::SIGINT;
-use std::fs::create_dir_all;
-use std::io;
+use std::collections::HashSet;
+use std::fs::{create_dir_all, read_dir, File};
+use std::io::{self, Write};
 use std::mem::size_of;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::result::Result;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 crate::gdb!();
 
+//  vere's u3o_* flags, passed in the serf's flag bitmap argument
+const FLAG_DEBUG_RAM: u32 = 1 << 0;
+const FLAG_DEBUG_CPU: u32 = 1 << 1;
+const FLAG_CHECK_CORRUPT: u32 = 1 << 2;
+const FLAG_CHECK_FATAL: u32 = 1 << 3;
+const FLAG_VERBOSE: u32 = 1 << 4;
+const FLAG_DRYRUN: u32 = 1 << 5;
+const FLAG_QUIET: u32 = 1 << 6;
+const FLAG_HASHLESS: u32 = 1 << 7;
 const FLAG_TRACE: u32 = 1 << 8;
 
+/**
+ * Parts of the Arvo kernel, by axis, measured in turn for a %mass report. `=| soul` puts Arvo's
+ * state at axis 6, and its modules `mod=[hoon=@ud zus=vase lul=vase zen=vase van=(map term vane)]`
+ * at the end of the state.
+ */
+const ARVO_PARTS: &[(&str, u64)] = &[
+    ("kernel", 2),
+    ("kernel context", 7),
+    ("hoon", 1790),
+    ("lull", 3582),
+    ("zuse", 7166),
+    ("vanes", ARVO_VANES),
+    ("filesystem", 222),
+    ("state", 6),
+];
+
+/// Axis of the vanes, `van` in Arvo's modules
+const ARVO_VANES: u64 = 7167;
+
+/// Smallest NockStack size in bits of bytes, 16GB. Vere's loom holds the whole state, but Arvo
+/// lives in the PMA, so the NockStack is only scratch space and the king's loom size a floor.
+const DEFAULT_STACK_BITS: u32 = 34;
+
+/// Largest NockStack an event which runs out of memory is retried with, in bits of bytes, 64GB
+const DEFAULT_LOOM_MAX_BITS: u32 = 36;
+
 #[repr(usize)]
 enum BTMetaField {
     SnapshotVersion = 0,
     Snapshot = 1,
+    /// First of [MAX_GENERATIONS] slots holding the handles of retained snapshots, newest first
+    Generations = 2,
+}
+
+/// Most past epochs whose snapshots can be retained for rollback
+const MAX_GENERATIONS: usize = 16;
+
+/// Handles of the retained snapshot generations, newest first
+fn generations() -> Vec<u64> {
+    (0..MAX_GENERATIONS)
+        .map(|i| pma_meta_get(BTMetaField::Generations as usize + i))
+        .take_while(|handle| *handle != 0)
+        .collect()
+}
+
+fn set_generations(handles: &[u64]) {
+    assert!(handles.len() <= MAX_GENERATIONS);
+    for i in 0..MAX_GENERATIONS {
+        let handle = handles.get(i).copied().unwrap_or(0);
+        pma_meta_set(BTMetaField::Generations as usize + i, handle);
+    }
+}
+
+/**
+ * When the PMA is synced, making completed events durable.
+ *
+ * Except with [Commit::Save], %work and %play replies are held until the commit that covers the
+ * events they report. Replies must reach the king in order, so any other writ (e.g. a %peek)
+ * commits pending events first, as does running out of input.
+ */
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Commit {
+    /// After every %work event or %play batch
+    Event,
+    /// After this many events or milliseconds, whichever comes first
+    Batch { events: u64, millis: u64 },
+    /// Only when the king sends %live %save; replies are not held
+    Save,
+}
+
+/**
+ * Serf settings. Those which vere has no flags or arguments for are read from the environment:
+ *
+ * - `ARES_EPOCH_EVENTS`: start a new epoch every this many events (default 0: only on demand)
+ * - `ARES_GENERATIONS`: retain the snapshots of this many past epochs for rollback (default 4)
+ * - `ARES_COMMIT`: the [Commit] policy, `event`, `batch` or `save` (default `save`)
+ * - `ARES_COMMIT_EVENTS`, `ARES_COMMIT_MS`: the limits of a `batch` (default 100 and 100)
+ * - `ARES_LOOM_MAX`: grow the NockStack up to 2^this many bytes for events which run out of
+ *   memory (default 36)
+ * - `ARES_LOG`: if 1, append each event to the pier's own [EventLog] (default 0)
+ * - `ARES_LOG_SEGMENT_MB`: start a new segment of the event log at this size (default 64)
+ * - `ARES_BYTECODE`: if 1, compile formulas to bytecode rather than walking them as trees
+ *   (default 0, or 1 with `ARES_CODEGEN`)
+ * - `ARES_BYTECODE_MAX`: drop the compiled bytecode once this many formulas have been compiled
+ *   (default 100000)
+ * - `ARES_CODEGEN`: a jammed codegen gate, to linearize hot arms and lower them to bytecode with;
+ *   see [codegen] (default none)
+ * - `ARES_CODEGEN_HOT`: linearize an arm once it is called this many times (default 1000)
+ */
+struct Options {
+    epoch_events: u64,
+    generations: usize,
+    commit: Commit,
+    /// Run events without saving them to the PMA, as with vere's dry-run flag
+    dry_run: bool,
+    /// Open the PMA without write access
+    read_only: bool,
+    /// NockStack size in words
+    stack_words: usize,
+    /// Most words the NockStack may grow to
+    max_stack_words: usize,
+    /// Most entries the memo cache takes per event
+    cache_max: usize,
+    /// Keep an event log
+    log: bool,
+    /// Size at which the event log starts a new segment
+    log_segment_bytes: u64,
+    /// Run formulas as bytecode
+    bytecode: bool,
+    /// Most formulas whose bytecode is kept
+    programs_max: usize,
+    /// File of the jammed codegen gate, if arms are to be linearized
+    codegen: Option<PathBuf>,
+    /// Calls of an arm after which it is linearized
+    codegen_hot: usize,
+    /// Flog the state loaded, and each commit
+    verbose: bool,
+    /// Flog NockStack use after each event
+    debug_ram: bool,
+    /// Profiling samples taken per second of CPU time, with vere's `-P`
+    profile_hz: u32,
+    /// Check that the state saved after each event is entirely in the PMA
+    check_corrupt: bool,
+    /// Abort when a consistency check fails, rather than only flogging it
+    check_fatal: bool,
+}
+
+impl Options {
+    fn from_env() -> Options {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|val| val.parse().ok())
+                .unwrap_or(default)
+        }
+
+        let commit = match var("ARES_COMMIT", String::from("save")).as_str() {
+            "event" => Commit::Event,
+            "batch" => Commit::Batch {
+                events: var("ARES_COMMIT_EVENTS", 100),
+                millis: var("ARES_COMMIT_MS", 100),
+            },
+            "save" => Commit::Save,
+            other => panic!("serf: unknown ARES_COMMIT policy {}", other),
+        };
+        //  hot arms are only found, and their lowered bytecode only run, by the bytecode VM
+        let codegen = std::env::var_os("ARES_CODEGEN").map(PathBuf::from);
+
+        Options {
+            epoch_events: var("ARES_EPOCH_EVENTS", 0),
+            generations: var("ARES_GENERATIONS", 4).min(MAX_GENERATIONS),
+            commit,
+            dry_run: false,
+            read_only: false,
+            stack_words: 1 << (DEFAULT_STACK_BITS - 3),
+            max_stack_words: 1 << (var("ARES_LOOM_MAX", DEFAULT_LOOM_MAX_BITS).clamp(20, 47) - 3),
+            cache_max: usize::MAX,
+            log: var("ARES_LOG", 0u8) != 0,
+            log_segment_bytes: var("ARES_LOG_SEGMENT_MB", 64u64) << 20,
+            bytecode: var("ARES_BYTECODE", 0u8) != 0 || codegen.is_some(),
+            programs_max: var("ARES_BYTECODE_MAX", 100_000usize).max(1),
+            codegen,
+            codegen_hot: var("ARES_CODEGEN_HOT", 1000usize).max(1),
+            verbose: false,
+            debug_ram: false,
+            profile_hz: var("ARES_PROFILE_HZ", 100u32).max(1),
+            check_corrupt: false,
+            check_fatal: false,
+        }
+    }
 }
 struct Snapshot(pub *mut SnapshotMem);
 
@@ -42,6 +230,8 @@ impl Persist for Snapshot {
         let mut cold = (*(self.0)).cold;
         let arvo_space_needed = arvo.space_needed(stack);
         let cold_space_needed = cold.space_needed(stack);
+        (*(self.0)).arvo = arvo;
+        (*(self.0)).cold = cold;
         (((size_of::<SnapshotMem>() + 7) >> 3) << 3) + arvo_space_needed + cold_space_needed
     }
 
@@ -69,6 +259,83 @@ impl Persist for Snapshot {
     }
 }
 
+impl Snapshot {
+    /**
+     * Find the snapshot saved in the PMA, migrating it to the current layout if it was saved by an
+     * older serf. Returns None for a fresh PMA.
+     *
+     * A migrated snapshot is saved and synced before it is returned, so that it is only migrated
+     * once. The old [SnapshotMem] is left in the PMA until the next %pack or %meld frees it.
+     *
+     * A snapshot saved by a newer serf is an error, rather than misread.
+     */
+    unsafe fn load(
+        stack: &mut NockStack,
+        version: u64,
+        handle: u64,
+    ) -> io::Result<Option<Snapshot>> {
+        Snapshot::load_with(stack, version, handle, &SNAPSHOT_MIGRATIONS)
+    }
+
+    /// [Snapshot::load] with a given table of migrations, whose length sets the current version
+    unsafe fn load_with(
+        stack: &mut NockStack,
+        version: u64,
+        handle: u64,
+        migrations: &[SnapshotMigration],
+    ) -> io::Result<Option<Snapshot>> {
+        let current = migrations.len() as u64 + 1;
+        if version == 0 {
+            return Ok(None);
+        }
+        if version > current {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "snapshot version {} is newer than this serf supports ({})",
+                    version, current
+                ),
+            ));
+        }
+        if version < current && pma_read_only() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "snapshot version {} must be migrated, which a read-only PMA can't be",
+                    version
+                ),
+            ));
+        }
+
+        // Retained generations were saved with the same layout, so they are migrated too
+        let mut handle = handle;
+        let mut retained = generations();
+        for migrate in &migrations[(version - 1) as usize..] {
+            handle = migrate(stack, handle);
+            for generation in retained.iter_mut() {
+                *generation = migrate(stack, *generation);
+            }
+        }
+        if version < current {
+            pma_meta_set(BTMetaField::SnapshotVersion as usize, current);
+            pma_meta_set(BTMetaField::Snapshot as usize, handle);
+            set_generations(&retained);
+            pma_sync();
+        }
+
+        Ok(Some(Snapshot::handle_from_u64(handle)))
+    }
+}
+
+/**
+ * The snapshot layout for [PMA_CURRENT_SNAPSHOT_VERSION].
+ *
+ * Snapshots saved by older serfs are upgraded when the PMA is loaded, so changing this layout
+ * takes three steps:
+ * - copy the current definition to a `SnapshotMemV<n>` struct, where n is the current version
+ * - bump [PMA_CURRENT_SNAPSHOT_VERSION] and change this struct
+ * - append a migration from `SnapshotMemV<n>` to [SNAPSHOT_MIGRATIONS]
+ */
 #[repr(C)]
 #[repr(packed)]
 struct SnapshotMem {
@@ -80,57 +347,331 @@ struct SnapshotMem {
 
 const PMA_CURRENT_SNAPSHOT_VERSION: u64 = 1;
 
+/// The current snapshot and the retained generations, so they can be relocated together
+struct Generations {
+    current: Snapshot,
+    retained: Vec<Snapshot>,
+}
+
+impl Persist for Generations {
+    unsafe fn space_needed(&mut self, stack: &mut NockStack) -> usize {
+        let mut bytes = self.current.space_needed(stack);
+        for snapshot in self.retained.iter_mut() {
+            bytes += snapshot.space_needed(stack);
+        }
+        bytes
+    }
+
+    unsafe fn copy_to_buffer(&mut self, stack: &mut NockStack, buffer: &mut *mut u8) {
+        self.current.copy_to_buffer(stack, buffer);
+        for snapshot in self.retained.iter_mut() {
+            snapshot.copy_to_buffer(stack, buffer);
+        }
+    }
+
+    unsafe fn handle_to_u64(&self) -> u64 {
+        self.current.handle_to_u64()
+    }
+
+    unsafe fn handle_from_u64(meta_handle: u64) -> Self {
+        Generations {
+            current: Snapshot::handle_from_u64(meta_handle),
+            retained: generations()
+                .into_iter()
+                .map(|handle| Snapshot::handle_from_u64(handle))
+                .collect(),
+        }
+    }
+}
+
+/**
+ * Upgrade a snapshot saved in the PMA from one layout to the next.
+ *
+ * A migration is passed the handle of a snapshot of layout n, reads the old `SnapshotMemV<n>`,
+ * and returns the handle of an equivalent snapshot of layout n+1 saved into the PMA. The new
+ * struct can be built on the NockStack and saved with [Persist::save_to_pma]: nouns the old
+ * snapshot points to are already in the PMA, so they are not copied again.
+ */
+type SnapshotMigration = unsafe fn(&mut NockStack, u64) -> u64;
+
+/// Migrations in order: the entry at index n - 1 upgrades snapshot version n to n + 1
+const SNAPSHOT_MIGRATIONS: [SnapshotMigration; (PMA_CURRENT_SNAPSHOT_VERSION - 1) as usize] = [];
+
 struct Context {
     epoch: u64,
     event_num: u64,
     arvo: Noun,
     mug: u32,
+    options: Options,
+    /// Events saved to the PMA since it was last synced
+    uncommitted: u64,
+    /// When the first of the uncommitted events was saved
+    uncommitted_since: Instant,
+    /// NockStacks replaced by [Context::grow], which the state may still point into
+    retired: Vec<NockStack>,
+    /// Jets of the hot state, to rebuild it on a new NockStack
+    hot_state: Vec<HotEntry>,
+    /// The event log, if one is kept; see [Context::open_log]
+    log: Option<EventLog>,
     nock_context: interpreter::Context,
 }
 
 impl Context {
     pub fn load(
         snap_path: PathBuf,
+        newt: Newt,
         trace_info: Option<TraceInfo>,
+        options: Options,
         constant_hot_state: &[HotEntry],
     ) -> Context {
-        pma_open(snap_path).expect("serf: pma open failed");
+        if options.read_only {
+            pma_open_read_only(snap_path).expect("serf: read-only pma open failed");
+        } else {
+            pma_open(snap_path).expect("serf: pma open failed");
+        }
 
         let snapshot_version = pma_meta_get(BTMetaField::SnapshotVersion as usize);
+        let snapshot_handle = pma_meta_get(BTMetaField::Snapshot as usize);
+        if snapshot_handle == 0 && !options.read_only {
+            //  a new PMA can only be opened again once it has been synced
+            pma_sync();
+        }
 
-        let snapshot = match snapshot_version {
-            0 => None,
-            1 => Some(unsafe {
-                Snapshot::handle_from_u64(pma_meta_get(BTMetaField::Snapshot as usize))
-            }),
-            _ => panic!("Unsupported snapshot version"),
+        let mut context = Context::new(
+            newt,
+            trace_info,
+            options,
+            snapshot_version,
+            snapshot_handle,
+            constant_hot_state,
+        );
+        context.load_codegen();
+        context
+    }
+
+    /**
+     * Load the codegen gate, if `ARES_CODEGEN` names one. Where Arvo binds an upgraded gate at
+     * [codegen::SCRY_SPUR], that is used instead. The code table starts out empty.
+     */
+    fn load_codegen(&mut self) {
+        let Some(path) = self.options.codegen.clone() else {
+            return;
         };
+        let hot = self.options.codegen_hot;
+        let codegen = match self.scry_codegen() {
+            Some(gate) => Ok(Codegen::new(&mut self.nock_context.stack, gate, hot)),
+            None => Codegen::load(&mut self.nock_context.stack, &path, hot),
+        };
+        match codegen {
+            Ok(codegen) => self.nock_context.codegen = codegen,
+            Err(e) => {
+                flog!(&mut self.nock_context, "\r codegen: {}", e);
+            }
+        }
+    }
+
+    /// The codegen gate bound in Arvo's %base desk, if there is one
+    fn scry_codegen(&mut self) -> Option<Noun> {
+        if self.event_num == 0 {
+            return None;
+        }
+        let stack = &mut self.nock_context.stack;
+        let mut spur = D(0);
+        for knot in codegen::SCRY_SPUR.iter().rev() {
+            spur = T(stack, &[D(*knot), spur]);
+        }
+        //  [lyc=[~ ~] %| %once %cx %base spur], as vere peeks
+        let lyc = T(stack, &[D(0), D(0)]);
+        let sam = T(
+            stack,
+            &[
+                lyc,
+                D(1),
+                D(tas!(b"once")),
+                D(tas!(b"cx")),
+                D(tas!(b"base")),
+                spur,
+            ],
+        );
+        //  (unit (cask)), with the jam as the noun of the %jam mark
+        let res = slam(self, PEEK_AXIS, sam).ok()?;
+        let jam = res
+            .as_cell()
+            .ok()?
+            .tail()
+            .as_cell()
+            .ok()?
+            .tail()
+            .as_atom()
+            .ok()?;
+        let gate = cue(&mut self.nock_context.stack, jam);
+        codegen::is_gate(gate).then_some(gate)
+    }
 
-        Context::new(trace_info, snapshot, constant_hot_state)
+    /**
+     * Open the pier's event log, if one is kept. Events after the loaded state were never
+     * committed, and will be run again, so they are removed.
+     */
+    pub fn open_log(&mut self, pier_path: &Path) -> io::Result<()> {
+        if !self.options.log || self.options.dry_run {
+            return Ok(());
+        }
+        let mut log = EventLog::open(pier_path, self.options.log_segment_bytes)?;
+        log.truncate(self.event_num)?;
+        self.log = Some(log);
+        Ok(())
     }
 
     pub unsafe fn save(&mut self) {
-        let handle = {
-            let mut snapshot = Snapshot({
-                let snapshot_mem_ptr: *mut SnapshotMem = self.nock_context.stack.struct_alloc(1);
-
-                // Save into PMA (does not sync)
-                (*snapshot_mem_ptr).epoch = self.epoch;
-                (*snapshot_mem_ptr).event_num = self.event_num;
-                (*snapshot_mem_ptr).arvo = self.arvo;
-                (*snapshot_mem_ptr).cold = self.nock_context.cold;
-                snapshot_mem_ptr
-            });
-
-            let handle = snapshot.save_to_pma(&mut self.nock_context.stack);
-
-            self.epoch = (*snapshot.0).epoch;
-            self.arvo = (*snapshot.0).arvo;
-            self.event_num = (*snapshot.0).event_num;
-            self.nock_context.cold = (*snapshot.0).cold;
-
-            handle
+        let mut snapshot = self.snapshot();
+        let handle = snapshot.save_to_pma(&mut self.nock_context.stack);
+        self.update_snapshot(snapshot, handle);
+    }
+
+    /**
+     * Deduplicate the snapshot: each structurally equal noun reachable from Arvo or the cold
+     * state is stored once in the PMA, and all other allocations are freed.
+     *
+     * ## Safety
+     *
+     * Invalidates all nouns not in the context; see [relocate].
+     */
+    pub unsafe fn meld(&mut self) {
+        self.relocate(true);
+        pma_sync();
+    }
+
+    /**
+     * Compact the PMA: everything reachable from the snapshot is moved into one contiguous
+     * allocation, everything else is freed, and the file is shrunk.
+     *
+     * The snapshot is relocated twice. The first copy must be allocated while the old pages are
+     * still in use, so it is the second which moves into the space they leave free at the front
+     * of the file.
+     *
+     * ## Safety
+     *
+     * Invalidates all nouns not in the context; see [relocate].
+     */
+    pub unsafe fn pack(&mut self) -> io::Result<()> {
+        self.relocate(false);
+        pma_sync();
+        self.relocate(false);
+        pma_sync();
+        pma_trim()
+    }
+
+    /**
+     * Copy the snapshot and the retained generations into a new PMA allocation and free all
+     * others (does not sync).
+     *
+     * ## Safety
+     *
+     * Invalidates all nouns not in the context. The warm state is rebuilt to match, and the
+     * cache and scry stack are reset.
+     */
+    unsafe fn relocate(&mut self, meld: bool) {
+        let mut generations = Generations {
+            current: self.snapshot(),
+            retained: generations()
+                .into_iter()
+                .map(|handle| Snapshot::handle_from_u64(handle))
+                .collect(),
         };
+        let handle = pma_relocate(&mut generations, &mut self.nock_context.stack, meld);
+        let retained: Vec<u64> = generations
+            .retained
+            .iter()
+            .map(|snapshot| snapshot.handle_to_u64())
+            .collect();
+        set_generations(&retained);
+        self.update_snapshot(generations.current, handle);
+        self.reset_warm();
+    }
+
+    /// Rebuild the warm state from the cold state, and reset the cache, compiled bytecode, and
+    /// scry stack
+    unsafe fn reset_warm(&mut self) {
+        let stack = &mut self.nock_context.stack;
+        self.nock_context.warm =
+            Warm::init(stack, &mut self.nock_context.cold, &self.nock_context.hot);
+        self.nock_context.cache_reset();
+        self.nock_context.programs_reset();
+        self.nock_context.scry_stack = D(0);
+    }
+
+    /**
+     * Start a new epoch. The saved snapshot, which ends the current epoch, is retained as a
+     * generation for [rollback], and the oldest generations beyond the configured number are
+     * dropped (their memory is freed by the next %pack or %meld). Does not sync.
+     *
+     * ## Safety
+     *
+     * Calls save(); see [event_update].
+     */
+    pub unsafe fn roll(&mut self, epoch: u64) {
+        let handle = pma_meta_get(BTMetaField::Snapshot as usize);
+        if handle != 0 {
+            let mut retained = generations();
+            retained.insert(0, handle);
+            retained.truncate(self.options.generations);
+            set_generations(&retained);
+        }
+        self.epoch = epoch;
+        self.save();
+    }
+
+    /**
+     * Restore the state at the end of a retained epoch, and sync.
+     *
+     * Generations newer than the restored one are dropped. Epoch numbers are not reused, so the
+     * restored state starts a new epoch numbered after the current one, and the restored
+     * generation is retained so it can be rolled back to again.
+     *
+     * ## Safety
+     *
+     * Invalidates all nouns not in the context.
+     */
+    pub unsafe fn rollback(&mut self, epoch: u64) -> io::Result<()> {
+        let retained = generations();
+        let index = retained
+            .iter()
+            .position(|handle| (*Snapshot::handle_from_u64(*handle).0).epoch == epoch)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("rollback: epoch {} is not retained", epoch),
+                )
+            })?;
+
+        let next_epoch = self.epoch + 1;
+        set_generations(&retained[index + 1..]);
+        self.update_snapshot(Snapshot::handle_from_u64(retained[index]), retained[index]);
+        self.reset_warm();
+        self.mug = mug_u32(&mut self.nock_context.stack, self.arvo);
+        self.roll(next_epoch);
+        pma_sync();
+        Ok(())
+    }
+
+    /// Build a snapshot of the current state on the NockStack
+    unsafe fn snapshot(&mut self) -> Snapshot {
+        let snapshot_mem_ptr: *mut SnapshotMem = self.nock_context.stack.struct_alloc(1);
+
+        (*snapshot_mem_ptr).epoch = self.epoch;
+        (*snapshot_mem_ptr).event_num = self.event_num;
+        (*snapshot_mem_ptr).arvo = self.arvo;
+        (*snapshot_mem_ptr).cold = self.nock_context.cold;
+        Snapshot(snapshot_mem_ptr)
+    }
+
+    /// Point the context and the PMA metadata at a snapshot saved into the PMA (does not sync)
+    unsafe fn update_snapshot(&mut self, snapshot: Snapshot, handle: u64) {
+        self.epoch = (*snapshot.0).epoch;
+        self.arvo = (*snapshot.0).arvo;
+        self.event_num = (*snapshot.0).event_num;
+        self.nock_context.cold = (*snapshot.0).cold;
+
         pma_meta_set(
             BTMetaField::SnapshotVersion as usize,
             PMA_CURRENT_SNAPSHOT_VERSION,
@@ -139,16 +680,20 @@ impl Context {
     }
 
     fn new(
+        newt: Newt,
         trace_info: Option<TraceInfo>,
-        snapshot: Option<Snapshot>,
+        options: Options,
+        snapshot_version: u64,
+        snapshot_handle: u64,
         constant_hot_state: &[HotEntry],
     ) -> Self {
-        let mut stack = NockStack::new(2048 << 10 << 10, 0);
-        let newt = Newt::new();
+        let mut stack = NockStack::new(options.stack_words, 0);
         let cache = Hamt::<Noun>::new(&mut stack);
 
         let (epoch, event_num, arvo, mut cold) = unsafe {
-            match snapshot {
+            match Snapshot::load(&mut stack, snapshot_version, snapshot_handle)
+                .expect("serf: snapshot load failed")
+            {
                 Some(snapshot) => (
                     (*(snapshot.0)).epoch,
                     (*(snapshot.0)).event_num,
@@ -158,6 +703,7 @@ impl Context {
                 None => (0, 0, D(0), Cold::new(&mut stack)),
             }
         };
+        let programs = Hamt::<Program>::new(&mut stack);
 
         let hot = Hot::init(&mut stack, constant_hot_state);
         let warm = Warm::init(&mut stack, &mut cold, &hot);
@@ -170,8 +716,18 @@ impl Context {
             warm,
             hot,
             cache,
+            cache_max: options.cache_max,
+            cache_len: 0,
             scry_stack: D(0),
             trace_info,
+            bytecode: options.bytecode,
+            programs,
+            programs_max: options.programs_max,
+            programs_len: 0,
+            codegen: Codegen::none(),
+            bouts: Vec::new(),
+            hela: None,
+            profile: None,
         };
 
         Context {
@@ -179,6 +735,12 @@ impl Context {
             event_num,
             arvo,
             mug,
+            options,
+            uncommitted: 0,
+            uncommitted_since: Instant::now(),
+            retired: Vec::new(),
+            hot_state: constant_hot_state.to_vec(),
+            log: None,
             nock_context,
         }
     }
@@ -192,17 +754,87 @@ impl Context {
     ///
     /// calls save(), which invalidates all nouns not in the context
     /// until [preserve_event_update_leftovers] is called to resolve forwarding pointers.
-    pub unsafe fn event_update(&mut self, new_event_num: u64, new_arvo: Noun) {
-        //  XX: assert event numbers are continuous
+    ///
+    /// `job` is the event, if it is to be appended to the event log.
+    pub unsafe fn event_update(&mut self, new_event_num: u64, new_arvo: Noun, job: Option<Noun>) {
+        //  only the lifecycle batch may advance by more than one event
+        assert!(
+            new_event_num == self.event_num + 1 || (self.event_num == 0 && new_event_num > 0),
+            "serf: event_update: discontinuous event number {} after {}",
+            new_event_num,
+            self.event_num
+        );
         self.arvo = new_arvo;
         self.event_num = new_event_num;
-        self.save();
 
-        self.nock_context.cache = Hamt::new(&mut self.nock_context.stack);
+        //  jammed before the save can move the event
+        let jam = match job {
+            Some(job) if self.log.is_some() => {
+                let mut jam = Vec::new();
+                jam_to_writer(&mut self.nock_context.stack, job, &mut jam)
+                    .expect("serf: event log: jam failed");
+                Some(jam)
+            }
+            _ => None,
+        };
+
+        //  in a dry run, the new state is only kept on the NockStack
+        if !self.options.dry_run {
+            self.save();
+
+            if self.uncommitted == 0 {
+                self.uncommitted_since = Instant::now();
+            }
+            self.uncommitted += 1;
+
+            //  no remainder by 0, so epochs are never rolled then
+            if self.event_num.checked_rem(self.options.epoch_events) == Some(0) {
+                self.roll(self.epoch + 1);
+            }
+
+            if self.options.check_corrupt {
+                self.check_saved();
+            }
+        }
+
+        if self.options.debug_ram {
+            let size = self.nock_context.stack.size();
+            let used = size - self.nock_context.stack.free();
+            flog!(
+                &mut self.nock_context,
+                "\r debug-ram: eve {}: {} KB of the {} MB NockStack in use",
+                new_event_num,
+                used >> 7,
+                size >> 17
+            );
+        }
+
+        if self.nock_context.profile.is_some() {
+            write_profile_safe(&mut self.nock_context);
+        }
+
+        self.nock_context.cache_reset();
         self.nock_context.scry_stack = D(0);
 
         // XX save to PMA
         self.mug = mug_u32(&mut self.nock_context.stack, self.arvo);
+
+        if let (Some(log), Some(jam)) = (self.log.as_mut(), jam) {
+            if let Err(e) = log.append(self.event_num, self.mug, &jam) {
+                self.log_failed(e);
+            }
+        }
+    }
+
+    /// Stop keeping the event log after it failed, rather than lose the events it can't hold
+    fn log_failed(&mut self, e: io::Error) {
+        self.log = None;
+        flog!(
+            &mut self.nock_context,
+            "\r event log: eve {}: {}; no longer kept",
+            self.event_num,
+            e
+        );
     }
 
     ///
@@ -213,16 +845,233 @@ impl Context {
     /// [event_update] and invocation of this function
     pub unsafe fn preserve_event_update_leftovers(&mut self) {
         let stack = &mut self.nock_context.stack;
+        if self.options.dry_run {
+            stack.preserve(&mut self.arvo);
+            stack.preserve(&mut self.nock_context.cold);
+        }
         stack.preserve(&mut self.nock_context.warm);
         stack.preserve(&mut self.nock_context.hot);
+        stack.preserve(&mut self.nock_context.programs);
+        stack.preserve(&mut self.nock_context.codegen);
         stack.flip_top_frame(0);
     }
 
+    /// Check that all of Arvo was copied to the PMA by the last save
+    fn check_saved(&mut self) {
+        let mut seen = HashSet::new();
+        let mut work = vec![self.arvo];
+        let mut outside = 0usize;
+        while let Some(noun) = work.pop() {
+            if let Ok(allocated) = noun.as_allocated() {
+                if !seen.insert(unsafe { noun.as_raw() }) {
+                    continue;
+                }
+                if !unsafe { pma_contains(allocated.to_raw_pointer(), 1) } {
+                    outside += 1;
+                } else if let Some(cell) = allocated.cell() {
+                    work.push(cell.tail());
+                    work.push(cell.head());
+                }
+            }
+        }
+        if outside != 0 {
+            self.check_failed(format!(
+                "check-corrupt: eve {}: {} nouns of Arvo are not in the PMA",
+                self.event_num, outside
+            ));
+        }
+    }
+
+    /// Report a failed consistency check, and abort if vere asked for such failures to be fatal
+    fn check_failed(&mut self, failure: String) {
+        if self.options.check_fatal {
+            panic!("serf: {}", failure);
+        }
+        flog!(&mut self.nock_context, "\r {}", failure);
+    }
+
+    /// Sync the PMA, and send the replies held until the events they report were durable
+    pub fn commit(&mut self) {
+        if self.options.verbose && self.uncommitted != 0 {
+            flog!(
+                &mut self.nock_context,
+                "\r commit: eve {}, {} events",
+                self.event_num,
+                self.uncommitted
+            );
+        }
+        //  the log is synced first, so it always covers the PMA
+        if let Some(log) = self.log.as_mut() {
+            if let Err(e) = log.sync() {
+                self.log_failed(e);
+            }
+        }
+        pma_sync();
+        self.uncommitted = 0;
+        self.nock_context.newt.release();
+    }
+
+    /// Commit if replies are held, i.e. if the commit policy delays them
+    pub fn flush(&mut self) {
+        if self.nock_context.newt.holding() {
+            self.commit();
+        }
+    }
+
+    /// Commit if the commit policy calls for it after a writ
+    pub fn commit_if_due(&mut self) {
+        match self.options.commit {
+            Commit::Event => self.flush(),
+            Commit::Batch { events, millis } => {
+                if self.uncommitted >= events
+                    || self.uncommitted_since.elapsed() >= Duration::from_millis(millis)
+                {
+                    self.flush();
+                }
+            }
+            Commit::Save => {}
+        }
+    }
+
+    /// Move to a NockStack twice as large, after an event ran out of memory. Fails if that
+    /// would exceed the maximum, or can't be mapped.
+    ///
+    /// The old NockStack is retired rather than freed, since the event and the state may still
+    /// point into it, but the jet state is rebuilt on the new one.
+    pub fn grow(&mut self) -> bool {
+        let words = self.nock_context.stack.size() << 1;
+        if words > self.options.max_stack_words {
+            return false;
+        }
+        let stack = match NockStack::try_new(words, 0) {
+            Ok(stack) => stack,
+            Err(e) => {
+                flog!(
+                    &mut self.nock_context,
+                    "\r serf: could not map a {} MB NockStack: {}",
+                    words >> 17,
+                    e
+                );
+                return false;
+            }
+        };
+        let old = std::mem::replace(&mut self.nock_context.stack, stack);
+        self.retired.push(old);
+        self.options.stack_words = words;
+
+        let stack = &mut self.nock_context.stack;
+        self.nock_context.hot = Hot::init(stack, &self.hot_state);
+        self.nock_context.warm =
+            Warm::init(stack, &mut self.nock_context.cold, &self.nock_context.hot);
+        self.nock_context.cache_reset();
+        self.nock_context.programs_reset();
+        self.nock_context.scry_stack = D(0);
+        self.nock_context.codegen = Codegen::none();
+        self.load_codegen();
+        flog!(
+            &mut self.nock_context,
+            "\r serf: out of memory, retrying with a {} MB NockStack",
+            words >> 17
+        );
+        true
+    }
+
+    /// Free the NockStacks retired by [grow], once all of the state is in the PMA. In a dry run
+    /// it never is, so they are kept.
+    pub fn free_retired(&mut self) {
+        if !self.options.dry_run && self.event_num > 0 {
+            self.retired.clear();
+        }
+    }
+
+    /// Report memory use for %mass, a line at a time
+    pub fn mass(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = |depth: usize, label: &str, bytes: usize| {
+            lines.push(format!(
+                "{}{}: {}",
+                "  ".repeat(depth),
+                label,
+                mass_fmt(bytes)
+            ));
+        };
+
+        //  memory shared between parts is counted for the first of them
+        let mut seen = Seen::new();
+        unsafe {
+            line(0, "arvo", self.arvo.measure(&mut Seen::new()));
+            for (label, axis) in ARVO_PARTS {
+                let Ok(part) = self.arvo.slot(*axis) else {
+                    continue;
+                };
+                let vanes = if *axis == ARVO_VANES {
+                    map_entries(part)
+                } else {
+                    None
+                };
+                match vanes {
+                    Some(vanes) => {
+                        let mut sizes = Vec::new();
+                        for (name, vane) in vanes {
+                            sizes.push((name, vane.measure(&mut seen)));
+                        }
+                        line(1, label, sizes.iter().map(|(_, bytes)| bytes).sum());
+                        for (name, bytes) in sizes {
+                            line(2, &name, bytes);
+                        }
+                    }
+                    None => line(1, label, part.measure(&mut seen)),
+                }
+            }
+            line(1, "other", self.arvo.measure(&mut seen));
+
+            line(0, "cold state", self.nock_context.cold.measure(&mut seen));
+            line(0, "warm state", self.nock_context.warm.measure(&mut seen));
+            line(0, "memo cache", self.nock_context.cache.measure(&mut seen));
+            for handle in generations() {
+                let snapshot = &*Snapshot::handle_from_u64(handle).0;
+                let (arvo, cold) = (snapshot.arvo, snapshot.cold);
+                let bytes = arvo.measure(&mut seen) + cold.measure(&mut seen);
+                line(
+                    0,
+                    &format!("epoch {} (retained)", { snapshot.epoch }),
+                    bytes,
+                );
+            }
+        }
+
+        let stack = &self.nock_context.stack;
+        lines.push(format!(
+            "NockStack: {} in use, {} at most, of {}",
+            mass_fmt((stack.size() - stack.free()) << 3),
+            mass_fmt(stack.high_water() << 3),
+            mass_fmt(stack.size() << 3)
+        ));
+        match pma_usage() {
+            Ok(usage) => {
+                let data = format!("{} in use", mass_fmt(usage.data));
+                let free = format!("{} free", mass_fmt(usage.free));
+                lines.push(format!(
+                    "PMA: {}, {}, of {}",
+                    data,
+                    free,
+                    mass_fmt(usage.file)
+                ));
+            }
+            Err(e) => lines.push(format!("PMA: {}", e)),
+        }
+        lines
+    }
+
     //
     // Newt functions
     //
 
+    /// Read the next writ, first committing a batch if the king has nothing more queued
     pub fn next(&mut self) -> Option<Noun> {
+        if self.nock_context.newt.holding() && !self.nock_context.newt.wait(Duration::ZERO) {
+            self.commit();
+        }
         self.nock_context.newt.next(&mut self.nock_context.stack)
     }
 
@@ -244,6 +1093,12 @@ impl Context {
             .peek_done(&mut self.nock_context.stack, dat);
     }
 
+    pub fn peek_bail(&mut self, dud: Noun) {
+        self.nock_context
+            .newt
+            .peek_bail(&mut self.nock_context.stack, dud);
+    }
+
     pub fn play_done(&mut self) {
         self.nock_context
             .newt
@@ -289,12 +1144,15 @@ impl Context {
 const LOAD_AXIS: u64 = 4;
 const PEEK_AXIS: u64 = 22;
 const POKE_AXIS: u64 = 23;
-#[allow(dead_code)]
 const WISH_AXIS: u64 = 10;
 
 // Necessary because Arc::new is not const
 lazy_static! {
     pub static ref TERMINATOR: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    /// Set by the watchdog once a %work or %peek deadline passes. Kept apart from [TERMINATOR] so
+    /// that a SIGINT during a timed-out event still finds it unset, and interrupts, not shuts down
+    pub static ref TIMEOUT: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    static ref DEADLINE: (Mutex<Option<Instant>>, Condvar) = (Mutex::new(None), Condvar::new());
 }
 
 /**
@@ -305,15 +1163,13 @@ pub fn serf(constant_hot_state: &[HotEntry]) -> io::Result<()> {
     // Register SIGINT signal hook to set flag first time, shutdown second time
     signal_hook::flag::register_conditional_shutdown(SIGINT, 1, Arc::clone(&TERMINATOR))?;
     signal_hook::flag::register(SIGINT, Arc::clone(&TERMINATOR))?;
+    start_watchdog();
 
     let pier_path_string = std::env::args()
         .nth(2)
         .ok_or(io::Error::new(io::ErrorKind::Other, "no pier path"))?;
     let pier_path = PathBuf::from(pier_path_string);
-    let mut snap_path = pier_path.clone();
-    snap_path.push(".urb");
-    snap_path.push("chk");
-    create_dir_all(&snap_path)?;
+    let snap_path = chk_path(&pier_path);
 
     let wag: u32 = std::env::args()
         .nth(4)
@@ -325,7 +1181,7 @@ pub fn serf(constant_hot_state: &[HotEntry]) -> io::Result<()> {
         )))?;
 
     let mut trace_info = if wag & FLAG_TRACE != 0 {
-        create_trace_file(pier_path).ok()
+        create_trace_file(pier_path.clone()).ok()
     } else {
         None
     };
@@ -339,22 +1195,125 @@ pub fn serf(constant_hot_state: &[HotEntry]) -> io::Result<()> {
         }
     }
 
-    let mut context = Context::load(snap_path, trace_info, constant_hot_state);
+    //  vere also passes the event number to replay up to, which it enforces itself
+    let hap: Option<usize> = serf_arg(5, "memo cache size")?;
+    let lom: Option<u32> = serf_arg(6, "loom size")?;
+
+    let mut options = Options::from_env();
+    if let Some(bits) = lom {
+        if !(20..48).contains(&bits) {
+            return Err(io::Error::other(format!("loom size {} out of range", bits)));
+        }
+        options.stack_words = options.stack_words.max(1 << (bits - 3));
+    }
+    //  0 leaves the memo cache uncapped, as in vere
+    options.cache_max = match hap {
+        Some(0) | None => usize::MAX,
+        Some(hap) => hap,
+    };
+    options.verbose = wag & FLAG_VERBOSE != 0;
+    options.debug_ram = wag & FLAG_DEBUG_RAM != 0;
+    options.check_corrupt = wag & FLAG_CHECK_CORRUPT != 0;
+    options.check_fatal = wag & FLAG_CHECK_FATAL != 0;
+
+    if wag & FLAG_DRYRUN != 0 {
+        //  run against the existing state without changing it, so there is nothing to commit
+        options.dry_run = true;
+        options.read_only = true;
+        options.commit = Commit::Save;
+    } else {
+        create_dir_all(&snap_path)?;
+    }
+
+    let mut newt = Newt::new();
+    newt.set_quiet(wag & FLAG_QUIET != 0);
+
+    let mut context = Context::load(snap_path, newt, trace_info, options, constant_hot_state);
+    if wag & FLAG_DEBUG_CPU != 0 {
+        match create_profile(&profile_path(&pier_path), context.options.profile_hz) {
+            Ok(profile) => context.nock_context.profile = Some(profile),
+            Err(e) => {
+                flog!(
+                    &mut context.nock_context,
+                    "\r serf: could not start profiling: {}",
+                    e
+                );
+            }
+        }
+    }
+    if let Err(e) = context.open_log(&pier_path) {
+        context.log_failed(e);
+    }
     context.ripe();
 
+    if context.options.verbose {
+        let stack_words = context.options.stack_words;
+        flog!(
+            &mut context.nock_context,
+            "\r serf: loaded eve {} mug {:x} in epoch {}, with a {} MB NockStack",
+            context.event_num,
+            context.mug,
+            context.epoch,
+            stack_words >> 17
+        );
+        if wag & FLAG_HASHLESS != 0 {
+            //  ares hashes no batteries, so there are none to skip
+            flog!(
+                &mut context.nock_context,
+                "\r serf: no battery hashes to skip, ignoring -S"
+            );
+        }
+    }
+
     // Can't use for loop because it borrows newt
     while let Some(writ) = context.next() {
         // Reset the local cache and scry handler stack
-        context.nock_context.cache = Hamt::<Noun>::new(&mut context.nock_context.stack);
+        context.nock_context.cache_reset();
         context.nock_context.scry_stack = D(0);
 
         let tag = slot(writ, 2)?.as_direct().unwrap();
+        if tag.data() == tas!(b"work") || tag.data() == tas!(b"play") {
+            if context.options.commit != Commit::Save {
+                context.nock_context.newt.hold();
+            }
+        } else {
+            //  keep replies in order
+            context.flush();
+        }
+
         match tag.data() {
             tas!(b"live") => {
                 let inner = slot(writ, 6)?.as_direct().unwrap();
                 match inner.data() {
                     tas!(b"cram") => {
-                        flog!(&mut context.nock_context, "\r %cram: not implemented");
+                        let eve = slot(writ, 7)?.as_atom().ok().and_then(|a| a.as_u64().ok());
+                        if eve != Some(context.event_num) {
+                            flog!(
+                                &mut context.nock_context,
+                                "\r %cram: event number mismatch (at {})",
+                                context.event_num
+                            );
+                        } else {
+                            match cram(&mut context, &pier_path) {
+                                Ok(bytes) => {
+                                    flog!(
+                                        &mut context.nock_context,
+                                        "\r %cram: eve {} mug {:x}, {} bytes",
+                                        context.event_num,
+                                        context.mug,
+                                        bytes
+                                    );
+                                }
+                                Err(e) => {
+                                    flog!(&mut context.nock_context, "\r %cram: failed: {}", e);
+                                }
+                            }
+                        }
+                    }
+                    tas!(b"mass") => {
+                        for line in context.mass() {
+                            flog!(&mut context.nock_context, "\r {}", line);
+                        }
                     }
                     tas!(b"exit") => {
                         flog!(&mut context.nock_context, "\r %exit");
@@ -362,13 +1321,31 @@ pub fn serf(constant_hot_state: &[HotEntry]) -> io::Result<()> {
                     }
                     tas!(b"save") => {
                         // XX what is eve for?
-                        pma_sync();
+                        context.commit();
                     }
-                    tas!(b"meld") => {
-                        flog!(&mut context.nock_context, "\r %meld: not implemented");
+                    tas!(b"meld") | tas!(b"pack") if context.options.dry_run => {
+                        flog!(
+                            &mut context.nock_context,
+                            "\r {}: skipped in dry run",
+                            inner
+                        );
                     }
+                    tas!(b"meld") => unsafe {
+                        context.meld();
+                        context.preserve_event_update_leftovers();
+                    },
                     tas!(b"pack") => {
-                        flog!(&mut context.nock_context, "\r %pack: not implemented");
+                        let res = unsafe { context.pack() };
+                        unsafe {
+                            context.preserve_event_update_leftovers();
+                        }
+                        if let Err(e) = res {
+                            flog!(
+                                &mut context.nock_context,
+                                "\r %pack: failed to shrink: {}",
+                                e
+                            );
+                        }
                     }
                     _ => {
                         flog!(&mut context.nock_context, "unknown live");
@@ -377,33 +1354,604 @@ pub fn serf(constant_hot_state: &[HotEntry]) -> io::Result<()> {
                 context.live();
             }
             tas!(b"peek") => {
+                let mil = mil(writ)?;
                 let ovo = slot(writ, 7)?;
-                let res = peek(&mut context, ovo);
-                context.peek_done(res);
+                set_deadline(mil);
+                match peek(&mut context, ovo) {
+                    Ok(res) => context.peek_done(res),
+                    Err(goof) => context.peek_bail(goof),
+                }
             }
             tas!(b"play") => {
+                let eve = eve(writ)?;
                 let lit = slot(writ, 7)?;
-                if context.epoch == 0 && context.event_num == 0 {
+                let res = if eve != context.event_num + 1 {
+                    //  don't apply a batch on top of the wrong state
+                    Err(play_mismatch(&mut context, eve))
+                } else if context.epoch == 0 && context.event_num == 0 {
                     // apply lifecycle to first batch
-                    play_life(&mut context, lit);
+                    play_life(&mut context, lit)
                 } else {
-                    play_list(&mut context, lit);
+                    play_list(&mut context, lit)
                 };
+                match res {
+                    Ok(()) => context.play_done(),
+                    Err(goof) => context.play_bail(goof),
+                }
             }
             tas!(b"work") => {
-                //  XX: what is in slot 6? it's mil_w in Vere Serf
+                let mil = mil(writ)?;
                 let job = slot(writ, 7)?;
+                set_deadline(mil);
                 work(&mut context, job);
             }
             _ => panic!("got message with unknown tag {}", tag),
         };
 
+        clear_deadline();
         clear_interrupt();
+        context.free_retired();
+        context.commit_if_due();
     }
 
+    context.flush();
     Ok(())
 }
 
+/** Keys and values of a `(map term *)`, or None if the noun is not one */
+fn map_entries(map: Noun) -> Option<Vec<(String, Noun)>> {
+    let mut entries = Vec::new();
+    let mut nodes = vec![map];
+    while let Some(node) = nodes.pop() {
+        if unsafe { node.raw_equals(D(0)) } {
+            continue;
+        }
+        let node = node.as_cell().ok()?;
+        let pair = node.head().as_cell().ok()?;
+        let branches = node.tail().as_cell().ok()?;
+        let key = pair.head().as_atom().ok()?;
+        let bytes = key.as_bytes();
+        let len = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        entries.push((
+            String::from_utf8_lossy(&bytes[..len]).into_owned(),
+            pair.tail(),
+        ));
+        nodes.push(branches.head());
+        nodes.push(branches.tail());
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Some(entries)
+}
+
+/** Parse a numeric serf argument, if vere passed it */
+fn serf_arg<T: std::str::FromStr>(index: usize, name: &str) -> io::Result<Option<T>> {
+    std::env::args()
+        .nth(index)
+        .map(|arg| {
+            arg.parse()
+                .map_err(|_| io::Error::other(format!("{} is not an integer", name)))
+        })
+        .transpose()
+}
+
+/** Boot a pier from a pill without a king.
+ *
+ * The pill is `[%pill nam=term bot=(list) mod=(list ovum) use=(list ovum)]`. As in vere, the
+ * lifecycle `bot` is the first batch, and the `mod` and `use` ova are timestamped 1/2^16s apart and
+ * played as the second. The resulting Arvo is saved to the PMA in `$pier/.urb/chk`. If ares keeps
+ * an event log, the events of the second batch start it.
+ */
+pub fn boot(pill_path: &Path, pier_path: &Path, constant_hot_state: &[HotEntry]) -> io::Result<()> {
+    let snap_path = chk_path(pier_path);
+    create_dir_all(&snap_path)?;
+
+    let mut context = Context::load(
+        snap_path,
+        Newt::new_mock(),
+        None,
+        Options::from_env(),
+        constant_hot_state,
+    );
+    if context.event_num != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("boot: pier already at event {}", context.event_num),
+        ));
+    }
+    context.open_log(pier_path)?;
+
+    let bad_pill = || io::Error::new(io::ErrorKind::InvalidData, "boot: not a %pill");
+    let start = Instant::now();
+    let pill = cue_file(&mut context.nock_context.stack, pill_path)?;
+    let tag = slot(pill, 2)?.as_direct().map_err(|_e| bad_pill())?;
+    if tag.data() != tas!(b"pill") {
+        return Err(bad_pill());
+    }
+    let bot = slot(pill, 14)?;
+    let mod_ = slot(pill, 30)?;
+    let use_ = slot(pill, 31)?;
+    eprintln!("boot: cue: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    if let Err(goof) = play_life(&mut context, bot) {
+        return Err(boot_bail(&mut context, goof));
+    }
+    eprintln!(
+        "boot: lifecycle: eve {} in {:?}",
+        context.event_num,
+        start.elapsed()
+    );
+
+    let lit = timestamp_ova(&mut context.nock_context.stack, &[mod_, use_]);
+    if !lit.is_atom() {
+        let start = Instant::now();
+        if let Err(goof) = play_list(&mut context, lit) {
+            return Err(boot_bail(&mut context, goof));
+        }
+        eprintln!(
+            "boot: events: eve {} in {:?}",
+            context.event_num,
+            start.elapsed()
+        );
+    }
+
+    context.commit();
+    eprintln!(
+        "boot: done: eve {} mug {:x}",
+        context.event_num, context.mug
+    );
+    Ok(())
+}
+
+/** Start a pier from a rock written by vere's `cram` or ares's `%cram`,
+ * `$pier/.urb/roc/<eve>.jam` (the newest one if no event number is given), so a ship can be moved
+ * onto ares without replaying its events.
+ *
+ * The rock's Arvo is only saved if its mug matches the one vere recorded for that event in its
+ * event log, and the one ares recorded beside the rock, of those there are. The cold jet state
+ * vere may include is not used, as ares keeps its own.
+ */
+pub fn uncram(
+    pier_path: &Path,
+    event_num: Option<u64>,
+    constant_hot_state: &[HotEntry],
+) -> io::Result<()> {
+    uncram_with(
+        pier_path,
+        event_num,
+        Options::from_env(),
+        constant_hot_state,
+    )
+}
+
+fn uncram_with(
+    pier_path: &Path,
+    event_num: Option<u64>,
+    options: Options,
+    constant_hot_state: &[HotEntry],
+) -> io::Result<()> {
+    let mut roc_path = pier_path.to_path_buf();
+    roc_path.push(".urb");
+    roc_path.push("roc");
+    let event_num = match event_num {
+        Some(event_num) => event_num,
+        None => {
+            let mut newest = None;
+            for entry in read_dir(&roc_path)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "jam") {
+                    let stem = path.file_stem().and_then(|stem| stem.to_str());
+                    if let Some(eve) = stem.and_then(|stem| stem.parse::<u64>().ok()) {
+                        newest = newest.max(Some(eve));
+                    }
+                }
+            }
+            newest.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("uncram: no rocks in {}", roc_path.display()),
+                )
+            })?
+        }
+    };
+    roc_path.push(format!("{}.jam", event_num));
+    if !roc_path.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("uncram: no rock at {}", roc_path.display()),
+        ));
+    }
+
+    let vere_mug = match event_mug(pier_path, event_num) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        res => res?,
+    };
+    let cram_mug = cram_mug(&roc_path)?;
+    if vere_mug.is_none() && cram_mug.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("uncram: no mug recorded for event {}", event_num),
+        ));
+    }
+
+    let snap_path = chk_path(pier_path);
+    create_dir_all(&snap_path)?;
+    let mut context = Context::load(
+        snap_path,
+        Newt::new_mock(),
+        None,
+        options,
+        constant_hot_state,
+    );
+    if context.event_num != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("uncram: pier already at event {}", context.event_num),
+        ));
+    }
+
+    let start = Instant::now();
+    let rock = cue_file(&mut context.nock_context.stack, &roc_path)?;
+    eprintln!("uncram: cue: {:?}", start.elapsed());
+
+    //  [%fast arvo cold] with vere's cold state, or just Arvo
+    let arvo = match rock.as_cell() {
+        Ok(cell) if unsafe { cell.head().raw_equals(D(tas!(b"fast"))) } => slot(rock, 6)?,
+        _ => rock,
+    };
+    if !arvo.is_cell() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "uncram: rock is not an Arvo kernel",
+        ));
+    }
+
+    let mug = mug_u32(&mut context.nock_context.stack, arvo);
+    for (king, recorded) in [("vere", vere_mug), ("ares", cram_mug)] {
+        if let Some(recorded) = recorded.filter(|recorded| *recorded != mug) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "uncram: rock mug {:x} does not match the mug {:x} {} recorded for event {}",
+                    mug, recorded, king, event_num
+                ),
+            ));
+        }
+    }
+
+    unsafe { context.event_update(event_num, arvo, None) };
+    pma_sync();
+    eprintln!(
+        "uncram: done: eve {} mug {:x}",
+        context.event_num, context.mug
+    );
+    Ok(())
+}
+
+/** Run the events in a pier's own [EventLog] which follow its state, up to `event_num` if given,
+ * checking the mug of Arvo after each against the logged one.
+ *
+ * Events are run as in %play, without a king, so a pier can be caught up or a failure reproduced
+ * from its own log alone. Nothing is committed unless every event is run and matches its mug.
+ */
+pub fn replay(
+    pier_path: &Path,
+    event_num: Option<u64>,
+    constant_hot_state: &[HotEntry],
+) -> io::Result<()> {
+    let mut context = load_pier(pier_path, false, constant_hot_state)?;
+    let log = EventLog::open(pier_path, context.options.log_segment_bytes)?;
+    let last = event_num.unwrap_or(u64::MAX).min(log.last_event());
+    if last <= context.event_num {
+        eprintln!(
+            "replay: nothing to replay after eve {}, the log ends at eve {}",
+            context.event_num,
+            log.last_event()
+        );
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    for event in log.events_after(context.event_num) {
+        let event = event?;
+        //  past a gap in the log, the events don't apply
+        if event.event_num > last || event.event_num != context.event_num + 1 {
+            break;
+        }
+
+        let stack = &mut context.nock_context.stack;
+        let ovo = unsafe {
+            let mut jam = IndirectAtom::new_raw_bytes_ref(stack, &event.jam);
+            cue(stack, jam.normalize_as_atom())
+        };
+        match soft(&mut context, ovo, None) {
+            Ok(res) => {
+                let arvo = res
+                    .as_cell()
+                    .expect("serf: replay: +slam returned atom")
+                    .tail();
+                unsafe {
+                    context.event_update(event.event_num, arvo, None);
+                    context.preserve_event_update_leftovers();
+                }
+            }
+            Err(goof) => {
+                slog_goof(&mut context, goof);
+                return Err(io::Error::other(format!(
+                    "replay: bail at event {}",
+                    event.event_num
+                )));
+            }
+        }
+
+        if context.mug != event.mug {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "replay: eve {} mug {:x} does not match the logged mug {:x}",
+                    event.event_num, context.mug, event.mug
+                ),
+            ));
+        }
+    }
+    if context.event_num != last {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "replay: event {} is not in the event log",
+                context.event_num + 1
+            ),
+        ));
+    }
+
+    context.commit();
+    eprintln!(
+        "replay: done: eve {} mug {:x} in {:?}",
+        context.event_num,
+        context.mug,
+        start.elapsed()
+    );
+    Ok(())
+}
+
+/** Remove the events after `event_num` from a pier's own [EventLog], e.g. to replay them again
+ * after a rollback. */
+pub fn truncate(pier_path: &Path, event_num: u64) -> io::Result<()> {
+    if !chk_path(pier_path).is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no pier at {}", pier_path.display()),
+        ));
+    }
+    let mut log = EventLog::open(pier_path, Options::from_env().log_segment_bytes)?;
+    log.truncate(event_num)?;
+    if log.last_event() == 0 {
+        eprintln!("truncate: the event log is empty");
+    } else {
+        eprintln!(
+            "truncate: the event log holds events {} to {}",
+            log.first_event(),
+            log.last_event()
+        );
+    }
+    Ok(())
+}
+
+/** Print a boot failure's goof to stderr, and make it an error. */
+fn boot_bail(context: &mut Context, goof: Noun) -> io::Error {
+    slog_goof(context, goof);
+    io::Error::other(format!("boot: bail at event {}", context.event_num + 1))
+}
+
+/** Slog each tank of a goof's tang, for commands run without a king. */
+fn slog_goof(context: &mut Context, goof: Noun) {
+    let mut tang = goof.slot(3).unwrap_or(D(0));
+    while let Ok(cell) = tang.as_cell() {
+        let stack = &mut context.nock_context.stack;
+        context.nock_context.newt.slog(stack, 0, cell.head());
+        tang = cell.tail();
+    }
+}
+
+/** Evaluate Hoon against the Arvo in a pier's PMA, like vere's u3v_wish, and print the product.
+ *
+ * The wish arm of Arvo is slammed with the Hoon source as a cord. No event is run, and the PMA is
+ * opened read-only.
+ */
+pub fn wish(pier_path: &Path, hoon: &str, constant_hot_state: &[HotEntry]) -> io::Result<()> {
+    let mut context = load_pier(pier_path, true, constant_hot_state)?;
+
+    let txt = nock_fmt(&mut context.nock_context, format_args!("{}", hoon))?.as_noun();
+    let start = Instant::now();
+    let res = slam(&mut context, WISH_AXIS, txt);
+    eprintln!("wish: eve {} in {:?}", context.event_num, start.elapsed());
+
+    match res {
+        Ok(product) => {
+            println!("{}", product);
+            Ok(())
+        }
+        Err(Error::Deterministic(mote, traces) | Error::NonDeterministic(mote, traces)) => {
+            let goof = goof(&mut context.nock_context, mote, traces);
+            slog_goof(&mut context, goof);
+            Err(io::Error::other(format!("wish: bail: {:?}", mote)))
+        }
+        Err(Error::ScryBlocked(_) | Error::ScryCrashed(_)) => {
+            Err(io::Error::other("wish: .^ invalid outside of virtual Nock"))
+        }
+    }
+}
+
+/** Start a new epoch in a pier which isn't running, retaining the snapshot that ends this one. */
+pub fn roll(pier_path: &Path, constant_hot_state: &[HotEntry]) -> io::Result<()> {
+    let mut context = load_pier(pier_path, false, constant_hot_state)?;
+    unsafe {
+        context.roll(context.epoch + 1);
+    }
+    pma_sync();
+    eprintln!("roll: epoch {} at eve {}", context.epoch, context.event_num);
+    Ok(())
+}
+
+/** Restore a pier which isn't running to the end of a retained epoch. */
+pub fn rollback(pier_path: &Path, epoch: u64, constant_hot_state: &[HotEntry]) -> io::Result<()> {
+    let mut context = load_pier(pier_path, false, constant_hot_state)?;
+    unsafe { context.rollback(epoch)? };
+    eprintln!(
+        "rollback: eve {} mug {:x}, now epoch {}",
+        context.event_num, context.mug, context.epoch
+    );
+    Ok(())
+}
+
+/** List the current epoch and the retained generations of a pier, newest first. */
+pub fn epochs(pier_path: &Path, constant_hot_state: &[HotEntry]) -> io::Result<()> {
+    let mut context = load_pier(pier_path, true, constant_hot_state)?;
+    println!(
+        "epoch {}: eve {} mug {:x} (current)",
+        context.epoch, context.event_num, context.mug
+    );
+    for handle in generations() {
+        let snapshot = unsafe { &*Snapshot::handle_from_u64(handle).0 };
+        let arvo = snapshot.arvo;
+        let mug = mug_u32(&mut context.nock_context.stack, arvo);
+        println!(
+            "epoch {}: eve {} mug {:x}",
+            { snapshot.epoch },
+            { snapshot.event_num },
+            mug
+        );
+    }
+    Ok(())
+}
+
+/** Print a %mass report on the memory used by a pier */
+pub fn mass(pier_path: &Path, constant_hot_state: &[HotEntry]) -> io::Result<()> {
+    let mut context = load_pier(pier_path, true, constant_hot_state)?;
+    for line in context.mass() {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/** Load a booted pier, for commands run without a king; read-only ones can't change it */
+fn load_pier(
+    pier_path: &Path,
+    read_only: bool,
+    constant_hot_state: &[HotEntry],
+) -> io::Result<Context> {
+    let snap_path = chk_path(pier_path);
+    if !snap_path.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no pier at {}", pier_path.display()),
+        ));
+    }
+
+    let options = Options {
+        read_only,
+        ..Options::from_env()
+    };
+    let context = Context::load(
+        snap_path,
+        Newt::new_mock(),
+        None,
+        options,
+        constant_hot_state,
+    );
+    if context.event_num == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "pier has not been booted",
+        ));
+    }
+    Ok(context)
+}
+
+/** The PMA directory of a pier, `$pier/.urb/chk` */
+fn chk_path(pier_path: &Path) -> PathBuf {
+    let mut snap_path = pier_path.to_path_buf();
+    snap_path.push(".urb");
+    snap_path.push("chk");
+    snap_path
+}
+
+/** Weld lists of ova into one list of [now ovum] events, starting at the current time and
+ * spaced 1/2^16 seconds apart, like vere does for the events of a pill.
+ */
+fn timestamp_ova(stack: &mut NockStack, lists: &[Noun]) -> Noun {
+    let mut ova = Vec::new();
+    for list in lists {
+        let mut list = *list;
+        while let Ok(cell) = list.as_cell() {
+            ova.push(cell.head());
+            list = cell.tail();
+        }
+    }
+
+    //  @da of the unix epoch is 0x8000000cce9e0d80 seconds, with 64 bits of fractional seconds
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("boot: clock before 1970");
+    let sec = UBig::from(0x8000000cce9e0d80u64 + since.as_secs()) << 64;
+    let fra = UBig::from((since.subsec_micros() as u64 * 65536) / 1_000_000) << 48;
+    let now = sec + fra;
+    let bit = UBig::from(1u64) << 48;
+
+    let mut lit = D(0);
+    for (i, ovo) in ova.iter().enumerate().rev() {
+        let wen = Atom::from_ubig(stack, &(&now + &bit * UBig::from(i))).as_noun();
+        let job = T(stack, &[wen, *ovo]);
+        lit = T(stack, &[job, lit]);
+    }
+    lit
+}
+
+/** Jam `[%fast arvo cold]` to `$pier/.urb/roc/<eve>.jam`, as vere's cram does, and write the
+ * mug of Arvo to `<eve>.mug`, so that [uncram] can check a rock vere's event log doesn't cover.
+ *
+ * Ares's cold jet state is not vere's, so the rock's is empty, and vere rebuilds its own as jets
+ * are registered. The jam is streamed to the file, so only its backreference map lives on the
+ * NockStack.
+ */
+fn cram(context: &mut Context, pier_path: &Path) -> io::Result<usize> {
+    let mut roc_path = pier_path.to_path_buf();
+    roc_path.push(".urb");
+    roc_path.push("roc");
+    create_dir_all(&roc_path)?;
+    roc_path.push(format!("{}.jam", context.event_num));
+
+    let stack = &mut context.nock_context.stack;
+    let rock = T(stack, &[D(tas!(b"fast")), context.arvo, D(0)]);
+    let mut file = File::create(&roc_path)?;
+    let bytes = jam_to_writer(stack, rock, &mut file)?;
+    file.sync_all()?;
+
+    //  written last, so a rock with a mug is complete
+    let mut file = File::create(roc_path.with_extension("mug"))?;
+    writeln!(file, "{:x}", context.mug)?;
+    file.sync_all()?;
+    Ok(bytes)
+}
+
+/** The mug [cram] recorded for a rock, if it was written by ares */
+fn cram_mug(roc_path: &Path) -> io::Result<Option<u32>> {
+    let mug_path = roc_path.with_extension("mug");
+    let text = match std::fs::read_to_string(&mug_path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    u32::from_str_radix(text.trim(), 16)
+        .map(Some)
+        .map_err(|_e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("uncram: bad mug in {}", mug_path.display()),
+            )
+        })
+}
+
 fn slam(context: &mut Context, axis: u64, ovo: Noun) -> Result<Noun, Error> {
     let arvo = context.arvo;
     let stack = &mut context.nock_context.stack;
@@ -415,52 +1963,89 @@ fn slam(context: &mut Context, axis: u64, ovo: Noun) -> Result<Noun, Error> {
     interpret(&mut context.nock_context, sub, fol)
 }
 
-fn peek(context: &mut Context, ovo: Noun) -> Noun {
-    if context.nock_context.trace_info.is_some() {
+/** Run a peek; a crash is processed into a goof for %peek %bail.
+ *  A blocked scry produces ~.
+ */
+fn peek(context: &mut Context, ovo: Noun) -> Result<Noun, Noun> {
+    let slam_res = if context.nock_context.trace_info.is_some() {
         //  XX: way too many cases in the input to pull the actual vane, care, and path out
         let trace_name = "peek";
         let start = Instant::now();
         let slam_res = slam(context, PEEK_AXIS, ovo);
         write_serf_trace_safe(&mut context.nock_context, trace_name, start);
 
-        slam_res.expect("peek error handling unimplemented")
+        slam_res
     } else {
-        slam(context, PEEK_AXIS, ovo).expect("peek error handling unimplemented")
+        slam(context, PEEK_AXIS, ovo)
+    };
+
+    match slam_res {
+        Ok(res) => Ok(res),
+        Err(error) => match error {
+            Error::Deterministic(mote, traces) | Error::NonDeterministic(mote, traces) => {
+                Err(goof(&mut context.nock_context, mote, traces))
+            }
+            Error::ScryBlocked(_) => Ok(D(0)),
+            Error::ScryCrashed(trace) => Err(goof(&mut context.nock_context, Mote::Exit, trace)),
+        },
     }
 }
 
-fn goof(context: &mut Context, mote: Mote, traces: Noun) -> Noun {
-    let trace = zing(&mut context.nock_context.stack, traces).expect("serf: goof: zing failed");
-    let tone = Cell::new(&mut context.nock_context.stack, D(2), trace);
-    let tang = mook(&mut context.nock_context, tone, false)
-        .expect("serf: goof: +mook crashed on bail")
-        .tail();
-    T(&mut context.nock_context.stack, &[D(mote as u64), tang])
+/** [mote tang] for a bail, its trace rendered by +mook. A trace which +mook can't render is
+ *  replaced by a leaf saying so, since a crash here would take the serf down with it.
+ */
+fn goof(context: &mut interpreter::Context, mote: Mote, traces: Noun) -> Noun {
+    //  %evil is a decryption failure: don't spend time rendering a trace
+    if let Mote::Evil = mote {
+        return T(&mut context.stack, &[D(mote as u64), D(0)]);
+    }
+    let tang = zing(&mut context.stack, traces)
+        .ok()
+        .and_then(|trace| {
+            let tone = Cell::new(&mut context.stack, D(2), trace);
+            mook(context, tone, false).ok()
+        })
+        .map(|tone| tone.tail())
+        .unwrap_or_else(|| {
+            let stack = &mut context.stack;
+            let tape = tape(stack, "serf: +mook crashed rendering the trace");
+            let leaf = T(stack, &[D(tas!(b"leaf")), tape]);
+            T(stack, &[leaf, D(0)])
+        });
+    T(&mut context.stack, &[D(mote as u64), tang])
 }
 
 /** Run slam; process stack trace to tang if error.
  *  Generate tracing events, if JSON tracing enabled.
  */
 fn soft(context: &mut Context, ovo: Noun, trace_name: Option<String>) -> Result<Noun, Noun> {
-    let slam_res = if context.nock_context.trace_info.is_some() {
-        let start = Instant::now();
-        let slam_res = slam(context, POKE_AXIS, ovo);
-        write_serf_trace_safe(
-            &mut context.nock_context,
-            trace_name.as_ref().unwrap(),
-            start,
-        );
+    let slam_res = loop {
+        let slam_res = if context.nock_context.trace_info.is_some() {
+            let start = Instant::now();
+            let slam_res = slam(context, POKE_AXIS, ovo);
+            write_serf_trace_safe(
+                &mut context.nock_context,
+                trace_name.as_ref().unwrap(),
+                start,
+            );
+
+            slam_res
+        } else {
+            slam(context, POKE_AXIS, ovo)
+        };
 
-        slam_res
-    } else {
-        slam(context, POKE_AXIS, ovo)
+        //  the same event would succeed with more memory, so run it again before giving up
+        if matches!(slam_res, Err(Error::NonDeterministic(Mote::Meme, _))) && context.grow() {
+            continue;
+        }
+        break slam_res;
     };
 
     match slam_res {
         Ok(res) => Ok(res),
         Err(error) => match error {
             Error::Deterministic(mote, traces) | Error::NonDeterministic(mote, traces) => {
-                Err(goof(context, mote, traces))
+                Err(goof(&mut context.nock_context, mote, traces))
             }
             Error::ScryBlocked(_) | Error::ScryCrashed(_) => {
                 panic!("serf: soft: .^ invalid outside of virtual Nock")
@@ -469,7 +2054,8 @@ fn soft(context: &mut Context, ovo: Noun, trace_name: Option<String>) -> Result<
     }
 }
 
-fn play_life(context: &mut Context, eve: Noun) {
+/** Run the lifecycle formula over the first batch; on a crash, return the goof for %play %bail. */
+fn play_life(context: &mut Context, eve: Noun) -> Result<(), Noun> {
     let stack = &mut context.nock_context.stack;
     let sub = T(stack, &[D(0), D(3)]);
     let lyf = T(stack, &[D(2), sub, D(0), D(2)]);
@@ -490,15 +2076,14 @@ fn play_life(context: &mut Context, eve: Noun) {
             let arvo = slot(gat, 7).expect("serf: play: lifecycle didn't return initial Arvo");
 
             unsafe {
-                context.event_update(eved, arvo);
+                context.event_update(eved, arvo, None);
                 context.preserve_event_update_leftovers();
             }
-            context.play_done();
+            Ok(())
         }
         Err(error) => match error {
             Error::Deterministic(mote, traces) | Error::NonDeterministic(mote, traces) => {
-                let goof = goof(context, mote, traces);
-                context.play_bail(goof);
+                Err(goof(&mut context.nock_context, mote, traces))
             }
             Error::ScryBlocked(_) | Error::ScryCrashed(_) => {
                 panic!("serf: play: .^ invalid outside of virtual Nock")
@@ -507,7 +2092,8 @@ fn play_life(context: &mut Context, eve: Noun) {
     }
 }
 
-fn play_list(context: &mut Context, mut lit: Noun) {
+/** Poke each event of a batch into Arvo; on a crash, return the goof for %play %bail. */
+fn play_list(context: &mut Context, mut lit: Noun) -> Result<(), Noun> {
     let mut eve = context.event_num;
     while let Ok(cell) = lit.as_cell() {
         let ovo = cell.head();
@@ -527,17 +2113,17 @@ fn play_list(context: &mut Context, mut lit: Noun) {
                 eve += 1;
 
                 unsafe {
-                    context.event_update(eve, arvo);
+                    context.event_update(eve, arvo, Some(ovo));
                     context.nock_context.stack.preserve(&mut lit);
                     context.preserve_event_update_leftovers();
                 }
             }
             Err(goof) => {
-                return context.play_bail(goof);
+                return Err(goof);
             }
         }
     }
-    context.play_done();
+    Ok(())
 }
 
 fn work(context: &mut Context, job: Noun) {
@@ -562,7 +2148,7 @@ fn work(context: &mut Context, job: Noun) {
             let eve = context.event_num;
 
             unsafe {
-                context.event_update(eve + 1, cell.tail());
+                context.event_update(eve + 1, cell.tail(), Some(job));
                 context.nock_context.stack.preserve(&mut fec);
                 context.preserve_event_update_leftovers();
             }
@@ -575,14 +2161,20 @@ fn work(context: &mut Context, job: Noun) {
 }
 
 fn work_swap(context: &mut Context, job: Noun, goof: Noun) {
-    //  TODO: on decryption failure in aes_siv, should bail as fast as
-    //  possible, without rendering stack trace or injecting crud event.  See
-    //  c3__evil in vere.
-
+    clear_deadline();
     clear_interrupt();
 
+    //  on decryption failure in aes_siv, bail as fast as possible, without
+    //  injecting a crud event.  See c3__evil in vere.
+    if let Ok(mote) = goof.as_cell().and_then(|cell| cell.head().as_direct()) {
+        if mote.data() == Mote::Evil as u64 {
+            let lud = T(&mut context.nock_context.stack, &[goof, D(0)]);
+            return context.work_bail(lud);
+        }
+    }
+
+    context.nock_context.cache_reset();
     let stack = &mut context.nock_context.stack;
-    context.nock_context.cache = Hamt::<Noun>::new(stack);
     //  crud ovo = [+(now) [%$ %arvo ~] [%crud goof ovo]]
     let job_cell = job.as_cell().expect("serf: work: job not a cell");
     let job_now = job_cell.head().as_atom().expect("serf: work: now not atom");
@@ -607,7 +2199,7 @@ fn work_swap(context: &mut Context, job: Noun, goof: Noun) {
             let eve = context.event_num;
 
             unsafe {
-                context.event_update(eve + 1, cell.tail());
+                context.event_update(eve + 1, cell.tail(), Some(ovo));
                 context.nock_context.stack.preserve(&mut ovo);
                 context.nock_context.stack.preserve(&mut fec);
                 context.preserve_event_update_leftovers();
@@ -653,6 +2245,264 @@ fn slot(noun: Noun, axis: u64) -> io::Result<Noun> {
         .map_err(|_e| io::Error::new(io::ErrorKind::InvalidInput, "Bad axis"))
 }
 
+/** Timeout in milliseconds of a %peek or %work writ */
+fn mil(writ: Noun) -> io::Result<u64> {
+    slot(writ, 6)?
+        .as_atom()
+        .and_then(|mil| mil.as_u64())
+        .map_err(|_e| io::Error::new(io::ErrorKind::InvalidInput, "Bad timeout"))
+}
+
+fn eve(writ: Noun) -> io::Result<u64> {
+    slot(writ, 6)?
+        .as_atom()
+        .and_then(|eve| eve.as_u64())
+        .map_err(|_e| io::Error::new(io::ErrorKind::InvalidInput, "Bad event number"))
+}
+
+/** Goof for a %play batch which doesn't start right after the current event.
+ *
+ * [%exit [%leaf "play: expected event <n>, got <eve>"] ~]
+ */
+fn play_mismatch(context: &mut Context, eve: u64) -> Noun {
+    let stack = &mut context.nock_context.stack;
+    let msg = format!(
+        "play: expected event {}, got {}",
+        context.event_num + 1,
+        eve
+    );
+    let tape = tape(stack, &msg);
+    let leaf = T(stack, &[D(tas!(b"leaf")), tape]);
+    let tang = T(stack, &[leaf, D(0)]);
+    T(stack, &[D(Mote::Exit as u64), tang])
+}
+
+/** Watch the event deadline, interrupting the interpreter by setting the TIMEOUT flag if it
+ *  passes.
+ */
+fn start_watchdog() {
+    std::thread::spawn(|| {
+        let (lock, cvar) = &*DEADLINE;
+        let mut deadline = lock.lock().unwrap();
+        loop {
+            match *deadline {
+                None => {
+                    deadline = cvar.wait(deadline).unwrap();
+                }
+                Some(when) => {
+                    let now = Instant::now();
+                    if now >= when {
+                        (*TIMEOUT).store(true, Ordering::Relaxed);
+                        *deadline = None;
+                    } else {
+                        deadline = cvar.wait_timeout(deadline, when - now).unwrap().0;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/** Interrupt the interpreter once `mil` milliseconds of wall-clock time have passed. A deadline
+ *  of 0 means none.
+ */
+fn set_deadline(mil: u64) {
+    let (lock, cvar) = &*DEADLINE;
+    *lock.lock().unwrap() = if mil == 0 {
+        None
+    } else {
+        Some(Instant::now() + Duration::from_millis(mil))
+    };
+    cvar.notify_one();
+}
+
+fn clear_deadline() {
+    set_deadline(0);
+}
+
 fn clear_interrupt() {
     (*TERMINATOR).store(false, Ordering::Relaxed);
+    (*TIMEOUT).store(false, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jets::util::test::{assert_noun_eq, init_context};
+    use crate::persist::pma_close;
+    use crate::persist::test::pma_test;
+
+    /// A layout from before epochs were kept, standing in for an older snapshot version
+    #[repr(C)]
+    #[repr(packed)]
+    struct SnapshotMemV1 {
+        pub event_num: u64,
+        pub arvo: Noun,
+        pub cold: Cold,
+    }
+
+    struct SnapshotV1(*mut SnapshotMemV1);
+
+    impl Persist for SnapshotV1 {
+        unsafe fn space_needed(&mut self, stack: &mut NockStack) -> usize {
+            let mut arvo = (*(self.0)).arvo;
+            let mut cold = (*(self.0)).cold;
+            let space = arvo.space_needed(stack) + cold.space_needed(stack);
+            (*(self.0)).arvo = arvo;
+            (*(self.0)).cold = cold;
+            (((size_of::<SnapshotMemV1>() + 7) >> 3) << 3) + space
+        }
+
+        unsafe fn copy_to_buffer(&mut self, stack: &mut NockStack, buffer: &mut *mut u8) {
+            let snapshot_buffer = *buffer as *mut SnapshotMemV1;
+            std::ptr::copy_nonoverlapping(self.0, snapshot_buffer, 1);
+            *self = SnapshotV1(snapshot_buffer);
+            *buffer = snapshot_buffer.add(1) as *mut u8;
+
+            let mut arvo = (*snapshot_buffer).arvo;
+            arvo.copy_to_buffer(stack, buffer);
+            (*snapshot_buffer).arvo = arvo;
+
+            let mut cold = (*snapshot_buffer).cold;
+            cold.copy_to_buffer(stack, buffer);
+            (*snapshot_buffer).cold = cold;
+        }
+
+        unsafe fn handle_to_u64(&self) -> u64 {
+            self.0 as u64
+        }
+
+        unsafe fn handle_from_u64(meta_handle: u64) -> Self {
+            SnapshotV1(meta_handle as *mut SnapshotMemV1)
+        }
+    }
+
+    unsafe fn migrate_v1(stack: &mut NockStack, handle: u64) -> u64 {
+        let old = SnapshotV1::handle_from_u64(handle).0;
+        let new: *mut SnapshotMem = stack.struct_alloc(1);
+        (*new).epoch = 0;
+        (*new).event_num = (*old).event_num;
+        (*new).arvo = (*old).arvo;
+        (*new).cold = (*old).cold;
+        Snapshot(new).save_to_pma(stack)
+    }
+
+    #[test]
+    fn test_snapshot_migration() {
+        let (_guard, path) = pma_test("migrate");
+        pma_open(path.clone()).unwrap();
+        let mut stack = NockStack::new(8 << 10 << 10, 0);
+        unsafe {
+            let arvo = T(&mut stack, &[D(1), D(2), D(3)]);
+            let mug = mug_u32(&mut stack, arvo);
+            let old: *mut SnapshotMemV1 = stack.struct_alloc(1);
+            (*old).event_num = 42;
+            (*old).arvo = arvo;
+            (*old).cold = Cold::new(&mut stack);
+            let handle = SnapshotV1(old).save_to_pma(&mut stack);
+            pma_sync();
+
+            let snapshot = Snapshot::load_with(&mut stack, 1, handle, &[migrate_v1])
+                .unwrap()
+                .unwrap();
+            assert_eq!({ (*snapshot.0).epoch }, 0);
+            assert_eq!({ (*snapshot.0).event_num }, 42);
+            assert_eq!(mug_u32(&mut stack, (*snapshot.0).arvo), mug);
+
+            //  the migrated snapshot replaces the old one, so is only migrated once
+            assert_eq!(pma_meta_get(BTMetaField::SnapshotVersion as usize), 2);
+            let migrated = pma_meta_get(BTMetaField::Snapshot as usize);
+            assert_eq!(migrated, snapshot.handle_to_u64());
+            let again = Snapshot::load_with(&mut stack, 2, migrated, &[migrate_v1])
+                .unwrap()
+                .unwrap();
+            assert_eq!(again.handle_to_u64(), migrated);
+        }
+        pma_close().unwrap();
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_snapshot_newer_version() {
+        let mut stack = NockStack::new(8 << 10 << 10, 0);
+        unsafe {
+            //  the handle is never read
+            let newer = PMA_CURRENT_SNAPSHOT_VERSION + 1;
+            let err = Snapshot::load(&mut stack, newer, u64::MAX).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(Snapshot::load(&mut stack, 0, 0).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_cram() {
+        let (_guard, path) = pma_test("cram");
+        let options = || Options {
+            stack_words: 1 << 20,
+            max_stack_words: 1 << 20,
+            ..Options::from_env()
+        };
+        create_dir_all(chk_path(&path)).unwrap();
+        let mut context = Context::load(chk_path(&path), Newt::new_mock(), None, options(), &[]);
+        let stack = &mut context.nock_context.stack;
+        let arvo = T(stack, &[D(1), D(2), D(3)]);
+        let mug = mug_u32(stack, arvo);
+        context.arvo = arvo;
+        context.event_num = 5;
+        context.mug = mug;
+        cram(&mut context, &path).unwrap();
+
+        //  the rock is vere's [%fast arvo cold], with an empty cold state
+        let roc_path = path.join(".urb/roc/5.jam");
+        let stack = &mut context.nock_context.stack;
+        let rock = cue_file(stack, &roc_path).unwrap();
+        let fast = T(stack, &[D(tas!(b"fast")), arvo, D(0)]);
+        assert_noun_eq(stack, rock, fast);
+        assert_eq!(cram_mug(&roc_path).unwrap(), Some(mug));
+        drop(context);
+        pma_close().unwrap();
+
+        //  with no event log from vere, the rock is checked against the mug cram recorded
+        uncram_with(&path, None, options(), &[]).unwrap();
+        pma_close().unwrap();
+        let context = Context::load(chk_path(&path), Newt::new_mock(), None, options(), &[]);
+        assert_eq!(context.event_num, 5);
+        assert_eq!(context.mug, mug);
+        drop(context);
+        pma_close().unwrap();
+
+        std::fs::write(roc_path.with_extension("mug"), "0\n").unwrap();
+        std::fs::remove_dir_all(chk_path(&path)).unwrap();
+        let err = uncram_with(&path, None, options(), &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        pma_close().unwrap();
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_goof() {
+        let context = &mut init_context();
+        let stack = &mut context.stack;
+        let mean = T(stack, &[D(tas!(b"mean")), D(tas!(b"hi"))]);
+        let trace = T(stack, &[mean, D(0)]);
+        let traces = T(stack, &[trace, D(0)]);
+        let hi = tape(stack, "hi");
+        let leaf = T(stack, &[D(tas!(b"leaf")), hi]);
+        let rendered = T(stack, &[D(Mote::Exit as u64), leaf, D(0)]);
+        let product = goof(context, Mote::Exit, traces);
+        assert_noun_eq(&mut context.stack, product, rendered);
+
+        //  a trace +mook crashes on, and one which isn't a list of lists, are not rendered
+        let stack = &mut context.stack;
+        let spot = T(stack, &[D(tas!(b"spot")), D(5)]);
+        let trace = T(stack, &[spot, D(0)]);
+        let bad_spot = T(stack, &[trace, D(0)]);
+        let msg = tape(stack, "serf: +mook crashed rendering the trace");
+        let leaf = T(stack, &[D(tas!(b"leaf")), msg]);
+        let crashed = T(stack, &[D(Mote::Exit as u64), leaf, D(0)]);
+        for traces in [bad_spot, D(5)] {
+            let product = goof(context, Mote::Exit, traces);
+            assert_noun_eq(&mut context.stack, product, crashed);
+        }
+    }
 }
@@ -4,6 +4,9 @@ use crate::mem::NockStack;
 use crate::noun::{Atom, Cell, DirectAtom, IndirectAtom, Noun};
 use bitvec::prelude::{BitSlice, Lsb0};
 use either::Either::{Left, Right};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
 
 crate::gdb!();
 
@@ -152,15 +155,23 @@ fn rub_backref(cursor: &mut usize, buffer: &BitSlice<u64, Lsb0>) -> u64 {
     }
 }
 
+/** Size in words of the buffer [jam_to_writer] fills before flushing */
+const JAM_CHUNK_WORDS: usize = 1 << 16;
+
 struct JamState<'a> {
     cursor: usize,
     size: usize,
     atom: IndirectAtom,
     slice: &'a mut BitSlice<u64, Lsb0>,
+    /** When present, full words are flushed here instead of growing the buffer */
+    sink: Option<&'a mut dyn Write>,
+    /** Bits already written to the sink */
+    flushed: usize,
+    error: Option<io::Error>,
 }
 
 pub fn jam(stack: &mut NockStack, noun: Noun) -> Atom {
-    let backref_map = MutHamt::new(stack);
+    stack.frame_push(0);
     let size = 8;
     let (atom, slice) = unsafe { IndirectAtom::new_raw_mut_bitslice(stack, size) };
     let mut state = JamState {
@@ -168,13 +179,81 @@ pub fn jam(stack: &mut NockStack, noun: Noun) -> Atom {
         size,
         atom,
         slice,
+        sink: None,
+        flushed: 0,
+        error: None,
     };
+    jam_loop(stack, &mut state, noun);
+    unsafe {
+        let mut result = state.atom.normalize_as_atom();
+        stack.preserve(&mut result);
+        stack.frame_pop();
+        result
+    }
+}
+
+/** Read a file of jammed bytes (e.g. a pill) and cue it */
+pub fn cue_file(stack: &mut NockStack, path: &Path) -> io::Result<Noun> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+    let atom = if len == 0 {
+        Atom::new(stack, 0)
+    } else {
+        unsafe {
+            let (mut indirect, bytes) = IndirectAtom::new_raw_mut_bytes(stack, len);
+            file.read_exact(bytes)?;
+            indirect.normalize_as_atom()
+        }
+    };
+    Ok(cue(stack, atom))
+}
+
+/** Jam a noun directly to a writer, returning the number of bytes written.
+ *
+ * Output is buffered in fixed-size chunks, so the jammed noun never needs to fit in the
+ * NockStack. Only the backreference map is retained, and it is freed before returning.
+ */
+pub fn jam_to_writer(
+    stack: &mut NockStack,
+    noun: Noun,
+    writer: &mut dyn Write,
+) -> io::Result<usize> {
     stack.frame_push(0);
+    let size = JAM_CHUNK_WORDS;
+    let (atom, slice) = unsafe { IndirectAtom::new_raw_mut_bitslice(stack, size) };
+    let mut state = JamState {
+        cursor: 0,
+        size,
+        atom,
+        slice,
+        sink: Some(writer),
+        flushed: 0,
+        error: None,
+    };
+    jam_loop(stack, &mut state, noun);
+    // Jammed nouns always end in a 1 bit, so the final partial byte is significant
+    let tail_bytes = (state.cursor + 7) >> 3;
+    // Clear stale bits left behind by earlier flushes
+    state.slice[state.cursor..tail_bytes << 3].fill(false);
+    flush_bytes(&mut state, tail_bytes);
+    let total = (state.flushed + state.cursor + 7) >> 3;
+    unsafe {
+        stack.frame_pop();
+    }
+    match state.error {
+        Some(error) => Err(error),
+        None => Ok(total),
+    }
+}
+
+/** Jam traversal shared by [jam] and [jam_to_writer]. Must be called in a fresh frame. */
+fn jam_loop(stack: &mut NockStack, state: &mut JamState, noun: Noun) {
+    let backref_map = MutHamt::new(stack);
     unsafe {
         *(stack.push::<Noun>()) = noun;
     };
     'jam: loop {
-        if stack.stack_is_empty() {
+        if stack.stack_is_empty() || state.error.is_some() {
             break;
         } else {
             let mut noun = unsafe { *(stack.top::<Noun>()) };
@@ -184,13 +263,13 @@ pub fn jam(stack: &mut NockStack, noun: Noun) -> Atom {
                         let atom_size = met0_usize(atom);
                         let backref_size = met0_u64_to_usize(backref);
                         if atom_size <= backref_size {
-                            jam_atom(stack, &mut state, atom);
+                            jam_atom(stack, state, atom);
                         } else {
-                            jam_backref(stack, &mut state, backref);
+                            jam_backref(stack, state, backref);
                         }
                     }
                     Right(_cell) => {
-                        jam_backref(stack, &mut state, backref);
+                        jam_backref(stack, state, backref);
                     }
                 }
                 unsafe {
@@ -198,17 +277,17 @@ pub fn jam(stack: &mut NockStack, noun: Noun) -> Atom {
                 };
                 continue 'jam;
             };
-            backref_map.insert(stack, &mut noun, state.cursor as u64);
+            backref_map.insert(stack, &mut noun, (state.flushed + state.cursor) as u64);
             match noun.as_either_atom_cell() {
                 Left(atom) => {
-                    jam_atom(stack, &mut state, atom);
+                    jam_atom(stack, state, atom);
                     unsafe {
                         stack.pop::<Noun>();
                     };
                     continue;
                 }
                 Right(cell) => {
-                    jam_cell(stack, &mut state);
+                    jam_cell(stack, state);
                     unsafe {
                         stack.pop::<Noun>();
                         *(stack.push::<Noun>()) = cell.tail();
@@ -219,18 +298,12 @@ pub fn jam(stack: &mut NockStack, noun: Noun) -> Atom {
             }
         }
     }
-    unsafe {
-        let mut result = state.atom.normalize_as_atom();
-        stack.preserve(&mut result);
-        stack.frame_pop();
-        result
-    }
 }
 
 fn jam_atom(traversal: &mut NockStack, state: &mut JamState, atom: Atom) {
     loop {
         if state.cursor + 1 > state.slice.len() {
-            double_atom_size(traversal, state);
+            grow_buffer(traversal, state);
         } else {
             break;
         }
@@ -241,7 +314,7 @@ fn jam_atom(traversal: &mut NockStack, state: &mut JamState, atom: Atom) {
         if let Ok(()) = mat(traversal, state, atom) {
             break;
         } else {
-            double_atom_size(traversal, state);
+            grow_buffer(traversal, state);
         }
     }
 }
@@ -249,7 +322,7 @@ fn jam_atom(traversal: &mut NockStack, state: &mut JamState, atom: Atom) {
 fn jam_cell(traversal: &mut NockStack, state: &mut JamState) {
     loop {
         if state.cursor + 2 > state.slice.len() {
-            double_atom_size(traversal, state);
+            grow_buffer(traversal, state);
         } else {
             break;
         }
@@ -262,7 +335,7 @@ fn jam_cell(traversal: &mut NockStack, state: &mut JamState) {
 fn jam_backref(traversal: &mut NockStack, state: &mut JamState, backref: u64) {
     loop {
         if state.cursor + 2 > state.slice.len() {
-            double_atom_size(traversal, state);
+            grow_buffer(traversal, state);
         } else {
             break;
         }
@@ -275,7 +348,7 @@ fn jam_backref(traversal: &mut NockStack, state: &mut JamState, backref: u64) {
         if let Ok(()) = mat(traversal, state, backref_atom) {
             break;
         } else {
-            double_atom_size(traversal, state);
+            grow_buffer(traversal, state);
         }
     }
 }
@@ -289,6 +362,35 @@ fn double_atom_size(traversal: &mut NockStack, state: &mut JamState) {
     state.slice = new_slice;
 }
 
+/** Make room in the jam buffer. When writing to a sink, whole words are flushed out;
+ * otherwise, or if a single atom is larger than the buffer, the buffer is doubled.
+ */
+fn grow_buffer(traversal: &mut NockStack, state: &mut JamState) {
+    let words = state.cursor >> 6;
+    if state.sink.is_some() && words > 0 {
+        flush_bytes(state, words << 3);
+        let bits = words << 6;
+        let cursor = state.cursor;
+        state.slice.copy_within(bits..cursor, 0);
+        state.cursor -= bits;
+        state.flushed += bits;
+    } else {
+        double_atom_size(traversal, state);
+    }
+}
+
+fn flush_bytes(state: &mut JamState, len: usize) {
+    if state.error.is_some() || len == 0 {
+        return;
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(state.atom.data_pointer() as *const u8, len) };
+    if let Some(sink) = state.sink.as_mut() {
+        if let Err(error) = sink.write_all(bytes) {
+            state.error = Some(error);
+        }
+    }
+}
+
 // INVARIANT: mat must not modify state.cursor unless it will also return `Ok(())`
 fn mat(traversal: &mut NockStack, state: &mut JamState, atom: Atom) -> Result<(), ()> {
     let b_atom_size = met0_usize(atom);
@@ -25,6 +25,8 @@ pub struct TraceInfo {
 pub struct TraceStack {
     pub start: Instant,
     pub path: Noun,
+    /// The frame the call was traced in, which writes the entry out when it returns
+    pub frame: *const u64,
     pub next: *const TraceStack,
 }
 
@@ -138,10 +140,12 @@ pub unsafe fn write_nock_trace(
     stack: &mut NockStack,
     info: &mut TraceInfo,
     mut trace_stack: *const TraceStack,
+    frame: *const u64,
 ) -> Result<(), Error> {
     let now = Instant::now();
 
-    while !trace_stack.is_null() {
+    //  the rest were traced by the frames this one was pushed on, and are theirs to write
+    while !trace_stack.is_null() && (*trace_stack).frame == frame {
         let ts = (*trace_stack)
             .start
             .saturating_duration_since(info.process_start)
@@ -219,6 +223,10 @@ pub fn path_to_cord(stack: &mut NockStack, path: Noun) -> Atom {
         cursor = c.tail();
     }
 
+    if length == 0 {
+        return unsafe { DirectAtom::new_unchecked(0).as_atom() };
+    }
+
     // reset cursor, then actually write the path
     cursor = path;
     let mut idx = 0;
@@ -0,0 +1,337 @@
+/** Reading what vere keeps in a pier
+ *
+ * Vere's event log is an LMDB database, `$pier/.urb/log/data.mdb`, or one per epoch in
+ * `$pier/.urb/log/0i<N>/data.mdb` since vere 3.0, where epoch `N` holds the events after `N`. Its
+ * `EVENTS` table maps each event number to the mug of the state after that event (4 bytes), then
+ * the jammed event.
+ *
+ * Only a read of a single event is needed here, so rather than linking LMDB this walks its B+tree
+ * directly. See `mdb.c` in LMDB for the layouts used. Like LMDB's own, its files are in native
+ * byte order.
+ */
+use memmap::Mmap;
+use std::cmp::Ordering;
+use std::convert::TryInto;
+use std::fs::{read_dir, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MDB_MAGIC: u32 = 0xBEEFC0DE;
+const MDB_DATA_VERSION: u32 = 1;
+
+/// Size of a page header; node offsets follow it
+const PAGE_HEADER: usize = 16;
+/// Offset of the meta struct's databases, after its magic, version, address, and map size
+const META_DBS: usize = PAGE_HEADER + 24;
+/// Size of a database record
+const DB_SIZE: usize = 48;
+/// Offset of the meta struct's transaction ID, after the databases and the last page
+const META_TXNID: usize = META_DBS + 2 * DB_SIZE + 8;
+/// Size of a node header; its key then its data follow it
+const NODE_HEADER: usize = 8;
+
+const P_BRANCH: u16 = 0x01;
+const P_LEAF: u16 = 0x02;
+const F_BIGDATA: u16 = 0x01;
+const F_SUBDATA: u16 = 0x02;
+const P_INVALID: u64 = u64::MAX;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/** A node of a B+tree page */
+#[derive(Clone, Copy)]
+struct Node<'a> {
+    flags: u16,
+    /// The size of a leaf's data, or a branch's child page without its high bits (in `flags`)
+    lo_hi: u64,
+    key: &'a [u8],
+    /// Everything after the key, which starts with a leaf's data
+    rest: &'a [u8],
+}
+
+/** A read-only LMDB environment, mapped into memory */
+struct Lmdb {
+    map: Mmap,
+    page_size: usize,
+    /// Root page of the main database, which holds the named databases
+    main_root: u64,
+}
+
+impl Lmdb {
+    fn open(path: &Path) -> io::Result<Lmdb> {
+        let file = File::open(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let map = unsafe { Mmap::map(&file)? };
+        let bad = || invalid(format!("{}: not an LMDB database", path.display()));
+        if map.len() < META_TXNID + 8 {
+            return Err(bad());
+        }
+
+        //  the page size is kept in the free database's padding
+        let page_size = u32_at(&map, META_DBS) as usize;
+        if page_size < META_TXNID + 8 || map.len() < 2 * page_size {
+            return Err(bad());
+        }
+
+        //  of the two meta pages, the one with the newest transaction is current
+        let mut current = None;
+        for meta in [0, page_size] {
+            if u32_at(&map, meta + PAGE_HEADER) != MDB_MAGIC
+                || u32_at(&map, meta + PAGE_HEADER + 4) != MDB_DATA_VERSION
+            {
+                continue;
+            }
+            let txnid = u64_at(&map, meta + META_TXNID);
+            if !matches!(current, Some((_, newest)) if newest >= txnid) {
+                current = Some((meta, txnid));
+            }
+        }
+        let (meta, _) = current.ok_or_else(bad)?;
+        let main_root = u64_at(&map, meta + META_DBS + DB_SIZE + 40);
+
+        Ok(Lmdb {
+            map,
+            page_size,
+            main_root,
+        })
+    }
+
+    /** Root page of a named database */
+    fn db_root(&self, name: &[u8]) -> io::Result<Option<u64>> {
+        match self.find(self.main_root, |key| key.cmp(name))? {
+            Some((flags, data)) if flags & F_SUBDATA != 0 && data.len() >= DB_SIZE => {
+                Ok(Some(u64_at(data, 40)))
+            }
+            Some(_) => Err(invalid(format!(
+                "{} is not a database",
+                String::from_utf8_lossy(name)
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    fn page(&self, pgno: u64) -> io::Result<&[u8]> {
+        let start = (pgno as usize)
+            .checked_mul(self.page_size)
+            .filter(|start| start + self.page_size <= self.map.len())
+            .ok_or_else(|| invalid(format!("LMDB page {} is out of bounds", pgno)))?;
+        Ok(&self.map[start..start + self.page_size])
+    }
+
+    /** The nodes of a page */
+    fn nodes<'a>(&self, page: &'a [u8]) -> io::Result<Vec<Node<'a>>> {
+        let lower = u16_at(page, 12) as usize;
+        let count = lower.saturating_sub(PAGE_HEADER) >> 1;
+        let mut nodes = Vec::with_capacity(count);
+        for i in 0..count {
+            let out_of_bounds = || invalid("LMDB node is out of bounds".to_string());
+            let offset = PAGE_HEADER + 2 * i;
+            if offset + 2 > page.len() {
+                return Err(out_of_bounds());
+            }
+            let offset = u16_at(page, offset) as usize;
+            let key_start = offset + NODE_HEADER;
+            if key_start > page.len() {
+                return Err(out_of_bounds());
+            }
+            let key_end = key_start + u16_at(page, offset + 6) as usize;
+            if key_end > page.len() {
+                return Err(out_of_bounds());
+            }
+            nodes.push(Node {
+                flags: u16_at(page, offset + 4),
+                lo_hi: u16_at(page, offset) as u64 | (u16_at(page, offset + 2) as u64) << 16,
+                key: &page[key_start..key_end],
+                rest: &page[key_end..],
+            });
+        }
+        Ok(nodes)
+    }
+
+    /** Find the node whose key compares equal in the tree at `root`, returning its flags and
+     * data. */
+    fn find<F>(&self, root: u64, cmp: F) -> io::Result<Option<(u16, &[u8])>>
+    where
+        F: Fn(&[u8]) -> Ordering,
+    {
+        if root == P_INVALID {
+            return Ok(None);
+        }
+        let mut page = self.page(root)?;
+        loop {
+            let flags = u16_at(page, 10);
+            let nodes = self.nodes(page)?;
+            if flags & P_BRANCH != 0 {
+                //  the first key of a branch is implicitly lower than all others
+                let node = nodes
+                    .iter()
+                    .enumerate()
+                    .take_while(|(i, node)| *i == 0 || cmp(node.key) != Ordering::Greater)
+                    .map(|(_, node)| *node)
+                    .last()
+                    .ok_or_else(|| invalid("empty LMDB branch page".to_string()))?;
+                page = self.page(node.lo_hi | (node.flags as u64) << 32)?;
+            } else if flags & P_LEAF != 0 {
+                let Some(node) = nodes
+                    .into_iter()
+                    .find(|node| cmp(node.key) == Ordering::Equal)
+                else {
+                    return Ok(None);
+                };
+                let size = node.lo_hi as usize;
+                let data = if node.flags & F_BIGDATA != 0 {
+                    //  the data is on overflow pages, which are contiguous
+                    let start = (u64_at(node.rest, 0) as usize)
+                        .checked_mul(self.page_size)
+                        .map(|start| start + PAGE_HEADER)
+                        .filter(|start| start + size <= self.map.len())
+                        .ok_or_else(|| invalid("LMDB overflow is out of bounds".to_string()))?;
+                    &self.map[start..start + size]
+                } else {
+                    node.rest
+                        .get(..size)
+                        .ok_or_else(|| invalid("LMDB data is out of bounds".to_string()))?
+                };
+                return Ok(Some((node.flags, data)));
+            } else {
+                return Err(invalid(format!("unexpected LMDB page flags {:x}", flags)));
+            }
+        }
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_ne_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/** The LMDB file of vere's event log which holds an event */
+fn log_path(pier_path: &Path, event_num: u64) -> io::Result<PathBuf> {
+    let mut log_path = pier_path.to_path_buf();
+    log_path.push(".urb");
+    log_path.push("log");
+
+    //  the newest epoch which starts before the event
+    let mut epoch = None;
+    for entry in read_dir(&log_path)? {
+        let name = entry?.file_name();
+        let Some(first) = name.to_str().and_then(|name| name.strip_prefix("0i")) else {
+            continue;
+        };
+        if let Ok(first) = first.parse::<u64>() {
+            if first < event_num && !matches!(epoch, Some((newest, _)) if newest >= first) {
+                epoch = Some((first, name));
+            }
+        }
+    }
+    if let Some((_, name)) = epoch {
+        log_path.push(name);
+    }
+
+    log_path.push("data.mdb");
+    Ok(log_path)
+}
+
+/** The mug vere recorded for the state after an event, if its event log has that event */
+pub fn event_mug(pier_path: &Path, event_num: u64) -> io::Result<Option<u32>> {
+    let path = log_path(pier_path, event_num)?;
+    let lmdb = Lmdb::open(&path)?;
+    let Some(root) = lmdb.db_root(b"EVENTS")? else {
+        return Ok(None);
+    };
+    //  keys are native-endian integers
+    let found = lmdb.find(root, |other| {
+        other.try_into().map_or(Ordering::Less, |other| {
+            u64::from_ne_bytes(other).cmp(&event_num)
+        })
+    })?;
+    match found {
+        Some((_, data)) if data.len() >= 4 => Ok(Some(u32_at(data, 0))),
+        Some(_) => Err(invalid(format!(
+            "{}: event {} has no mug",
+            path.display(),
+            event_num
+        ))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /** A pier whose event log has two epochs, written with LMDB as vere does (`EVENTS` has
+     * `MDB_INTEGERKEY`), 100 events per transaction. `0i0` holds events 1 to 300 in a tree two
+     * levels deep, with event 100's 6000 byte jam on overflow pages, and a `META` database besides.
+     * `0i300` holds events 301 to 310. Other jams are 24 bytes.
+     */
+    fn pier() -> PathBuf {
+        PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../resources/pier"))
+    }
+
+    /// The mug recorded for each event in the fixture
+    fn mug(event_num: u64) -> u32 {
+        (event_num.wrapping_mul(0x9E3779B1) & 0x7FFFFFFF) as u32
+    }
+
+    #[test]
+    fn test_log_path() {
+        let log = pier().join(".urb").join("log");
+        for (event_num, epoch) in [(1, "0i0"), (300, "0i0"), (301, "0i300"), (310, "0i300")] {
+            assert_eq!(
+                log_path(&pier(), event_num).unwrap(),
+                log.join(epoch).join("data.mdb")
+            );
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let lmdb = Lmdb::open(&pier().join(".urb/log/0i0/data.mdb")).unwrap();
+        assert!(lmdb.db_root(b"META").unwrap().is_some());
+        assert!(lmdb.db_root(b"NONE").unwrap().is_none());
+
+        let root = lmdb.db_root(b"EVENTS").unwrap().unwrap();
+        assert!(u16_at(lmdb.page(root).unwrap(), 10) & P_BRANCH != 0);
+        for event_num in 0..=301u64 {
+            let found = lmdb
+                .find(root, |key| {
+                    u64::from_ne_bytes(key.try_into().unwrap()).cmp(&event_num)
+                })
+                .unwrap();
+            if event_num == 0 || event_num == 301 {
+                assert!(found.is_none());
+                continue;
+            }
+            let (_, data) = found.unwrap();
+            let len = if event_num == 100 { 6000 } else { 24 };
+            assert_eq!(data.len(), 4 + len);
+            assert_eq!(u32_at(data, 0), mug(event_num));
+            assert_eq!(data[4 + len - 1], (event_num * 7 + len as u64 - 1) as u8);
+        }
+    }
+
+    #[test]
+    fn test_event_mug() {
+        for event_num in [1, 2, 100, 299, 300, 301, 310] {
+            assert_eq!(event_mug(&pier(), event_num).unwrap(), Some(mug(event_num)));
+        }
+        assert_eq!(event_mug(&pier(), 311).unwrap(), None);
+    }
+
+    #[test]
+    fn test_not_lmdb() {
+        let path = pier().join("../jam/decrement.jam");
+        let err = Lmdb::open(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
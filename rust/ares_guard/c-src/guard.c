@@ -14,6 +14,7 @@
 #define GD_PAGE_SIZE              (1ULL << GD_PAGE_BITS)    // 16 KB
 #define GD_PAGE_MASK              (GD_PAGE_SIZE - 1)
 #define GD_PAGE_ROUND_DOWN(foo)   (foo & (~GD_PAGE_MASK))
+#define GD_DISTANCE(a, b)         ((a) > (b) ? (a) - (b) : (b) - (a))
 
 #ifdef __APPLE__
   #define GD_SIGNAL   SIGBUS
@@ -40,6 +41,7 @@ struct GD_state {
   const uintptr_t  *alloc_pp;
   GD_buflistnode   *buffer_list;
   struct sigaction  prev_sa;
+  guard_side        oom_side;
 };
 
 static GD_state _gd_state = { 
@@ -48,6 +50,7 @@ static GD_state _gd_state = {
   .alloc_pp = NULL,
   .buffer_list = NULL,
   .prev_sa = { .sa_sigaction = NULL, .sa_flags = 0 },
+  .oom_side = guard_side_none,
 };
 
 static uint32_t
@@ -105,6 +108,32 @@ _focus_guard(GD_state *gd)
   return 0;
 }
 
+// Find which pointer ran into the guard page: the one which has moved into or
+// past it, or else the one nearest the faulting address.
+static guard_side
+_oom_side(GD_state *gd, uintptr_t sig_addr)
+{
+  uintptr_t stack_p = *(gd->stack_pp);
+  uintptr_t alloc_p = *(gd->alloc_pp);
+  uintptr_t low_p = stack_p < alloc_p ? stack_p : alloc_p;
+  uintptr_t high_p = stack_p < alloc_p ? alloc_p : stack_p;
+  int low_crossed = low_p > gd->guard_p;
+  int high_crossed = high_p < gd->guard_p + GD_PAGE_SIZE;
+  uintptr_t near_p;
+
+  if (low_crossed && !high_crossed) {
+    near_p = low_p;
+  } else if (high_crossed && !low_crossed) {
+    near_p = high_p;
+  } else if (GD_DISTANCE(sig_addr, low_p) < GD_DISTANCE(sig_addr, high_p)) {
+    near_p = low_p;
+  } else {
+    near_p = high_p;
+  }
+
+  return near_p == stack_p ? guard_side_stack : guard_side_alloc;
+}
+
 static void
 _signal_handler(int sig, siginfo_t *si, void *unused)
 {
@@ -124,6 +153,9 @@ _signal_handler(int sig, siginfo_t *si, void *unused)
   {
     err = _focus_guard(&_gd_state);
     if (err) {
+      if (err == guard_oom) {
+        _gd_state.oom_side = _oom_side(&_gd_state, sig_addr);
+      }
       siglongjmp(_gd_state.buffer_list->buffer, err);
     }
   }
@@ -165,7 +197,8 @@ guard(
   void *closure,
   const uintptr_t *const s_pp,
   const uintptr_t *const a_pp,
-  void **ret
+  void **ret,
+  uint32_t *side
 ) {
   GD_buflistnode *new_buffer;
   uint32_t        err = 0;
@@ -206,6 +239,8 @@ guard(
   // Run given closure.
   if (!(err = sigsetjmp(_gd_state.buffer_list->buffer, 1))) {
     *ret = f(closure);
+  } else if (err == guard_oom) {
+    *side = _gd_state.oom_side;
   }
 
   // Restore previous longjmp buffer.
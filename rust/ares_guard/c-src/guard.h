@@ -16,6 +16,16 @@ typedef enum {
   guard_sigaction,  // sigaction error
 } guard_err;
 
+/**
+ * The end of the `NockStack` which ran into the guard page, when `guard`
+ * returns `guard_oom`.
+ */
+typedef enum {
+  guard_side_none,   // no room for a guard page to begin with
+  guard_side_stack,  // stack frames, at the stack pointer
+  guard_side_alloc,  // allocations, at the allocation pointer
+} guard_side;
+
 /**
  * @brief Executes the given callback function `f` within the memory arena 
  * between the stack and allocation pointers pointed to by `s_pp` and `a_pp`,
@@ -58,6 +68,8 @@ typedef enum {
  * @param s_pp A pointer to the stack pointer location.
  * @param a_pp A pointer to the allocation pointer location.
  * @param ret A pointer to a location where the callback's result can be stored.
+ * @param side A pointer to a location where the `guard_side` which ran out of
+ * memory is stored, if the callback runs out of memory.
  * 
  * @return 0 on callback success; otherwise `guard_err` error code.
  */
@@ -67,7 +79,8 @@ guard(
   void *closure,
   const uintptr_t *const s_pp,
   const uintptr_t *const a_pp,
-  void **ret
+  void **ret,
+  uint32_t *side
 );
 
 #endif  // __GUARD_H__
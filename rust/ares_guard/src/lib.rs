@@ -10,3 +10,7 @@ pub const GUARD_OOM:       u32 = guard_err_guard_oom;
 pub const GUARD_MALLOC:    u32 = guard_err_guard_malloc;
 pub const GUARD_MPROTECT:  u32 = guard_err_guard_mprotect;
 pub const GUARD_SIGACTION: u32 = guard_err_guard_sigaction;
+
+pub const GUARD_SIDE_NONE:  u32 = guard_side_guard_side_none;
+pub const GUARD_SIDE_STACK: u32 = guard_side_guard_side_stack;
+pub const GUARD_SIDE_ALLOC: u32 = guard_side_guard_side_alloc;
@@ -133,6 +133,29 @@ _nlist_eq(BT_nlistnode *l, BT_nlistnode *r)
   bp(0);
 }
 
+static pgno_t
+_test_fohi(BT_state *state, void *lo, size_t pages)
+/* the file page just past an allocation */
+{
+  BT_findpath path = {0};
+  assert(SUCC(_bt_find(state, &path, addr2off(lo), addr2off(lo) + pages)));
+  return path.path[path.depth]->datk[path.idx[path.depth]].fo + pages;
+}
+
+static void
+_test_trimmed(BT_state *state)
+/* nothing free, in use, or in a node partition lies past the end of the
+   file */
+{
+  BT_meta *meta = state->meta_pages[state->which];
+  for (BT_flistnode *n = state->flist; n; n = n->next) {
+    assert(n->hi <= state->file_size_p);
+  }
+  for (size_t i = 0; i < BT_NUMPARTS && meta->blk_base[i] != 0; i++) {
+    assert(meta->blk_base[i] + B2PAGES(BLK_BASE_LENS_b[i]) <= state->file_size_p);
+  }
+}
+
 static int
 _flist_eq(BT_flistnode *l, BT_flistnode *r)
 {
@@ -378,5 +401,124 @@ int main(int argc, char *argv[])
   /* the partition offset should be the same */
   assert(t4partoff0 == state5->meta_pages[state5->which]->blk_base[1]);
 
+
+  DPUTS("== test 6: trimming");
+  BT_state *state6;
+  struct stat t6stat;
+
+  bt_state_new(&state6);
+  if (mkdir("./pmatest6", 0774) == -1)
+    return errno;
+  assert(SUCC(bt_state_open(state6, "./pmatest6", 0, 0644)));
+
+  /* grow the file twice over, keeping the first growth and freeing the
+     second */
+  BYTE *t6a = bt_malloc(state6, PMA_GROW_SIZE_p);
+  BYTE *t6b = bt_malloc(state6, PMA_GROW_SIZE_p * 2);
+  for (size_t i = 0; i < PMA_GROW_SIZE_b; i++) {
+    t6a[i] = i * 7;
+  }
+  assert(SUCC(bt_sync(state6)));
+  pgno_t t6ahi = _test_fohi(state6, t6a, PMA_GROW_SIZE_p);
+  pgno_t t6bhi = _test_fohi(state6, t6b, PMA_GROW_SIZE_p * 2);
+  assert(t6bhi > t6ahi);
+
+  /* only the free pages past the last allocation are truncated */
+  assert(SUCC(bt_trim(state6)));
+  _test_trimmed(state6);
+  assert(state6->file_size_p == t6bhi);
+
+  /* and trimming again does nothing */
+  assert(SUCC(bt_trim(state6)));
+  assert(state6->file_size_p == t6bhi);
+
+  /* freeing the last allocation frees the tail to be truncated */
+  bt_free(state6, t6b, t6b + P2BYTES(PMA_GROW_SIZE_p * 2));
+  assert(SUCC(bt_sync(state6)));
+  assert(SUCC(bt_trim(state6)));
+  _test_trimmed(state6);
+  pgno_t t6size = state6->file_size_p;
+  assert(t6size < t6bhi);
+  assert(t6size >= t6ahi);
+  assert(fstat(state6->data_fd, &t6stat) == 0);
+  assert(t6stat.st_size == (off_t)P2BYTES(t6size));
+
+  /* and the kept pages survive reopening */
+  bt_state_close(state6);
+  bt_state_new(&state6);
+  assert(SUCC(bt_state_open(state6, "./pmatest6", 0, 0644)));
+  assert(state6->file_size_p == t6size);
+  for (size_t i = 0; i < PMA_GROW_SIZE_b; i++) {
+    assert(t6a[i] == (BYTE)(i * 7));
+  }
+
+
+  DPUTS("== test 7: persisting the first node stripe");
+  BT_state *state7;
+  static BYTE t7page[BT_PAGESIZE];
+
+  bt_state_new(&state7);
+  if (mkdir("./pmatest7", 0774) == -1)
+    return errno;
+  assert(SUCC(bt_state_open(state7, "./pmatest7", 0, 0644)));
+
+  /* the root of a new btree is in the first node stripe, which is written
+     through to the file on sync */
+  BYTE *t7a = bt_malloc(state7, 1);
+  t7a[0] = 0x77;
+  assert(SUCC(bt_sync(state7)));
+  BT_meta *t7meta = state7->meta_pages[state7->which];
+  BT_page *t7root = _node_get(state7, t7meta->root);
+  assert(pread(state7->data_fd, t7page, BT_PAGESIZE, P2BYTES(t7meta->root))
+         == BT_PAGESIZE);
+  assert(memcmp(t7page, t7root, BT_PAGESIZE) == 0);
+
+  /* so the allocation is still found after reopening */
+  bt_state_close(state7);
+  bt_state_new(&state7);
+  assert(SUCC(bt_state_open(state7, "./pmatest7", 0, 0644)));
+  ZERO(&path, sizeof path);
+  assert(SUCC(_bt_find(state7, &path, addr2off(t7a), addr2off(t7a + BT_PAGESIZE))));
+  assert(t7a[0] == 0x77);
+
+
+  DPUTS("== test 8: node partitions on reopen");
+  BT_state *state8;
+
+  bt_state_new(&state8);
+  if (mkdir("./pmatest8", 0774) == -1)
+    return errno;
+  assert(SUCC(bt_state_open(state8, "./pmatest8", 0, 0644)));
+  BYTE *t8a = bt_malloc(state8, 1);
+  t8a[0] = 0x88;
+  assert(SUCC(bt_sync(state8)));
+  bt_state_close(state8);
+
+  bt_state_new(&state8);
+  assert(SUCC(bt_state_open(state8, "./pmatest8", 0, 0644)));
+
+  /* the restored flist doesn't hand out the pages of a node partition */
+  BT_meta *t8meta = state8->meta_pages[state8->which];
+  for (BT_flistnode *n = state8->flist; n; n = n->next) {
+    for (size_t i = 0; i < BT_NUMPARTS && t8meta->blk_base[i] != 0; i++) {
+      pgno_t lo = t8meta->blk_base[i];
+      pgno_t hi = lo + B2PAGES(BLK_BASE_LENS_b[i]);
+      assert(n->hi <= lo || n->lo >= hi);
+    }
+  }
+
+  /* so filling new allocations leaves the btree intact */
+  BYTE *t8b = bt_malloc(state8, PMA_GROW_SIZE_p);
+  memset(t8b, 0xFF, PMA_GROW_SIZE_b);
+  assert(SUCC(bt_sync(state8)));
+  bt_state_close(state8);
+
+  bt_state_new(&state8);
+  assert(SUCC(bt_state_open(state8, "./pmatest8", 0, 0644)));
+  ZERO(&path, sizeof path);
+  assert(SUCC(_bt_find(state8, &path, addr2off(t8a), addr2off(t8a + BT_PAGESIZE))));
+  assert(t8a[0] == 0x88);
+  assert(t8b[PMA_GROW_SIZE_b - 1] == 0xFF);
+
   return 0;
 }
@@ -127,12 +127,15 @@ off2addr(vaof_t off)
 
 #define BT_NOPAGE 0
 
+/* read-only states map the file private, so that writes never reach it */
+#define BT_FLAG_SHARE(s) (((s)->flags & BT_RDONLY) ? MAP_PRIVATE : MAP_SHARED)
+
 #define BT_PROT_CLEAN (PROT_READ)
-#define BT_FLAG_CLEAN (MAP_FIXED | MAP_SHARED)
+#define BT_FLAG_CLEAN(s) (MAP_FIXED | BT_FLAG_SHARE(s))
 #define BT_PROT_FREE  (PROT_NONE)
 #define BT_FLAG_FREE  (MAP_ANONYMOUS | MAP_SHARED | MAP_FIXED | MAP_NORESERVE)
 #define BT_PROT_DIRTY (PROT_READ | PROT_WRITE)
-#define BT_FLAG_DIRTY (MAP_FIXED | MAP_SHARED)
+#define BT_FLAG_DIRTY(s) (MAP_FIXED | BT_FLAG_SHARE(s))
 
 /*
   FO2BY: file offset to byte
@@ -339,6 +342,7 @@ struct BT_flistnode {
 typedef struct BT_state BT_state;
 struct BT_state {
   int           data_fd;
+  ULONG         flags;          /* flags passed to bt_state_open */
   char         *path;
   void         *fixaddr;
   /* TODO: refactor ->map to be a (BT_page *) */
@@ -474,7 +478,7 @@ _nlist_grow(BT_state *state)
   if (targ != mmap(targ,
                    block_len_b,
                    BT_PROT_CLEAN,
-                   BT_FLAG_CLEAN,
+                   BT_FLAG_CLEAN(state),
                    state->data_fd,
                    partoff_b)) {
     DPRINTF("mmap: failed to map node stripe %zu, addr: 0x%p, file offset (bytes): 0x%zX, errno: %s",
@@ -1267,12 +1271,16 @@ _bt_delco_trim_rsubtree_lhs2(BT_state *state, vaof_t lo, vaof_t hi,
     }
   }
 
-  /* set the lo address of datk[hiidx] to hi */
-  node->datk[hiidx-1].va = hi;
+  /* the subtree the range ends in is kept, unless the range ends where it
+     does, in which case it is covered entirely and the next is kept instead */
+  size_t keep = node->datk[hiidx].va == hi ? hiidx : hiidx-1;
+
+  /* set the lo address of the kept subtree to hi */
+  node->datk[keep].va = hi;
 
   /* drop the subtrees left of the range */
   if (depth != maxdepth) {
-    for (i = 0; i < hiidx-1; i++) {
+    for (i = 0; i < keep; i++) {
       pgno_t childpg = node->datk[i].fo;
       if (childpg == 0)
         break;
@@ -1283,7 +1291,7 @@ _bt_delco_trim_rsubtree_lhs2(BT_state *state, vaof_t lo, vaof_t hi,
 
   /* memmove the buffer so the found range is the first in the node */
   BYTE *dst = (BYTE *)&node->datk[0].va;
-  BYTE *src = (BYTE *)&node->datk[hiidx-1].va;
+  BYTE *src = (BYTE *)&node->datk[keep].va;
   BYTE *end = (BYTE *)&node->datk[BT_DAT_MAXKEYS-1].fo;
   size_t len = end - src;
 
@@ -1294,11 +1302,12 @@ _bt_delco_trim_rsubtree_lhs2(BT_state *state, vaof_t lo, vaof_t hi,
   /* and now zero the moved range */
   ZERO(dst+len, end-(dst+len));
 
-  /* done if this is a leaf */
-  if (depth == maxdepth)
+  /* done if this is a leaf, or the range ended where the kept subtree
+     begins */
+  if (depth == maxdepth || keep == hiidx)
     return;
-  /* otherwise, recur on subtree */
-  pgno_t rsubtree = node->datk[hiidx].fo;
+  /* otherwise, recur on the kept subtree, now the first */
+  pgno_t rsubtree = node->datk[0].fo;
   _bt_delco_trim_rsubtree_lhs2(state, lo, hi, rsubtree, depth+1, maxdepth);
 }
 
@@ -1445,22 +1454,30 @@ _bt_delco(BT_state *state, vaof_t lo, vaof_t hi,
       assert(0);
     }
 
+    /* if the range ends where the right subtree does, that subtree is covered
+       entirely and is dropped with those between, rather than left empty */
+    int rcovered = node->datk[hiidx+1].va == hi;
+    size_t rkeep = rcovered ? hiidx + 1 : hiidx;
+
     /* set leftmost boundary va to hi */
     node->datk[loidx+1].va = hi;
 
     /* set the lo side of the right boundary to hi */
-    node->datk[hiidx].va = hi;
+    node->datk[rkeep].va = hi;
 
-    /* drop all trees between the two subtrees */
-    for (size_t i = loidx+1; i < hiidx; i++) {
-      pgno_t childpg = node->datk[i].fo;
-      int ischilddirty = _bt_ischilddirty(node, i);
-      _bt_delco_droptree(state, childpg, depth+1, ischilddirty);
+    /* drop all trees between the two subtrees. In a leaf these are free data
+       ranges, not trees */
+    if (depth < maxdepth) {
+      for (size_t i = loidx+1; i < rkeep; i++) {
+        pgno_t childpg = node->datk[i].fo;
+        int ischilddirty = _bt_ischilddirty(node, i);
+        _bt_delco_droptree(state, childpg, depth+1, ischilddirty);
+      }
     }
 
     /* move buffer */
     BYTE *dst = (BYTE *)&node->datk[loidx+1].va;
-    BYTE *src = (BYTE *)&node->datk[hiidx].va;
+    BYTE *src = (BYTE *)&node->datk[rkeep].va;
     BYTE *end = (BYTE *)&node->datk[BT_DAT_MAXKEYS-1].fo;
     size_t len = end - src;
     memmove(dst, src, len);
@@ -1469,7 +1486,8 @@ _bt_delco(BT_state *state, vaof_t lo, vaof_t hi,
     /* unless at leaf trim left subtree then trim right subtree */
     if (depth < maxdepth) {
       _bt_delco_trim_lsubtree_rhs(state, lo, hi, lsubtree, depth+1);
-      _bt_delco_trim_rsubtree_lhs(state, lo, hi, rsubtree, depth+1);
+      if (!rcovered)
+        _bt_delco_trim_rsubtree_lhs(state, lo, hi, rsubtree, depth+1);
     }
 
     /* done */
@@ -1721,6 +1739,20 @@ _nlist_new(BT_state *state)
   /* ;;: tmp. assert. for debugging changes */
   assert(partition_0 == &((BT_page *)state->map)[BT_NUMMETAS]);
 
+  /* the node segment was mapped before the first stripe was allocated, so it
+     is still anonymous memory. Map the stripe to the file, as _nlist_grow does,
+     so that nodes are persisted */
+  if ((BYTE *)partition_0 != mmap(partition_0,
+                                  BLK_BASE_LEN0_b,
+                                  BT_PROT_CLEAN,
+                                  BT_FLAG_CLEAN(state),
+                                  state->data_fd,
+                                  P2BYTES(partition_0_pg))) {
+    DPRINTF("mmap: failed to map node stripe 0, addr: 0x%p, errno: %s",
+            partition_0, strerror(errno));
+    abort();
+  }
+
   /* the size of a new node freelist is just the first stripe length */
   return _nlist_creat(state, partition_0, B2PAGES(BLK_BASE_LEN0_b));
 }
@@ -2105,9 +2137,10 @@ _flist_read(BT_state *state)
 static int
 _flist_delete(BT_state *state)
 {
+  /* the flist may be empty, after trimming or allocating the last free range */
   BT_flistnode *head, *prev;
-  head = prev = state->flist;
-  while (head->next) {
+  head = state->flist;
+  while (head) {
     prev = head;
     head = head->next;
     free(prev);
@@ -2162,7 +2195,7 @@ _bt_state_restore_maps2(BT_state *state, BT_page *node,
             mmap(loaddr,
                  bytelen,
                  BT_PROT_CLEAN,
-                 BT_FLAG_CLEAN,
+                 BT_FLAG_CLEAN(state),
                  state->data_fd,
                  offset)) {
           DPRINTF("mmap: failed to map at addr %p, errno: %s", loaddr, strerror(errno));
@@ -2410,6 +2443,14 @@ _freelist_restore(BT_state *state)
   assert(SUCC(_flist_new(state, state->file_size_p)));
   assert(SUCC(_nlist_load(state)));
   assert(SUCC(_mlist_new(state)));
+  /* the node partitions are allocated in the file, though not in the tree */
+  for (size_t i = 0
+         ; i < BT_NUMPARTS && meta->blk_base[i] != 0
+         ; i++) {
+    pgno_t partoff_p = meta->blk_base[i];
+    _flist_record_alloc(state, partoff_p,
+                        partoff_p + B2PAGES(BLK_BASE_LENS_b[i]));
+  }
   /* first record root's allocation */
   _nlist_record_alloc(state, root);
   _freelist_restore2(state, root, 1, meta->depth);
@@ -2433,7 +2474,7 @@ _bt_state_map_node_segment(BT_state *state)
     if (targ != mmap(targ,
                      partlen_b,
                      BT_PROT_CLEAN,
-                     BT_FLAG_CLEAN,
+                     BT_FLAG_CLEAN(state),
                      state->data_fd,
                      partoff_b)) {
       DPRINTF("mmap: failed to map node stripe %zu, addr: 0x%p, file offset (bytes): 0x%zX, errno: %s",
@@ -2477,7 +2518,7 @@ _bt_state_load(BT_state *state)
   state->map = mmap(BT_MAPADDR,
                     BT_META_SECTION_WIDTH,
                     BT_PROT_CLEAN,
-                    BT_FLAG_CLEAN,
+                    BT_FLAG_CLEAN(state),
                     state->data_fd,
                     0);
 
@@ -2492,6 +2533,8 @@ _bt_state_load(BT_state *state)
 
   if (!SUCC(rc = _bt_state_read_header(state))) {
     if (rc != ENOENT) return rc;
+    /* can't create a db without write access */
+    if (state->flags & BT_RDONLY) return rc;
     DPUTS("creating new db");
     state->file_size_p = PMA_GROW_SIZE_p;
     new = 1;
@@ -2780,9 +2823,9 @@ bt_state_open(BT_state *state, const char *path, ULONG flags, mode_t mode)
   char *dpath;
 
   TRACE();
-  UNUSED(flags);
 
-  oflags = O_RDWR | O_CREAT;
+  state->flags = flags;
+  oflags = (flags & BT_RDONLY) ? O_RDONLY : O_RDWR | O_CREAT;
   dpath = malloc(strlen(path) + sizeof(DATANAME));
   if (!dpath) return ENOMEM;
   sprintf(dpath, "%s" DATANAME, path);
@@ -2832,6 +2875,12 @@ bt_malloc(BT_state *state, size_t pages)
 {
   BT_mlistnode **n = &state->mlist;
   void *ret = 0;
+
+  if (state->flags & BT_RDONLY) {
+    DPUTS("can't allocate in a read-only state");
+    return 0;
+  }
+
   /* first fit */
   for (; *n; n = &(*n)->next) {
     size_t sz_p = addr2off((*n)->hi) - addr2off((*n)->lo);
@@ -2861,7 +2910,7 @@ bt_malloc(BT_state *state, size_t pages)
       mmap(ret,
            P2BYTES(pages),
            BT_PROT_DIRTY,
-           BT_FLAG_DIRTY,
+           BT_FLAG_DIRTY(state),
            state->data_fd,
            P2BYTES(pgno))) {
     DPRINTF("mmap: failed to map at addr %p, errno: %s", ret, strerror(errno));
@@ -2931,6 +2980,10 @@ bt_sync(BT_state *state)
   BT_page *root = _node_get(state, meta->root);
   int rc = 0;
 
+  /* nothing is ever written to the file of a read-only state */
+  if (state->flags & BT_RDONLY)
+    return BT_SUCC;
+
   if ((rc = _bt_sync(state, root, 1, meta->depth)))
     return rc;
 
@@ -3080,7 +3133,7 @@ _bt_data_cow(BT_state *state, vaof_t lo, vaof_t hi, pgno_t pg)
       mmap(loaddr,
            bytelen,
            BT_PROT_DIRTY,
-           BT_FLAG_DIRTY,
+           BT_FLAG_DIRTY(state),
            state->data_fd,
            offset)) {
     DPRINTF("mmap: failed to map at addr %p, errno: %s", loaddr, strerror(errno));
@@ -3153,6 +3206,14 @@ bt_dirty(BT_state *state, void *lo, void *hi)
   vaof_t looff = addr2off(lo);
   vaof_t hioff = addr2off(hi);
 
+  /* a read-only PMA is mapped privately, so writes are never synced to the
+     file. Make the range writable in place rather than CoWing it */
+  if (state->flags & BT_RDONLY) {
+    if (mprotect(lo, (BYTE *)hi - (BYTE *)lo, BT_PROT_DIRTY) != 0)
+      return errno;
+    return BT_SUCC;
+  }
+
   return _bt_dirty(state, looff, hioff, meta->root, 1, meta->depth);
 }
 
@@ -3224,6 +3285,197 @@ bt_inbounds(BT_state *state, void *p)
     && p < (void *)((uintptr_t)BT_MAPADDR + BT_ADDRSIZE);
 }
 
+/* a growable array of allocated data ranges, collected from the leaves */
+typedef struct BT_allocs BT_allocs;
+struct BT_allocs {
+  BT_kv *kv;                    /* va and fo of each range */
+  vaof_t *hi;                   /* exclusive upper bound of each range */
+  size_t len;
+  size_t cap;
+};
+
+static void
+_bt_allocs_push(BT_allocs *allocs, vaof_t lo, vaof_t hi, pgno_t fo)
+{
+  if (allocs->len == allocs->cap) {
+    allocs->cap = allocs->cap ? allocs->cap * 2 : 64;
+    allocs->kv = realloc(allocs->kv, allocs->cap * sizeof *allocs->kv);
+    allocs->hi = realloc(allocs->hi, allocs->cap * sizeof *allocs->hi);
+    if (!allocs->kv || !allocs->hi)
+      abort();
+  }
+  allocs->kv[allocs->len].va = lo;
+  allocs->kv[allocs->len].fo = fo;
+  allocs->hi[allocs->len] = hi;
+  allocs->len++;
+}
+
+static void
+_bt_allocs2(BT_state *state, BT_page *node, uint8_t depth, uint8_t maxdepth,
+            BT_allocs *allocs)
+{
+  size_t N = _bt_numkeys(node);
+
+  /* leaf */
+  if (depth == maxdepth) {
+    for (size_t i = 0; i < N-1; i++) {
+      if (node->datk[i].fo != 0) {
+        _bt_allocs_push(allocs,
+                        node->datk[i].va,
+                        node->datk[i+1].va,
+                        node->datk[i].fo);
+      }
+    }
+    return;
+  }
+
+  /* branch */
+  for (size_t i = 0; i < N-1; i++) {
+    pgno_t fo = node->datk[i].fo;
+    if (fo != 0) {
+      BT_page *child = _node_get(state, fo);
+      _bt_allocs2(state, child, depth+1, maxdepth, allocs);
+    }
+  }
+}
+
+static void
+_bt_allocs(BT_state *state, BT_allocs *allocs)
+/* collect every allocated data range. caller frees allocs->kv and allocs->hi */
+{
+  BT_meta *meta = state->meta_pages[state->which];
+  BT_page *root = _node_get(state, meta->root);
+  allocs->kv = 0;
+  allocs->hi = 0;
+  allocs->len = 0;
+  allocs->cap = 0;
+  _bt_allocs2(state, root, 1, meta->depth, allocs);
+}
+
+int
+bt_scratch(BT_state *state)
+{
+  BT_allocs allocs;
+  _bt_allocs(state, &allocs);
+
+  for (size_t i = 0; i < allocs.len; i++) {
+    BYTE *loaddr = off2addr(allocs.kv[i].va);
+    size_t bytelen = P2BYTES(allocs.hi[i] - allocs.kv[i].va);
+
+    /* private mapping: writes are never carried through to the file */
+    if (loaddr !=
+        mmap(loaddr,
+             bytelen,
+             BT_PROT_DIRTY,
+             MAP_FIXED | MAP_PRIVATE,
+             state->data_fd,
+             P2BYTES(allocs.kv[i].fo))) {
+      DPRINTF("mmap: failed to map at addr %p, errno: %s", loaddr, strerror(errno));
+      abort();
+    }
+  }
+
+  free(allocs.kv);
+  free(allocs.hi);
+  return BT_SUCC;
+}
+
+int
+bt_free_except(BT_state *state, void *lo, void *hi)
+{
+  vaof_t looff = addr2off(lo);
+  vaof_t hioff = addr2off(hi);
+  BT_allocs allocs;
+  _bt_allocs(state, &allocs);
+
+  /* ranges were collected up front, since freeing rewrites the tree */
+  for (size_t i = 0; i < allocs.len; i++) {
+    vaof_t rlo = allocs.kv[i].va;
+    vaof_t rhi = allocs.hi[i];
+
+    if (rlo < looff)
+      bt_free(state, off2addr(rlo), off2addr(MIN(rhi, looff)));
+    if (rhi > hioff)
+      bt_free(state, off2addr(MAX(rlo, hioff)), off2addr(rhi));
+  }
+
+  free(allocs.kv);
+  free(allocs.hi);
+  return BT_SUCC;
+}
+
+int
+bt_usage(BT_state *state, size_t *file_b, size_t *data_b, size_t *free_b)
+{
+  BT_allocs allocs;
+  pgno_t data_p = 0;
+  pgno_t free_p = 0;
+
+  _bt_allocs(state, &allocs);
+  for (size_t i = 0; i < allocs.len; i++)
+    data_p += allocs.hi[i] - allocs.kv[i].va;
+  free(allocs.kv);
+  free(allocs.hi);
+
+  for (BT_flistnode *n = state->flist; n; n = n->next)
+    free_p += n->hi - n->lo;
+  for (BT_flistnode *n = state->pending_flist; n; n = n->next)
+    free_p += n->hi - n->lo;
+
+  *file_b = P2BYTES(state->file_size_p);
+  *data_b = P2BYTES(data_p);
+  *free_b = P2BYTES(free_p);
+  return BT_SUCC;
+}
+
+int
+bt_trim(BT_state *state)
+{
+  BT_meta *meta = state->meta_pages[state->which];
+  BT_flistnode **n = &state->flist;
+  pgno_t min_p = PMA_GROW_SIZE_p;
+  pgno_t lo;
+
+  if (state->flags & BT_RDONLY)
+    return EROFS;
+
+  /* never truncate into a node partition */
+  for (size_t i = 0
+         ; i < BT_NUMPARTS && meta->blk_base[i] != 0
+         ; i++) {
+    min_p = MAX(min_p, meta->blk_base[i] + B2PAGES(BLK_BASE_LENS_b[i]));
+  }
+
+  if (*n == 0)
+    return BT_SUCC;
+
+  /* find the last free range */
+  while ((*n)->next)
+    n = &(*n)->next;
+
+  /* the end of the file is in use */
+  if ((*n)->hi != state->file_size_p)
+    return BT_SUCC;
+
+  lo = MAX((*n)->lo, min_p);
+  if (lo >= (*n)->hi)
+    return BT_SUCC;
+
+  if (ftruncate(state->data_fd, P2BYTES(lo)) != 0)
+    return errno;
+
+  if (lo == (*n)->lo) {
+    free(*n);
+    *n = 0;
+  }
+  else {
+    (*n)->hi = lo;
+  }
+  state->file_size_p = lo;
+
+  return BT_SUCC;
+}
+
 
 //// ===========================================================================
 ////                                    tests
@@ -19,6 +19,16 @@ typedef unsigned long ULONG;
  */
 int bt_state_new(BT_state **state);
 
+/**
+ * bt_state_open flags
+ *
+ * BT_RDONLY: open an existing state without write access. The file is mapped
+ * private, so writes to the map are never carried through to it. Allocation
+ * fails, dirtying only makes a range writable in memory, and syncing does
+ * nothing
+ */
+#define BT_RDONLY 0x1
+
 /**
  * Open the persistent state or create if one doesn't exist
  */
@@ -84,4 +94,27 @@ void bt_bounds(BT_state *state, void **lo, void **hi);
  */
 int bt_inbounds(BT_state *state, void *p);
 
+/**
+ * Remap every allocated range copy-on-write and private, so that it may be
+ * written to without touching the file. Only useful before freeing all of them
+ */
+int bt_scratch(BT_state *state);
+
+/**
+ * Free every allocated range, except for those parts within [lo, hi)
+ */
+int bt_free_except(BT_state *state, void *lo, void *hi);
+
+/**
+ * Shrink the file by truncating free pages at its end. Pages freed since the
+ * last call to `bt_sync()` are not yet free, so call this right after a sync
+ */
+int bt_trim(BT_state *state);
+
+/**
+ * Report, in bytes, the size of the file, the data allocated in it, and the
+ * file space free for reuse, including space freed since the last `bt_sync()`
+ */
+int bt_usage(BT_state *state, size_t *file_b, size_t *data_b, size_t *free_b);
+
 #endif